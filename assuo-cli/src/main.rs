@@ -1,32 +1,1315 @@
-use assuo::patch::do_patch;
+use assuo::models::{
+    AssuoFile, AssuoPatch, AssuoSource, Direction, Format, LenEncoding, Resolvable,
+    DEFAULT_TEMPLATE,
+};
+use assuo::patch::{
+    do_patch, do_patch_onto, do_patch_profiled, take_captures, Lock, Manifest, Origin,
+    PatchOptions, PatchSnapshot, PostProcessor, Profile, Provenance, Snapshots,
+};
 use std::io::prelude::*;
 
 #[paw::main]
 // #[tokio::main(flavor = "current_thread")] 0.3+ only
 fn main(args: paw::Args) -> Result<(), Box<dyn std::error::Error>> {
-    for arg in args.skip(1) {
-        if arg == "--init" || arg == "-i" {
-            init();
-            std::process::exit(0);
+    let mut resolve_only = false;
+    let mut verify_lengths = false;
+    let mut check_only = false;
+    let mut input_encoding = None;
+    let mut input_format = None;
+    let mut config_profile = None;
+    let mut output = None;
+    let mut tee = false;
+    let mut out_permissions = None;
+    let mut strict_utf8 = false;
+    let mut profile_output = None;
+    let mut lockfile = None;
+    let mut manifest_output = None;
+    let mut snapshots_dir = None;
+    let mut config_file = None;
+    let mut apply_to = None;
+    let mut batch = None;
+    let mut out_dir = None;
+    let mut emit = None;
+    let mut hash_only = None;
+    let mut emit_name = String::from("OUTPUT");
+    let mut split = None;
+    let mut split_prefix = String::from("part_");
+    let mut split_width = 3;
+    let mut frame = None;
+    let mut output_encoding = None;
+    let mut output_bom = false;
+    let mut options = PatchOptions::default();
+    let mut post_processors: Vec<PostProcessor> = Vec::new();
+    let mut rewritten_by = Vec::new();
+
+    let mut args = args.skip(1).peekable();
+
+    if args.peek().map(String::as_str) == Some("diff-apply") {
+        args.next();
+        let base = args.next().ok_or("expected a base file after diff-apply")?;
+        let diff = args
+            .next()
+            .ok_or("expected a diff file after the base file")?;
+        return diff_apply(&base, &diff);
+    }
+
+    if args.peek().map(String::as_str) == Some("from-diff") {
+        args.next();
+        return from_diff(args);
+    }
+
+    if args.peek().map(String::as_str) == Some("merge") {
+        args.next();
+        return merge(args);
+    }
+
+    while let Some(arg) = args.next() {
+        // a bare path is the config, which is how a patch file starting with `#!/usr/bin/env assuo` gets run. the
+        // shebang itself is a TOML comment, so it doesn't need to be skipped
+        if !arg.starts_with('-') && arg != "/?" {
+            config_file = Some(arg);
+            continue;
+        }
+
+        if arg == "-" {
+            config_file = Some(String::from("-"));
+            continue;
+        }
+
+        // values can come after an `=` too, like `--output=out.bin`
+        let (name, inline_value) = match arg.find('=') {
+            Some(at) if arg.starts_with("--") => (&arg[..at], Some(String::from(&arg[at + 1..]))),
+            _ => (arg.as_str(), None),
+        };
+
+        // flags that aren't known have always been ignored, so that a config ran by a newer assuo still gets
+        // patched by an older one, but it's worth knowing about in case it's a typo
+        let flag = match find_flag(name) {
+            Some(flag) => flag,
+            None => {
+                eprintln!("warning: ignoring unknown flag {}", name);
+                continue;
+            }
+        };
+        let value = match (flag.value, inline_value) {
+            (Some(_), Some(value)) => value,
+            (Some(what), None) => args
+                .next()
+                .ok_or_else(|| format!("expected {} after {}", what, flag.long))?,
+            (None, Some(_)) => return Err(format!("{} doesn't take a value", flag.long).into()),
+            (None, None) => String::new(),
+        };
+
+        match flag.long {
+            "--init" => {
+                init();
+                std::process::exit(0);
+            }
+            "--help" => {
+                help();
+                std::process::exit(0);
+            }
+            "--bench-self" => {
+                bench_self()?;
+                std::process::exit(0);
+            }
+            "--list-flags" => {
+                list_flags();
+                std::process::exit(0);
+            }
+            "--resolve-only" => resolve_only = true,
+            "--check-only" => check_only = true,
+            "--verify-lengths" => verify_lengths = true,
+            "--strict-utf8" => strict_utf8 = true,
+            "--trailing-newline" => {
+                post_processors.push(Box::new(trailing_newline));
+                rewritten_by.push(flag.long);
+            }
+            "--normalize-newlines" => {
+                let newline: &'static [u8] = match value.as_str() {
+                    "lf" => b"\n",
+                    "crlf" => b"\r\n",
+                    _ => return Err("expected lf or crlf after --normalize-newlines".into()),
+                };
+
+                post_processors.push(Box::new(move |output| {
+                    Ok(normalize_newlines(&output, newline))
+                }));
+                rewritten_by.push(flag.long);
+            }
+            "--frame" => {
+                frame = Some(
+                    LenEncoding::from_name(&value)
+                        .ok_or("expected u8, u16le, u16be, u32le or u32be after --frame")?,
+                );
+            }
+            "--timeout-all" => {
+                options.fetch_defaults.timeout = Some(
+                    assuo::models::parse_duration(&value)
+                        .ok_or("expected a duration like 10s after --timeout-all")?,
+                );
+            }
+            "--retry-all" => {
+                options.fetch_defaults.retry = Some(
+                    value
+                        .parse()
+                        .map_err(|_| "expected a number of retries after --retry-all")?,
+                );
+            }
+            "--no-empty-sources" => options.no_empty_sources = true,
+            "--continue-on-resolve-error" => options.continue_on_resolve_error = true,
+            "--dedupe-sources" => options.dedupe_sources = true,
+            "--strict-spots" => options.strict_spots = true,
+            "--sort-patches" => options.sort_patches = true,
+            "--dump-indexes" => options.after_patch = Some(dump_indexes),
+            "--explain-bytes" => options.provenance = Some(Provenance::default()),
+            "--trace-http" => options.trace_http = Some(|trace| eprintln!("http: {}", trace)),
+            "--allow" => {
+                options.allowed_sources = Some(value.split(',').map(String::from).collect());
+            }
+            "--base-url" => options.base_url = Some(value),
+            "--output" => output = Some(value),
+            "--tee" => tee = true,
+            "--out-permissions" => {
+                let mode = u32::from_str_radix(&value, 8)
+                    .ok()
+                    .filter(|mode| *mode <= 0o7777)
+                    .ok_or("expected an octal mode like 0755 after --out-permissions")?;
+
+                if cfg!(unix) {
+                    out_permissions = Some(mode);
+                } else {
+                    eprintln!("warning: --out-permissions only works on Unix, so it's ignored");
+                }
+            }
+            "--split" => {
+                split = match value.parse::<usize>() {
+                    Ok(size) if size > 0 => Some(size),
+                    _ => return Err("expected a positive number of bytes after --split".into()),
+                };
+            }
+            "--split-prefix" => split_prefix = value,
+            "--split-width" => {
+                split_width = value
+                    .parse()
+                    .map_err(|_| "expected a number of digits after --split-width")?;
+            }
+            "--file" => config_file = Some(value),
+            "--apply-to" => apply_to = Some(value),
+            "--batch" => batch = Some(value),
+            "--out-dir" => out_dir = Some(value),
+            "--emit" => {
+                emit = Some(match value.as_str() {
+                    "rust" => Emit::Rust,
+                    "c" => Emit::C,
+                    _ => return Err("expected rust or c after --emit".into()),
+                });
+            }
+            "--emit-name" => emit_name = value,
+            "--hash-only" => {
+                hash_only = Some(match value.as_str() {
+                    "sha256" => Hash::Sha256,
+                    "sha512" => Hash::Sha512,
+                    _ => return Err("expected sha256 or sha512 after --hash-only".into()),
+                });
+            }
+            "--lockfile" => lockfile = Some(value),
+            "--manifest" => {
+                manifest_output = Some(value);
+                options.manifest = Some(Manifest::default());
+            }
+            "--snapshots" => {
+                snapshots_dir = Some(value);
+                options.snapshots = Some(Snapshots::default());
+            }
+            "--profile" => profile_output = Some(value),
+            "--use-profile" => config_profile = Some(value),
+            "--input-format" => {
+                input_format = match value.as_str() {
+                    "toml" => Some(Format::Toml),
+                    "json" => Some(Format::Json),
+                    "yaml" => Some(Format::Yaml),
+                    "auto" => None,
+                    _ => {
+                        return Err("expected toml, json, yaml, or auto after --input-format".into())
+                    }
+                };
+            }
+            "--input-encoding" => input_encoding = Some(value),
+            "--output-encoding" => {
+                output_encoding = Some(
+                    encoding_rs::Encoding::for_label(value.as_bytes())
+                        .ok_or_else(|| format!("unknown output encoding '{}'", value))?,
+                );
+            }
+            "--output-bom" => output_bom = true,
+            _ => unreachable!("{} is in FLAGS, but isn't handled", flag.long),
+        }
+    }
+
+    // the output is only transcoded once everything else has been done to it as UTF-8
+    if output_encoding.is_some() || output_bom {
+        if output_encoding.is_some() {
+            rewritten_by.push("--output-encoding");
+        }
+        if output_bom {
+            rewritten_by.push("--output-bom");
+        }
+
+        let encoding = output_encoding.unwrap_or(encoding_rs::UTF_8);
+        post_processors.push(Box::new(move |output| encode(output, encoding, output_bom)));
+    }
+
+    // the length prefix goes on last, so that it's the length of the output as it really ends up
+    if let Some(encoding) = frame {
+        post_processors.push(Box::new(move |output| with_length_prefix(output, encoding)));
+    }
+
+    // a batch writes an output for every file it patches, so flags about the one output there would be have nothing to
+    // go on, and would otherwise be quietly ignored
+    if batch.is_some() {
+        let single_output = [
+            ("--output", output.is_some()),
+            ("--split", split.is_some()),
+            ("--emit", emit.is_some()),
+            ("--manifest", manifest_output.is_some()),
+            ("--lockfile", lockfile.is_some()),
+            ("--hash-only", hash_only.is_some()),
+        ];
+
+        if let Some((flag, _)) = single_output.iter().find(|(_, given)| *given) {
+            return Err(format!("{} can't be used with --batch", flag).into());
+        }
+    }
+
+    // the digest is all that gets printed, so nothing would come of flags about where else the output goes
+    if hash_only.is_some() {
+        let elsewhere = [
+            ("--output", output.is_some()),
+            ("--tee", tee),
+            ("--split", split.is_some()),
+            ("--emit", emit.is_some()),
+        ];
+
+        if let Some((flag, _)) = elsewhere.iter().find(|(_, given)| *given) {
+            return Err(format!("{} can't be used with --hash-only", flag).into());
+        }
+    }
+
+    // `-` is how Unix tools spell stdin, which is also where the config comes from when no file is given
+    let buffer = match config_file.as_deref() {
+        None | Some("-") => {
+            let mut buffer = Vec::new();
+            std::io::stdin().lock().read_to_end(&mut buffer).unwrap();
+            buffer
+        }
+        Some(path) => std::fs::read(path)?,
+    };
+    let assuo_config = decode(&buffer, input_encoding.as_deref())?;
+
+    let config = assuo::models::try_parse_in_profile(
+        &assuo_config,
+        input_format,
+        config_profile.as_deref(),
+    )?;
+
+    if check_only {
+        let findings = config.check_urls(&options);
+        for finding in &findings {
+            eprintln!("{}", finding);
+        }
+
+        std::process::exit(if findings.is_empty() { 0 } else { 1 });
+    }
+
+    if verify_lengths {
+        let findings = tokio::runtime::Runtime::new()?.block_on(config.lint_bounds(&options))?;
+        for finding in &findings {
+            eprintln!("{}", finding);
+        }
+
+        std::process::exit(if findings.is_empty() { 0 } else { 1 });
+    }
+
+    if resolve_only {
+        let resolved = tokio::runtime::Runtime::new()?.block_on(resolve(config, &options))?;
+        print!("{}", assuo::models::to_toml(&resolved)?);
+        return Ok(());
+    }
+
+    // a footer is a checksum of the bytes before it, which wouldn't match them anymore once they're rewritten. a length
+    // prefix only goes in front of all of it, so that's fine
+    if let (Some(_), Some(flag)) = (&config.footer, rewritten_by.first()) {
+        return Err(format!("{} can't be used with a config that has a footer", flag).into());
+    }
+
+    // what was recorded in the lockfile before gets replayed, and anything new gets recorded into it
+    if let Some(path) = &lockfile {
+        options.lock = Some(match std::fs::read_to_string(path) {
+            Ok(payload) => Lock::from_toml(&payload)?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Lock::default(),
+            Err(error) => return Err(error.into()),
+        });
+    }
+
+    if let Some(pattern) = batch {
+        let out_dir = out_dir.ok_or("expected --out-dir to write what --batch patches into")?;
+        return patch_batch(
+            &pattern,
+            std::path::Path::new(&out_dir),
+            config.patch.unwrap_or_default(),
+            &options,
+            &post_processors,
+            out_permissions,
+        );
+    }
+
+    let mut profile = Profile::default();
+    let mut runtime = tokio::runtime::Runtime::new()?;
+    let patch = match apply_to {
+        // the config's own source is ignored entirely, and the patches go onto the given input instead
+        Some(path) => runtime.block_on(do_patch_onto(
+            std::fs::read(path)?,
+            config.patch.unwrap_or_default(),
+            &options,
+            &mut profile,
+        )),
+        None => runtime.block_on(do_patch_profiled(config, &options, &mut profile)),
+    };
+
+    // named so that they sort in the order the patches were applied in, like `000.bin`, `001.bin`... these get
+    // written even if patching failed, since seeing how far it got is most of what they're for
+    if let (Some(dir), Some(snapshots)) = (&snapshots_dir, &options.snapshots) {
+        std::fs::create_dir_all(dir)?;
+        for (index, snapshot) in snapshots.take().into_iter().enumerate() {
+            std::fs::write(
+                std::path::Path::new(dir).join(format!("{:03}.bin", index)),
+                snapshot,
+            )?;
+        }
+    }
+
+    let patch = patch?;
+
+    if let Some(path) = profile_output {
+        std::fs::write(path, profile.to_folded())?;
+    }
+
+    if let (Some(path), Some(lock)) = (&lockfile, &options.lock) {
+        write_output_file(std::path::Path::new(path), lock.to_toml().as_bytes(), None)?;
+    }
+
+    if let (Some(path), Some(manifest)) = (&manifest_output, &options.manifest) {
+        std::fs::write(path, manifest.to_json())?;
+    }
+
+    // this explains the output as it was patched, before anything else is done to it
+    if let Some(provenance) = &options.provenance {
+        explain_bytes(&patch, &provenance.take());
+    }
+
+    for warning in options.warnings.take() {
+        eprintln!("warning: {}", warning);
+    }
+
+    if strict_utf8 {
+        if let Err(error) = std::str::from_utf8(&patch) {
+            return Err(format!(
+                "output isn't valid UTF-8, starting at byte {}",
+                error.valid_up_to()
+            )
+            .into());
+        }
+    }
+
+    // these run in the order they were given in
+    let mut patch = patch;
+    for post_process in &post_processors {
+        patch = post_process(patch)?;
+    }
+
+    if let Some(hash) = hash_only {
+        println!("{}", hash.hex(&patch));
+        return Ok(());
+    }
+
+    let patch = match emit {
+        Some(emit) => emit.format(&emit_name, &patch).into_bytes(),
+        None => patch,
+    };
+
+    if let Some(size) = split {
+        // every chunk is written on its own, so a failed run can leave some of them behind
+        for (index, chunk) in patch.chunks(size).enumerate() {
+            let path = format!("{}{:0width$}", split_prefix, index, width = split_width);
+            write_output_file(std::path::Path::new(&path), chunk, out_permissions)?;
+        }
+
+        return Ok(());
+    }
+
+    match output {
+        Some(path) => {
+            // with --tee, stdout still gets the output even if the file couldn't be written, but it's still an error
+            let written = write_output_file(std::path::Path::new(&path), &patch, out_permissions);
+            if tee {
+                write_output(&patch)?;
+            }
+            written?;
+        }
+        None => write_output(&patch)?,
+    }
+
+    Ok(())
+}
+
+/// Patches every file matching the pattern on its own, as if it were the config's source, so spots are counted in
+/// each file. Every output is written into `out_dir`, at the same path the file had under the directory the pattern
+/// starts in, like `dist/a/b.txt` for `src/a/b.txt` with a pattern of `src/**/*.txt`.
+fn patch_batch(
+    pattern: &str,
+    out_dir: &std::path::Path,
+    patches: Vec<AssuoPatch>,
+    options: &PatchOptions,
+    post_processors: &[PostProcessor],
+    mode: Option<u32>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // the directories before the first wildcard are the same for every match
+    let root = std::path::Path::new(pattern)
+        .parent()
+        .map(|parent| {
+            parent
+                .components()
+                .take_while(|component| {
+                    !component
+                        .as_os_str()
+                        .to_string_lossy()
+                        .contains(&['*', '?', '['][..])
+                })
+                .collect::<std::path::PathBuf>()
+        })
+        .unwrap_or_default();
+
+    let mut runtime = tokio::runtime::Runtime::new()?;
+    let mut patched_any = false;
+
+    for path in glob::glob(pattern)? {
+        let path = path?;
+        if !path.is_file() {
+            continue;
+        }
+
+        let mut patched = runtime
+            .block_on(do_patch_onto(
+                std::fs::read(&path)?,
+                patches.clone(),
+                options,
+                &mut Profile::default(),
+            ))
+            .map_err(|error| format!("couldn't patch '{}': {}", path.display(), error))?;
+
+        for post_process in post_processors {
+            patched = post_process(patched)?;
+        }
+
+        let output = out_dir.join(path.strip_prefix(&root)?);
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        write_output_file(&output, &patched, mode)?;
+        patched_any = true;
+    }
+
+    for warning in options.warnings.take() {
+        eprintln!("warning: {}", warning);
+    }
+
+    if !patched_any {
+        return Err(format!("no files matched '{}'", pattern).into());
+    }
+
+    Ok(())
+}
+
+/// Combines the patch files given, and prints the combined file, or writes it to the file after `--output`.
+fn merge(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut paths = Vec::new();
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "--output" || arg == "-o" {
+            output = Some(args.next().ok_or("expected a path after --output")?);
+        } else {
+            paths.push(arg);
+        }
+    }
+
+    let mut files = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let payload = std::fs::read_to_string(path)?;
+        let file = assuo::models::try_parse(&payload)
+            .map_err(|error| format!("couldn't parse '{}': {}", path, error))?;
+        files.push(file);
+    }
+
+    let merged = assuo::models::to_toml(&assuo::models::merge(&files)?)?;
+
+    match output {
+        Some(path) => write_output_file(std::path::Path::new(&path), merged.as_bytes(), None)?,
+        None => print!("{}", merged),
+    }
+
+    Ok(())
+}
+
+/// Writes out an Assuo patch file whose source is the old file, with the patches that turn it into the new one.
+fn from_diff(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut paths = Vec::new();
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "--output" || arg == "-o" {
+            output = Some(args.next().ok_or("expected a path after --output")?);
+        } else {
+            paths.push(arg);
+        }
+    }
+
+    let (old, new) = match paths.as_slice() {
+        [old, new] => (old, new),
+        _ => return Err("expected an old and a new file after from-diff".into()),
+    };
+
+    let patches = assuo::diff::patches_from_diff(&std::fs::read(old)?, &std::fs::read(new)?);
+
+    // file sources are read relative to wherever assuo is ran from, which is rarely where the patch file ends up
+    let path = std::fs::canonicalize(old)?
+        .into_os_string()
+        .into_string()
+        .map_err(|path| format!("the path {:?} isn't UTF-8", path))?;
+
+    let file = AssuoFile {
+        source: AssuoSource::File {
+            path,
+            expect_size: None,
+            strip_bom: false,
+            range: None,
+        },
+        patch: Some(patches),
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    let file = assuo::models::to_toml(&file)?;
+
+    match output {
+        Some(path) => write_output_file(std::path::Path::new(&path), file.as_bytes(), None)?,
+        None => print!("{}", file),
+    }
+
+    Ok(())
+}
+
+/// Makes the output end in exactly one newline, like most tools expect text files to.
+fn trailing_newline(mut output: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    while output.last() == Some(&b'\n') {
+        output.pop();
+    }
+
+    output.push(b'\n');
+    Ok(output)
+}
+
+/// Puts the length of the output in front of it, for protocols that expect every message to say how long it is.
+fn with_length_prefix(output: Vec<u8>, encoding: LenEncoding) -> std::io::Result<Vec<u8>> {
+    let mut framed = encoding.encode(output.len()).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "the output is {} bytes long, which doesn't fit in a {}",
+                output.len(),
+                encoding.name()
+            ),
+        )
+    })?;
+
+    framed.extend(output);
+    Ok(framed)
+}
+
+/// Rewrites every line ending (`\n` or `\r\n`) in the output to the given one. A last line without a line ending is
+/// left without one.
+fn normalize_newlines(output: &[u8], newline: &[u8]) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(output.len());
+    let mut lines = output.split(|byte| *byte == b'\n').peekable();
+
+    while let Some(line) = lines.next() {
+        if lines.peek().is_none() {
+            normalized.extend_from_slice(line);
+            break;
         }
 
-        if arg == "--help" || arg == "-h" || arg == "/?" {
-            help();
-            std::process::exit(0);
+        normalized.extend_from_slice(line.strip_suffix(b"\r").unwrap_or(line));
+        normalized.extend_from_slice(newline);
+    }
+
+    normalized
+}
+
+/// Converts a unified diff into patches against the base file, and prints the base with them applied.
+fn diff_apply(base: &str, diff: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let base = std::fs::read(base)?;
+    let diff = std::fs::read_to_string(diff)?;
+
+    let patches = assuo::diff::patches_from_unified_diff(&base, &diff)?;
+    let patch = tokio::runtime::Runtime::new()?.block_on(do_patch_onto(
+        base,
+        patches,
+        &PatchOptions::default(),
+        &mut Profile::default(),
+    ))?;
+
+    write_output(&patch)?;
+    Ok(())
+}
+
+/// Source code the patched output can be emitted as, rather than raw bytes.
+#[derive(Clone, Copy)]
+enum Emit {
+    /// `pub const NAME: &[u8] = &[0x48, ...];`
+    Rust,
+    /// `const unsigned char NAME[] = {0x48, ...};`
+    C,
+}
+
+impl Emit {
+    /// Formats the bytes as an array with the given name, twelve bytes to a line.
+    fn format(self, name: &str, bytes: &[u8]) -> String {
+        let (open, close) = match self {
+            Emit::Rust => (format!("pub const {}: &[u8] = &[", name), "];"),
+            Emit::C => (
+                format!("const unsigned char {}[{}] = {{", name, bytes.len()),
+                "};",
+            ),
+        };
+
+        let mut code = open;
+        for line in bytes.chunks(12) {
+            let line = line
+                .iter()
+                .map(|byte| format!("0x{:02x},", byte))
+                .collect::<Vec<_>>();
+            code.push_str("\n    ");
+            code.push_str(&line.join(" "));
         }
+        code.push('\n');
+        code.push_str(close);
+        code.push('\n');
+        code
     }
+}
+
+/// A digest `--hash-only` can print, rather than the output.
+#[derive(Clone, Copy)]
+enum Hash {
+    Sha256,
+    Sha512,
+}
+
+impl Hash {
+    /// The digest of the bytes, in lowercase hex.
+    fn hex(self, bytes: &[u8]) -> String {
+        use sha2::{Digest, Sha256, Sha512};
 
-    let mut buffer = Vec::new();
-    std::io::stdin().lock().read_to_end(&mut buffer).unwrap();
-    let assuo_config = String::from_utf8(buffer).unwrap();
+        match self {
+            Hash::Sha256 => format!("{:x}", Sha256::digest(bytes)),
+            Hash::Sha512 => format!("{:x}", Sha512::digest(bytes)),
+        }
+    }
+}
+
+/// Writes the patched output to stdout in chunks, flushing after each one so that whatever we're piped into sees
+/// data as soon as possible. If whatever we're piped into stops reading, we stop writing.
+fn write_output(output: &[u8]) -> std::io::Result<()> {
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    let stdout = std::io::stdout();
+    let mut stdout = std::io::BufWriter::with_capacity(CHUNK_SIZE, stdout.lock());
+
+    let result = output.chunks(CHUNK_SIZE).try_for_each(|chunk| {
+        stdout.write_all(chunk)?;
+        stdout.flush()
+    });
+
+    match result {
+        Err(error) if error.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
 
-    let config = assuo::models::try_parse(&assuo_config).unwrap();
-    let patch = tokio::runtime::Runtime::new()?.block_on(do_patch(config))?;
-    std::io::stdout().lock().write_all(&patch).unwrap();
+/// Writes the patched output to a temporary file next to the given path, and only once it's all written, renames it
+/// over the path. That way, nobody ever sees a partially written output. If there's a mode, the temporary file gets
+/// it before the rename, so the output never has any other mode, not even for a moment.
+fn write_output_file(
+    path: &std::path::Path,
+    output: &[u8],
+    mode: Option<u32>,
+) -> std::io::Result<()> {
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => std::path::Path::new("."),
+    };
 
+    let mut file = tempfile::NamedTempFile::new_in(directory)?;
+    file.write_all(output)?;
+    match mode {
+        Some(mode) => set_mode(file.as_file(), mode)?,
+        // temporary files are only readable by their owner, which the output shouldn't be left as
+        None => file
+            .as_file()
+            .set_permissions(output_permissions(path, directory)?)?,
+    }
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|error| error.error)?;
+
+    Ok(())
+}
+
+/// The permissions the output would have if it was written to in place: those of the file it replaces, or those any
+/// new file in `directory` gets (whatever the umask leaves of `0666`) if there's nothing there yet.
+fn output_permissions(
+    path: &std::path::Path,
+    directory: &std::path::Path,
+) -> std::io::Result<std::fs::Permissions> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.permissions()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            // the umask can't be read without changing it, so a file is made to see what it leaves. it goes in a
+            // directory of its own, so that nothing shows up at the output's path until the output is complete
+            let probe = tempfile::tempdir_in(directory)?;
+            let file = std::fs::File::create(probe.path().join("probe"))?;
+            Ok(file.metadata()?.permissions())
+        }
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(file: &std::fs::File, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    file.set_permissions(std::fs::Permissions::from_mode(mode))
+}
+
+// modes are a Unix thing, and --out-permissions already warned that it's ignored
+#[cfg(not(unix))]
+fn set_mode(_file: &std::fs::File, _mode: u32) -> std::io::Result<()> {
     Ok(())
 }
 
+/// Prints every byte of the buffer in the middle of patching to stderr, next to the spots in the original source it
+/// stands for (or `-`, for inserted bytes).
+fn dump_indexes(snapshot: &PatchSnapshot) {
+    eprintln!("after patch #{}:", snapshot.patch);
+
+    for (position, (byte, indexes)) in snapshot.buffer.iter().zip(snapshot.indexes).enumerate() {
+        let original = indexes
+            .iter()
+            .filter(|index| **index != std::usize::MAX)
+            .map(|index| index.to_string())
+            .collect::<Vec<_>>();
+
+        let original = if original.is_empty() {
+            String::from("-")
+        } else {
+            original.join(",")
+        };
+
+        eprintln!(
+            "{:>8}  {:02x} {:?}  {}",
+            position, byte, *byte as char, original
+        );
+    }
+}
+
+/// Prints the output to stderr as a hex dump, next to where its bytes came from. Bytes inserted by the same patch, or
+/// that follow each other in the base, share a line, up to 16 of them. Bytes past the last one with an origin are the
+/// footer.
+fn explain_bytes(output: &[u8], origins: &[Origin]) {
+    let follows = |position: usize| match (origins.get(position - 1), origins.get(position)) {
+        (Some(Origin::Base(before)), Some(Origin::Base(after))) => *after == before + 1,
+        (before, after) => before == after,
+    };
+
+    let mut start = 0;
+    while start < output.len() {
+        let line_end = output.len().min(start + 16);
+        let end = (start + 1..line_end)
+            .find(|position| !follows(*position))
+            .unwrap_or(line_end);
+
+        let hex = output[start..end]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let origin = match origins.get(start) {
+            Some(Origin::Base(offset)) => format!("base {}..{}", offset, offset + (end - start)),
+            Some(Origin::Patch(patch)) => format!("patch #{}", patch),
+            None => String::from("footer"),
+        };
+
+        eprintln!("{:08x}  {:<47}  {}", start, hex, origin);
+        start = end;
+    }
+}
+
+/// Transcodes the piped in config to UTF-8. A BOM always wins over the given encoding, and without either, the
+/// config is assumed to be UTF-8.
+fn decode(bytes: &[u8], encoding: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    let encoding = match encoding {
+        Some(label) => encoding_rs::Encoding::for_label(label.as_bytes())
+            .ok_or_else(|| format!("unknown input encoding '{}'", label))?,
+        None => encoding_rs::UTF_8,
+    };
+
+    let (config, encoding, had_errors) = encoding.decode(bytes);
+    if had_errors {
+        return Err(format!("config isn't valid {}", encoding.name()).into());
+    }
+
+    Ok(config.into_owned())
+}
+
+/// Transcodes UTF-8 output into the given encoding, with the encoding's BOM in front of it if `bom` is set.
+fn encode(
+    output: Vec<u8>,
+    encoding: &'static encoding_rs::Encoding,
+    bom: bool,
+) -> std::io::Result<Vec<u8>> {
+    let invalid = |reason: String| std::io::Error::new(std::io::ErrorKind::InvalidData, reason);
+
+    let text = String::from_utf8(output).map_err(|error| {
+        invalid(format!(
+            "the output can only be written as {} if it's UTF-8, but it isn't at byte {}",
+            encoding.name(),
+            error.utf8_error().valid_up_to()
+        ))
+    })?;
+
+    // encoding_rs only decodes UTF-16, and encodes anything it's asked to write as UTF-16 as UTF-8 instead
+    let (bom_bytes, mut encoded): (&[u8], Vec<u8>) = if encoding == encoding_rs::UTF_16LE {
+        let units = text
+            .encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes().to_vec());
+        (b"\xFF\xFE", units.collect())
+    } else if encoding == encoding_rs::UTF_16BE {
+        let units = text
+            .encode_utf16()
+            .flat_map(|unit| unit.to_be_bytes().to_vec());
+        (b"\xFE\xFF", units.collect())
+    } else if encoding == encoding_rs::UTF_8 {
+        (b"\xEF\xBB\xBF", text.into_bytes())
+    } else {
+        let (encoded, _, had_errors) = encoding.encode(&text);
+        if had_errors {
+            return Err(invalid(format!(
+                "the output has characters that can't be written as {}",
+                encoding.name()
+            )));
+        }
+
+        (b"", encoded.into_owned())
+    };
+
+    if !bom {
+        return Ok(encoded);
+    }
+
+    if bom_bytes.is_empty() {
+        return Err(invalid(format!("{} doesn't have a BOM", encoding.name())));
+    }
+
+    encoded.splice(0..0, bom_bytes.iter().copied());
+    Ok(encoded)
+}
+
+/// Resolves the base and every patch source of a config, and swaps them out for the `bytes` they resolved to.
+/// Nothing gets patched, so the result can be snapshotted and ran later to get the same output.
+async fn resolve(config: AssuoFile, options: &PatchOptions) -> std::io::Result<AssuoFile> {
+    let config = config.resolve_with(options).await?;
+    let patches = config.patch.unwrap_or_default();
+
+    // `captured` and `base` sources read from the base, which isn't around to be read from after this
+    let options = PatchOptions {
+        captures: take_captures(&config.source, &patches)?,
+        base: Some(std::sync::Arc::new(config.source.clone())),
+        ..options.clone()
+    };
+
+    let mut resolved = Vec::new();
+    for patch in patches {
+        resolved.push(with_bytes(patch.resolve_with(&options).await?));
+    }
+
+    Ok(AssuoFile {
+        source: AssuoSource::Bytes(config.source),
+        patch: Some(resolved),
+        expect_output_sha256: config.expect_output_sha256,
+        footer: config.footer,
+    })
+}
+
+/// Swaps out every resolved source of a patch for the `bytes` it resolved to.
+fn with_bytes(patch: AssuoPatch<Vec<u8>>) -> AssuoPatch {
+    patch.map_sources(&mut AssuoSource::Bytes)
+}
+
+/// A flag assuo takes.
+struct Flag {
+    /// The long way of writing the flag, like `--output`.
+    long: &'static str,
+    /// The short way of writing the flag, like `-o`, if it has one.
+    short: Option<&'static str>,
+    /// What has to come after the flag, like `a path`, if it takes a value.
+    value: Option<&'static str>,
+    /// What the flag does, for the help. Flags without any are left out of it.
+    help: Option<&'static str>,
+}
+
+/// Every flag assuo takes, in the order the help lists them in. Flags are looked up in here before they're handled,
+/// so a flag that isn't in here is ignored, and `--list-flags` can't leave any out.
+const FLAGS: &[Flag] = &[
+    Flag {
+        long: "--help",
+        short: Some("-h"),
+        value: None,
+        help: Some("Prints help."),
+    },
+    Flag {
+        long: "--init",
+        short: Some("-i"),
+        value: None,
+        help: Some("Makes a new blank assuo patch file."),
+    },
+    Flag {
+        long: "--bench-self",
+        short: None,
+        value: None,
+        help: Some(
+            "Patches a large file made up in memory, and prints how long it took and how fast.",
+        ),
+    },
+    Flag {
+        long: "--file",
+        short: Some("-f"),
+        value: Some("a path"),
+        help: Some("Reads the config from a file rather than stdin. A file of `-` means stdin."),
+    },
+    Flag {
+        long: "--output",
+        short: Some("-o"),
+        value: Some("a path"),
+        help: Some("Writes the output to a file rather than stdout, all at once."),
+    },
+    Flag {
+        long: "--out-permissions",
+        short: None,
+        value: Some("an octal mode"),
+        help: Some(
+            "Gives the output files this mode, like 0755, on Unix. Elsewhere, it's warned about.",
+        ),
+    },
+    Flag {
+        long: "--tee",
+        short: None,
+        value: None,
+        help: Some(
+            "With --output, writes the output to stdout too. If one fails, the other still works.",
+        ),
+    },
+    Flag {
+        long: "--split",
+        short: None,
+        value: Some("a positive number of bytes"),
+        help: Some("Writes the output to files of this many bytes each. The last may be shorter."),
+    },
+    Flag {
+        long: "--split-prefix",
+        short: None,
+        value: Some("a prefix"),
+        help: Some(
+            "What the files written by --split start with, before the index. Defaults to `part_`.",
+        ),
+    },
+    Flag {
+        long: "--split-width",
+        short: None,
+        value: Some("a number of digits"),
+        help: Some("How many digits the index of the files written by --split has. Defaults to 3."),
+    },
+    Flag {
+        long: "--resolve-only",
+        short: Some("-r"),
+        value: None,
+        help: Some("Prints the config with every source resolved to bytes, without patching."),
+    },
+    Flag {
+        long: "--check-only",
+        short: None,
+        value: None,
+        help: Some(
+            "Checks that the config parses and every url in it is valid, without fetching them.",
+        ),
+    },
+    Flag {
+        long: "--verify-lengths",
+        short: None,
+        value: None,
+        help: Some("Checks every patch's spot and count against the source, without patching."),
+    },
+    Flag {
+        long: "--apply-to",
+        short: None,
+        value: Some("a path"),
+        help: Some("Patches the given file, rather than the config's source."),
+    },
+    Flag {
+        long: "--batch",
+        short: None,
+        value: Some("a pattern"),
+        help: Some(
+            "Patches every file matching the pattern on its own, rather than the config's source.",
+        ),
+    },
+    Flag {
+        long: "--out-dir",
+        short: None,
+        value: Some("a path"),
+        help: Some(
+            "Where --batch writes each output, at the path it had under the pattern's directory.",
+        ),
+    },
+    Flag {
+        long: "--emit",
+        short: None,
+        value: Some("rust or c"),
+        help: Some("Prints the output as a `rust` or `c` array of bytes, rather than raw bytes."),
+    },
+    Flag {
+        long: "--emit-name",
+        short: None,
+        value: Some("a name"),
+        help: Some("The name of the array printed by --emit. Defaults to `OUTPUT`."),
+    },
+    Flag {
+        long: "--hash-only",
+        short: None,
+        value: Some("sha256 or sha512"),
+        help: Some("Prints the hex digest of the output, rather than the output itself."),
+    },
+    Flag {
+        long: "--lockfile",
+        short: None,
+        value: Some("a path"),
+        help: Some(
+            "Replays what sources like `now` resolved to from this file, recording any missing.",
+        ),
+    },
+    Flag {
+        long: "--manifest",
+        short: None,
+        value: Some("a path"),
+        help: Some("Writes every resolved source, with its length and SHA-256, to a file as JSON."),
+    },
+    Flag {
+        long: "--snapshots",
+        short: None,
+        value: Some("a directory"),
+        help: Some(
+            "Writes the output after every patch into the directory, as `000.bin`, `001.bin`...",
+        ),
+    },
+    Flag {
+        long: "--profile",
+        short: None,
+        value: Some("a path"),
+        help: Some(
+            "Writes how long each phase took to a file, in the folded format of flamegraph tools.",
+        ),
+    },
+    Flag {
+        long: "--trailing-newline",
+        short: None,
+        value: None,
+        help: Some("Makes the output end in exactly one newline."),
+    },
+    Flag {
+        long: "--normalize-newlines",
+        short: None,
+        value: Some("lf or crlf"),
+        help: Some("Rewrites every line ending in the output to `lf` or `crlf`."),
+    },
+    Flag {
+        long: "--output-encoding",
+        short: None,
+        value: Some("an encoding"),
+        help: Some(
+            "Writes the output, which has to be UTF-8, in another encoding, like `utf-16le`.",
+        ),
+    },
+    Flag {
+        long: "--output-bom",
+        short: None,
+        value: None,
+        help: Some("Puts the BOM of the output's encoding in front of it."),
+    },
+    Flag {
+        long: "--frame",
+        short: None,
+        value: Some("u8, u16le, u16be, u32le or u32be"),
+        help: Some("Puts the length of the output in front of it, encoded as the given integer."),
+    },
+    Flag {
+        long: "--strict-utf8",
+        short: None,
+        value: None,
+        help: Some("Errors when the output isn't valid UTF-8, rather than writing it."),
+    },
+    Flag {
+        long: "--strict-spots",
+        short: None,
+        value: None,
+        help: Some("Errors when multiple inserts target the same spot in the same direction."),
+    },
+    Flag {
+        long: "--sort-patches",
+        short: None,
+        value: None,
+        help: Some(
+            "Applies patches in order of their spots, which only matters for patches that collide.",
+        ),
+    },
+    Flag {
+        long: "--no-empty-sources",
+        short: None,
+        value: None,
+        help: Some("Errors when the source, or the source of any patch, resolves to no bytes."),
+    },
+    Flag {
+        long: "--continue-on-resolve-error",
+        short: None,
+        value: None,
+        help: Some("Inserts nothing, with a warning, for patch sources that can't be resolved."),
+    },
+    Flag {
+        long: "--dedupe-sources",
+        short: None,
+        value: None,
+        help: Some("Resolves sources that patches share only once."),
+    },
+    Flag {
+        long: "--input-encoding",
+        short: None,
+        value: Some("an encoding"),
+        help: Some("The encoding of the piped in config. Defaults to UTF-8, unless it has a BOM."),
+    },
+    Flag {
+        long: "--input-format",
+        short: None,
+        value: Some("toml, json, yaml, or auto"),
+        help: Some(
+            "Whether the config is `toml`, `json` or `yaml`. Defaults to `auto`, which tries each.",
+        ),
+    },
+    Flag {
+        long: "--use-profile",
+        short: None,
+        value: Some("a name"),
+        help: Some("Merges that profile from the config's `[profiles]` table into the rest of it."),
+    },
+    Flag {
+        long: "--timeout-all",
+        short: None,
+        value: Some("a duration like 10s"),
+        help: Some(
+            "How long fetching a url can take, like `500ms` or `10s`, unless its source says.",
+        ),
+    },
+    Flag {
+        long: "--retry-all",
+        short: None,
+        value: Some("a number of retries"),
+        help: Some("How many more times a failed fetch of a url is tried, unless its source says."),
+    },
+    Flag {
+        long: "--trace-http",
+        short: None,
+        value: None,
+        help: Some(
+            "Prints every HTTP request made, and how it went, to stderr, leaving out credentials.",
+        ),
+    },
+    Flag {
+        long: "--allow",
+        short: None,
+        value: Some("source kinds"),
+        help: Some(
+            "Only allows these kinds of sources, separated by commas, like `text,bytes,url`.",
+        ),
+    },
+    Flag {
+        long: "--base-url",
+        short: None,
+        value: Some("a url"),
+        help: Some("Resolves relative urls in the config against this url."),
+    },
+    Flag {
+        long: "--explain-bytes",
+        short: None,
+        value: None,
+        help: Some(
+            "Prints the output to stderr as a hex dump, next to which patch each byte came from.",
+        ),
+    },
+    // for debugging the patching algorithm, so it's left out of the help
+    Flag {
+        long: "--dump-indexes",
+        short: None,
+        value: None,
+        help: None,
+    },
+    // for tools like shell completions, so it's left out of the help
+    Flag {
+        long: "--list-flags",
+        short: None,
+        value: None,
+        help: None,
+    },
+];
+
+/// Finds the flag written like this. `/?` is how Windows tools spell `--help`, so it's that too.
+fn find_flag(name: &str) -> Option<&'static Flag> {
+    let name = if name == "/?" { "--help" } else { name };
+    FLAGS
+        .iter()
+        .find(|flag| flag.long == name || flag.short == Some(name))
+}
+
+/// Prints every flag on a line of its own, as its long name, its short name (or `-` if it has none), and how many
+/// values it takes, separated by tabs. This is meant for other tools to read, so it should stay this way.
+fn list_flags() {
+    for flag in FLAGS {
+        println!(
+            "{}\t{}\t{}",
+            flag.long,
+            flag.short.unwrap_or("-"),
+            if flag.value.is_some() { 1 } else { 0 }
+        );
+    }
+}
+
 fn help() {
     eprintln!(
         "OVERVIEW: assuo patch maker
@@ -34,23 +1317,124 @@ fn help() {
 USAGE:
   assuo --init
   assuo --help
+  assuo --bench-self
   cat assuo.toml | assuo
+  assuo --file assuo.toml
+  assuo assuo.toml
+  cat assuo.toml | assuo --file -
+  assuo --file patches.toml --apply-to input.bin
+  assuo --file patches.toml --batch 'src/**/*.txt' --out-dir dist
+  assuo diff-apply main.rs changes.diff
+  assuo from-diff old.txt new.txt --output patch.toml
+  assuo merge a.toml b.toml --output combined.toml
+  cat assuo.toml | assuo --resolve-only
+  cat assuo.toml | assuo --check-only
+  cat assuo.toml | assuo --verify-lengths
+  cat assuo.toml | assuo --input-encoding utf-16le
+  cat assuo.json | assuo --input-format json
+  cat assuo.toml | assuo --use-profile prod
+  cat assuo.toml | assuo --allow text,bytes
+  cat assuo.toml | assuo --base-url https://example.com/parts/
+  cat assuo.toml | assuo --trace-http
+  cat assuo.toml | assuo --explain-bytes
+  cat assuo.toml | assuo --timeout-all 10s --retry-all 2
+  cat assuo.toml | assuo --output out.bin
+  cat assuo.toml | assuo --output out.bin --tee
+  cat assuo.toml | assuo --output out.sh --out-permissions 0755
+  cat assuo.toml | assuo --split 1024 --split-prefix part_
+  cat assuo.toml | assuo --profile out.folded
+  cat assuo.toml | assuo --lockfile assuo.lock
+  cat assuo.toml | assuo --manifest manifest.json
+  cat assuo.toml | assuo --snapshots snapshots
+  cat assuo.toml | assuo --emit rust --emit-name GREETING
+  cat assuo.toml | assuo --hash-only sha256
+  cat assuo.toml | assuo --trailing-newline
+  cat assuo.toml | assuo --normalize-newlines=crlf
+  cat assuo.toml | assuo --output-encoding utf-16le --output-bom
+  cat assuo.toml | assuo --frame u32be
+  cat assuo.toml | assuo --no-empty-sources
+  cat assuo.toml | assuo --continue-on-resolve-error
+  cat assuo.toml | assuo --dedupe-sources
 
-OPTIONS:
--h, --help   Prints help.
--i, --init   Makes a new blank assuo patch file."
+OPTIONS:"
+    );
+
+    for flag in FLAGS {
+        let help = match flag.help {
+            Some(help) => help,
+            None => continue,
+        };
+
+        let names = match flag.short {
+            Some(short) => format!("{}, {}", short, flag.long),
+            None => String::from(flag.long),
+        };
+
+        eprintln!("{:<20} {}", names, help);
+    }
+
+    eprintln!(
+        "
+The config file can be given on its own too, like `assuo assuo.toml`, so patch files starting with
+`#!/usr/bin/env assuo` can be ran. Just `-` means stdin, like it does for --file."
     );
 }
 
 fn init() {
-    let assuo_config = r#"[source]
-text = "Hello!"
-
-[[patch]]
-do = "insert"
-way = "post"
-spot = 4
-source = { text = ", World" }
-"#;
-    println!("{}", assuo_config);
+    print!("{}", DEFAULT_TEMPLATE);
+}
+
+/// The size of the base `--bench-self` patches.
+const BENCH_BASE_LEN: usize = 64 * 1024;
+
+/// The amount of patches `--bench-self` applies.
+const BENCH_PATCHES: usize = 1000;
+
+/// Patches a large base with a lot of inserts and removals spread all over it, and prints how long that took and how
+/// many bytes of the base got patched a second to stderr. Every source is in memory, so nothing but the patching
+/// algorithm is measured.
+fn bench_self() -> Result<(), Box<dyn std::error::Error>> {
+    let patches = (0..BENCH_PATCHES)
+        .map(|i| {
+            // the spots are jumbled, but the same every run, so runs are comparable
+            let spot = 1 + (i * 7919) % (BENCH_BASE_LEN - 1);
+            match i % 3 {
+                0 => AssuoPatch::Insert {
+                    way: Direction::Pre,
+                    spot,
+                    source: AssuoSource::Text(String::from("inserted")),
+                },
+                1 => AssuoPatch::Insert {
+                    way: Direction::Post,
+                    spot,
+                    source: AssuoSource::Text(String::from("inserted")),
+                },
+                _ => AssuoPatch::Remove {
+                    way: Direction::At,
+                    spot,
+                    count: 1,
+                },
+            }
+        })
+        .collect();
+
+    let file = AssuoFile {
+        source: AssuoSource::Bytes(vec![b'.'; BENCH_BASE_LEN]),
+        patch: Some(patches),
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    let mut runtime = tokio::runtime::Runtime::new()?;
+    let start = std::time::Instant::now();
+    runtime.block_on(do_patch(file))?;
+    let elapsed = start.elapsed();
+
+    let megabytes = BENCH_BASE_LEN as f64 / (1024.0 * 1024.0);
+    eprintln!(
+        "patched {} bytes with {} patches in {:?}",
+        BENCH_BASE_LEN, BENCH_PATCHES, elapsed
+    );
+    eprintln!("{:.3} MB/s", megabytes / elapsed.as_secs_f64());
+    Ok(())
 }
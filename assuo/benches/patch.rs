@@ -0,0 +1,221 @@
+//! Benchmarks for the patching algorithm of `assuo`
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use assuo::{
+    models::{AssuoFile, AssuoPatch, AssuoSource, Direction},
+    patch::{do_patch, Patcher},
+};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+/// Counts every time memory gets grown, so that how often patching has to reallocate can be compared.
+struct CountingAllocator;
+
+static REALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        REALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The size of the base every workload patches.
+const BASE_LEN: usize = 64 * 1024;
+
+/// The amount of patches in every workload.
+const PATCHES: usize = 1000;
+
+/// Makes a file to patch, given the amount of patches it should have.
+type Workload = fn(usize) -> AssuoFile;
+
+/// Makes a file with a large base and the given patches. Sources are only ever in-memory, so nothing but the
+/// algorithm is measured.
+fn file(patches: Vec<AssuoPatch>) -> AssuoFile {
+    AssuoFile {
+        source: AssuoSource::Bytes(vec![b'.'; BASE_LEN]),
+        patch: Some(patches),
+        expect_output_sha256: None,
+        footer: None,
+    }
+}
+
+/// Spots that are spread all over the base, in a fixed but jumbled order, so runs are comparable.
+fn scattered_spots(count: usize) -> impl Iterator<Item = usize> {
+    (0..count).map(|i| 1 + (i * 7919) % (BASE_LEN - 1))
+}
+
+/// Spots that are evenly spread over the base, starting from the back. Removals are listed back to front, like they
+/// usually are when written by hand, so that every one of them is still inside of the base.
+fn back_to_front_spots(count: usize) -> impl Iterator<Item = usize> {
+    let stride = BASE_LEN / (count + 1);
+    (1..=count).rev().map(move |i| i * stride)
+}
+
+fn way(i: usize) -> Direction {
+    match i % 2 {
+        0 => Direction::Pre,
+        _ => Direction::Post,
+    }
+}
+
+/// `count` inserts that all land on the same spot, alternating between pre and post inserts.
+fn adjacent_inserts(count: usize) -> AssuoFile {
+    file(
+        (0..count)
+            .map(|i| AssuoPatch::Insert {
+                way: way(i),
+                spot: BASE_LEN / 2,
+                source: AssuoSource::Text(String::from("inserted")),
+            })
+            .collect(),
+    )
+}
+
+/// `count` inserts spread all over the base, alternating between pre and post inserts.
+fn scattered_inserts(count: usize) -> AssuoFile {
+    file(
+        scattered_spots(count)
+            .enumerate()
+            .map(|(i, spot)| AssuoPatch::Insert {
+                way: way(i),
+                spot,
+                source: AssuoSource::Text(String::from("inserted")),
+            })
+            .collect(),
+    )
+}
+
+/// `count` removals of a few bytes each, spread over the base.
+fn removes(count: usize) -> AssuoFile {
+    file(
+        back_to_front_spots(count)
+            .enumerate()
+            .map(|(i, spot)| AssuoPatch::Remove {
+                way: way(i),
+                spot,
+                count: 8,
+            })
+            .collect(),
+    )
+}
+
+/// `count` patches spread over the base, cycling between pre inserts, post inserts and removals.
+fn mixed(count: usize) -> AssuoFile {
+    file(
+        back_to_front_spots(count)
+            .enumerate()
+            .map(|(i, spot)| match i % 3 {
+                0 => AssuoPatch::Insert {
+                    way: Direction::Pre,
+                    spot,
+                    source: AssuoSource::Bytes(vec![0xFF; 16]),
+                },
+                1 => AssuoPatch::Insert {
+                    way: Direction::Post,
+                    spot,
+                    source: AssuoSource::Text(String::from("inserted")),
+                },
+                _ => AssuoPatch::Remove {
+                    way: way(i),
+                    spot,
+                    count: 8,
+                },
+            })
+            .collect(),
+    )
+}
+
+/// How many bytes each insert of the presizing workload inserts. Altogether, they insert several times the base, so
+/// that the data has to grow more than once.
+const PRESIZED_INSERT_LEN: usize = 4 * 1024;
+
+/// `count` already resolved inserts of [`PRESIZED_INSERT_LEN`] bytes each, spread all over the base, for a
+/// [`Patcher`] to apply.
+fn resolved_inserts(count: usize) -> Vec<AssuoPatch<Vec<u8>>> {
+    scattered_spots(count)
+        .enumerate()
+        .map(|(i, spot)| AssuoPatch::Insert {
+            way: way(i),
+            spot,
+            source: vec![b'+'; PRESIZED_INSERT_LEN],
+        })
+        .collect()
+}
+
+/// Applies the patches onto a large base, first making room for everything they insert if `presize` is set.
+fn apply(patches: Vec<AssuoPatch<Vec<u8>>>, presize: bool) -> Vec<u8> {
+    let mut patcher = Patcher::new(vec![b'.'; BASE_LEN]);
+    if presize {
+        patcher.reserve(patches.len() * PRESIZED_INSERT_LEN);
+    }
+
+    for patch in patches {
+        patcher.apply(patch).unwrap();
+    }
+
+    patcher.finish()
+}
+
+fn bench_patch(c: &mut Criterion) {
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let workloads: [(&str, Workload); 4] = [
+        ("adjacent inserts", adjacent_inserts),
+        ("scattered inserts", scattered_inserts),
+        ("removes", removes),
+        ("mixed", mixed),
+    ];
+
+    for (name, workload) in workloads.iter() {
+        c.bench_function(&format!("{} {}", PATCHES, name), |b| {
+            b.iter_batched(
+                || workload(PATCHES),
+                |file| runtime.block_on(do_patch(file)).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
+/// Compares applying many inserts with and without making room for them first, both in time and in how many times
+/// memory had to be grown.
+fn bench_presizing(c: &mut Criterion) {
+    // every insert is large, so there are less of them, to keep a run about as long as the other workloads
+    let count = PATCHES / 10;
+
+    for (name, presize) in &[("growing", false), ("presized", true)] {
+        let patches = resolved_inserts(count);
+        let before = REALLOCATIONS.load(Ordering::Relaxed);
+        apply(patches, *presize);
+        println!(
+            "{} {} inserts: {} reallocations",
+            count,
+            name,
+            REALLOCATIONS.load(Ordering::Relaxed) - before
+        );
+
+        c.bench_function(&format!("{} {} inserts", count, name), |b| {
+            b.iter_batched(
+                || resolved_inserts(count),
+                |patches| apply(patches, *presize),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
+criterion_group!(benches, bench_patch, bench_presizing);
+criterion_main!(benches);
@@ -0,0 +1,419 @@
+//! This module converts unified diffs (the kind `diff -u` makes) into patches, and works out the patches between two
+//! versions of a file.
+
+use std::io::{Error, ErrorKind};
+
+use crate::models::{AssuoPatch, AssuoSource, Direction};
+
+/// Converts every hunk of a unified diff into patches against `base`, which is the file the diff was made from.
+/// Every removed line becomes a removal, and every run of added lines becomes a single insert. Context and removed
+/// lines are checked against the base, so a diff for some other file is an error rather than a mess.
+///
+/// # Example
+/// ```
+/// use assuo::diff::patches_from_unified_diff;
+///
+/// let diff = "--- a.txt
+/// +++ b.txt
+/// @@ -1,2 +1,2 @@
+///  Hello
+/// -World
+/// +There
+/// ";
+///
+/// assert_eq!(patches_from_unified_diff(b"Hello\nWorld\n", diff).unwrap().len(), 2)
+/// ```
+pub fn patches_from_unified_diff(base: &[u8], diff: &str) -> std::io::Result<Vec<AssuoPatch>> {
+    let lines = Lines::new(base);
+    let mut patches = Vec::new();
+
+    let mut diff_lines = diff.lines().peekable();
+    let mut hunk = 0;
+
+    while let Some(header) = diff_lines.next() {
+        // everything outside of a hunk (like the `---` and `+++` lines) is ignored
+        if !header.starts_with("@@ ") {
+            continue;
+        }
+
+        let (old_start, mut old_left, mut new_left) =
+            parse_hunk_header(header).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("hunk #{} has an invalid header '{}'", hunk, header),
+                )
+            })?;
+
+        // an empty old range starts *after* the line it names, rather than at it
+        let mut line = if old_left == 0 {
+            old_start
+        } else {
+            old_start.saturating_sub(1)
+        };
+
+        let mut insert: Option<(usize, Vec<u8>)> = None;
+
+        while old_left > 0
+            || new_left > 0
+            || matches!(diff_lines.peek(), Some(line) if line.starts_with('\\'))
+        {
+            let body = diff_lines.next().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("hunk #{} ends early", hunk),
+                )
+            })?;
+
+            let (kind, text) = match body.chars().next() {
+                Some(kind) => (kind, &body[kind.len_utf8()..]),
+                // some tools strip the space off of empty context lines
+                None => (' ', ""),
+            };
+
+            match kind {
+                '+' => {
+                    let (_, bytes) = insert.get_or_insert_with(|| (line, Vec::new()));
+                    bytes.extend_from_slice(text.as_bytes());
+                    bytes.push(b'\n');
+                    new_left = new_left.saturating_sub(1);
+                    continue;
+                }
+                // the line before this one doesn't end in a newline
+                '\\' => {
+                    if let Some((_, bytes)) = &mut insert {
+                        bytes.pop();
+                    }
+                    continue;
+                }
+                ' ' | '-' => {
+                    if !lines.matches(line, text) {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!("hunk #{} doesn't match the base at line {}", hunk, line + 1),
+                        ));
+                    }
+                }
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("hunk #{} has an invalid line '{}'", hunk, body),
+                    ))
+                }
+            }
+
+            if let Some((at, bytes)) = insert.take() {
+                patches.push(lines.insert(at, bytes)?);
+            }
+
+            if kind == '-' {
+                patches.push(lines.remove(line)?);
+            } else {
+                new_left = new_left.saturating_sub(1);
+            }
+
+            old_left = old_left.saturating_sub(1);
+            line += 1;
+        }
+
+        if let Some((at, bytes)) = insert.take() {
+            patches.push(lines.insert(at, bytes)?);
+        }
+
+        hunk += 1;
+    }
+
+    Ok(patches)
+}
+
+/// Parses `@@ -start,count +start,count @@` into the old start, the old count and the new count. Counts that are
+/// left out are 1.
+fn parse_hunk_header(header: &str) -> Option<(usize, usize, usize)> {
+    let mut ranges = header.trim_start_matches("@@ ").split(' ');
+
+    let range = |range: Option<&str>, sign: char| -> Option<(usize, usize)> {
+        let range = range?.strip_prefix(sign)?;
+        let mut parts = range.splitn(2, ',');
+        let start = parts.next()?.parse().ok()?;
+        let count = match parts.next() {
+            Some(count) => count.parse().ok()?,
+            None => 1,
+        };
+        Some((start, count))
+    };
+
+    let (old_start, old_count) = range(ranges.next(), '-')?;
+    let (_, new_count) = range(ranges.next(), '+')?;
+    Some((old_start, old_count, new_count))
+}
+
+/// The lines of the base, and where each of them are.
+struct Lines<'a> {
+    base: &'a [u8],
+    /// The offset of the start of every line, and then the end of the base.
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(base: &'a [u8]) -> Self {
+        let mut starts = vec![0];
+        starts.extend(
+            base.iter()
+                .enumerate()
+                .filter(|(_, byte)| **byte == b'\n')
+                .map(|(index, _)| index + 1),
+        );
+
+        if starts.last() != Some(&base.len()) {
+            starts.push(base.len());
+        }
+
+        Lines { base, starts }
+    }
+
+    /// The offset of the start of the line, which is the end of the base for the line after the last one.
+    fn offset(&self, line: usize) -> std::io::Result<usize> {
+        self.starts.get(line).copied().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("line {} is past the end of the base", line + 1),
+            )
+        })
+    }
+
+    fn matches(&self, line: usize, text: &str) -> bool {
+        match (self.starts.get(line), self.starts.get(line + 1)) {
+            (Some(start), Some(end)) => {
+                let found = &self.base[*start..*end];
+                let found = found.strip_suffix(b"\n").unwrap_or(found);
+                found == text.as_bytes()
+            }
+            _ => false,
+        }
+    }
+
+    /// An insert of the bytes right before the line.
+    fn insert(&self, line: usize, bytes: Vec<u8>) -> std::io::Result<AssuoPatch> {
+        let spot = self.offset(line)?;
+
+        // pre inserts need a byte after them, so the very end of the base gets a post insert instead. an empty base has
+        // no bytes at all, and takes a pre insert at spot 0 like it does in `patches_from_diff`
+        let way = if spot < self.base.len() || self.base.is_empty() {
+            Direction::Pre
+        } else {
+            Direction::Post
+        };
+
+        Ok(AssuoPatch::Insert {
+            way,
+            spot,
+            source: AssuoSource::Bytes(bytes),
+        })
+    }
+
+    /// A removal of the whole line.
+    fn remove(&self, line: usize) -> std::io::Result<AssuoPatch> {
+        let start = self.offset(line)?;
+        let end = self.offset(line + 1)?;
+        let count = end - start;
+
+        // a pre removal removes right up to the byte at the spot, and a post removal starts right after it
+        if end < self.base.len() {
+            Ok(AssuoPatch::Remove {
+                way: Direction::Pre,
+                spot: end,
+                count,
+            })
+        } else if start > 0 {
+            Ok(AssuoPatch::Remove {
+                way: Direction::Post,
+                spot: start - 1,
+                count,
+            })
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                "can't remove the whole base",
+            ))
+        }
+    }
+}
+
+/// Works out the patches that turn `old` into `new`, byte by byte. Every run of bytes that changed becomes an insert of
+/// what it changed to, followed by a removal of what it was, so spots of every patch refer to `old` like any other
+/// patches would. The patches are as few bytes as it gets, but working them out takes longer the more the two differ.
+///
+/// # Example
+/// ```
+/// use assuo::diff::patches_from_diff;
+///
+/// assert_eq!(patches_from_diff(b"Hello!", b"Hello, World!").len(), 1)
+/// ```
+pub fn patches_from_diff(old: &[u8], new: &[u8]) -> Vec<AssuoPatch> {
+    // whatever the two start and end with doesn't need diffing
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+
+    let mut patches = Vec::new();
+    for hunk in hunks(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    ) {
+        let spot = prefix + hunk.start;
+
+        // inserting before the bytes that get removed keeps the spot of the insert from being removed first. the very
+        // end of `old` has no byte after it though, so that gets a post insert instead
+        if !hunk.inserted.is_empty() {
+            let way = if spot < old.len() || old.is_empty() {
+                Direction::Pre
+            } else {
+                Direction::Post
+            };
+
+            let source = match String::from_utf8(hunk.inserted) {
+                Ok(text) => AssuoSource::Text(text),
+                Err(error) => AssuoSource::Bytes(error.into_bytes()),
+            };
+
+            patches.push(AssuoPatch::Insert { way, spot, source });
+        }
+
+        if hunk.removed > 0 {
+            patches.push(AssuoPatch::Remove {
+                way: Direction::At,
+                spot,
+                count: hunk.removed,
+            });
+        }
+    }
+
+    patches
+}
+
+/// A run of bytes that changed, which is `removed` bytes of the old version from `start`, and `inserted` in the new
+/// one.
+struct Hunk {
+    start: usize,
+    removed: usize,
+    inserted: Vec<u8>,
+}
+
+/// How many edits [`hunks`] looks for before giving up. Every round is kept to walk back through, so the memory that
+/// takes grows with the square of this, which is around eight megabytes here.
+const MAX_EDITS: isize = 1024;
+
+/// Finds the fewest bytes to remove from and insert into `old` to make `new` with Myers' algorithm, and groups them
+/// into hunks. Every round `d` of it finds how far along `old` each diagonal `k` gets with `d` edits, and then the
+/// rounds are walked back from the end to find which edits those were.
+///
+/// Files that need more than [`MAX_EDITS`] edits are too different for that to be worth it, so all of `old` is
+/// replaced with all of `new` in one hunk.
+fn hunks(old: &[u8], new: &[u8]) -> Vec<Hunk> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    // no diagonal is further out than the most edits looked for, so `v` only needs to be as big as that, however big
+    // the files are
+    let bound = (n + m).min(MAX_EDITS);
+
+    // diagonal `k` is at `v[k + bound + 1]`, so the diagonals on either side of it are always there too
+    let mut v = vec![0; 2 * bound as usize + 3];
+    let at = |k: isize| (k + bound + 1) as usize;
+
+    // what `v` was after every round, for the diagonals that round could reach
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let mut reached_end = false;
+
+    'rounds: for d in 0..=bound {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                v[at(k + 1)]
+            } else {
+                v[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v[at(k)] = x;
+
+            if x >= n && y >= m {
+                trace.push(v[at(-d)..=at(d)].to_vec());
+                reached_end = true;
+                break 'rounds;
+            }
+        }
+
+        trace.push(v[at(-d)..=at(d)].to_vec());
+    }
+
+    if !reached_end {
+        return vec![Hunk {
+            start: 0,
+            removed: old.len(),
+            inserted: new.to_vec(),
+        }];
+    }
+
+    // walking back gives the edits from last to first, as where in `old` they are and the byte that's inserted, if any
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let before = &trace[d as usize - 1];
+        let reached = |k: isize| before[(k + d - 1) as usize];
+
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && reached(k - 1) < reached(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = reached(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+        }
+
+        if x == prev_x {
+            edits.push((prev_x as usize, Some(new[prev_y as usize])));
+        } else {
+            edits.push((prev_x as usize, None));
+        }
+
+        x = prev_x;
+        y = prev_y;
+    }
+
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for (position, inserted) in edits.into_iter().rev() {
+        let hunk = match hunks.last_mut() {
+            Some(hunk) if hunk.start + hunk.removed == position => hunk,
+            _ => {
+                hunks.push(Hunk {
+                    start: position,
+                    removed: 0,
+                    inserted: Vec::new(),
+                });
+                hunks.last_mut().unwrap()
+            }
+        };
+
+        match inserted {
+            Some(byte) => hunk.inserted.push(byte),
+            None => hunk.removed += 1,
+        }
+    }
+
+    hunks
+}
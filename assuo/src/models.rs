@@ -1,12 +1,28 @@
 //! This module holds the data structures used when deserializing an Assuo patch file.
 
+use std::collections::HashMap;
 use std::io::ErrorKind;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use serde::de::Error;
-use serde::Deserialize;
+use serde::ser::{SerializeMap, SerializeStruct};
+use serde::{Deserialize, Serialize, Serializer};
 use toml::Value;
 
+use crate::patch::{HttpTrace, PatchOptions, Warning};
+
+/// The Assuo patch file `assuo --init` starts you off with, which patches `Hello!` into `Hello, World!`.
+pub const DEFAULT_TEMPLATE: &str = r#"[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { text = ", World" }
+"#;
+
 /// Tries to deserialize a string to an AssuoFile
 ///
 /// # Example
@@ -21,18 +37,478 @@ use toml::Value;
 /// assert!(try_parse(source).is_ok())
 /// ```
 pub fn try_parse(payload: &str) -> Result<AssuoFile, toml::de::Error> {
-    toml::from_str(payload)
+    from_value(toml::from_str::<Value>(payload)?, None)
+}
+
+/// What an Assuo patch file can be written in. JSON and YAML files have the same layout as TOML ones, so
+/// `{ "source": { "text": "Hello!" } }` is the same as the TOML `[source]` table with `text = "Hello!"` in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Format::Toml => "TOML",
+            Format::Json => "JSON",
+            Format::Yaml => "YAML",
+        })
+    }
+}
+
+/// Tries to deserialize a string written in the given format to an AssuoFile.
+pub fn try_parse_as(payload: &str, format: Format) -> std::io::Result<AssuoFile> {
+    parse_as(payload, format, None)
+}
+
+/// Tries to deserialize a string to an AssuoFile, in whichever format it's written in. TOML is tried first, then
+/// JSON, then YAML, and the first one to give a valid AssuoFile is used. If none do, the error says why each failed.
+///
+/// Since JSON is also YAML, trying JSON before YAML means JSON files get JSON's errors. A TOML file can't be valid
+/// JSON, nor the other way around, since a JSON file has to start with `{` for it to be an AssuoFile.
+///
+/// # Example
+/// ```
+/// use assuo::models::try_parse_any;
+///
+/// assert!(try_parse_any(r#"{ "source": { "text": "Hello, World!" } }"#).is_ok())
+/// ```
+pub fn try_parse_any(payload: &str) -> std::io::Result<AssuoFile> {
+    parse_any(payload, None)
+}
+
+/// Tries to deserialize a string to an AssuoFile like [`try_parse_as`] does, or like [`try_parse_any`] does if no
+/// format is given. If a profile is given, its overrides in the `[profiles]` table are merged on top of the rest of
+/// the file first: its `source` replaces the file's, the patches labeled in its `disable` are left out, and its own
+/// patches are applied after the file's.
+///
+/// # Example
+/// ```
+/// use assuo::models::{try_parse_in_profile, AssuoSource};
+///
+/// let source = r#"
+/// [source]
+/// text = "Hello, dev!"
+///
+/// [profiles.prod]
+/// source = { text = "Hello, prod!" }
+/// "#;
+///
+/// let file = try_parse_in_profile(source, None, Some("prod")).unwrap();
+/// assert!(matches!(file.source, AssuoSource::Text(text) if text == "Hello, prod!"))
+/// ```
+pub fn try_parse_in_profile(
+    payload: &str,
+    format: Option<Format>,
+    profile: Option<&str>,
+) -> std::io::Result<AssuoFile> {
+    match format {
+        Some(format) => parse_as(payload, format, profile),
+        None => parse_any(payload, profile),
+    }
+}
+
+fn parse_as(payload: &str, format: Format, profile: Option<&str>) -> std::io::Result<AssuoFile> {
+    let file = match format {
+        Format::Toml => toml::from_str::<Value>(payload)
+            .and_then(|value| from_value(value, profile))
+            .map_err(|error| error.to_string()),
+        // everything is read into the same kind of value TOML is, so that it all gets checked the same way
+        Format::Json => serde_json::from_str::<Value>(payload)
+            .map_err(|error| error.to_string())
+            .and_then(|value| from_value(value, profile).map_err(|error| error.to_string())),
+        Format::Yaml => serde_yaml::from_str::<Value>(payload)
+            .map_err(|error| error.to_string())
+            .and_then(|value| from_value(value, profile).map_err(|error| error.to_string())),
+    };
+
+    file.map_err(|error| std::io::Error::new(ErrorKind::InvalidData, error))
+}
+
+fn parse_any(payload: &str, profile: Option<&str>) -> std::io::Result<AssuoFile> {
+    let mut errors = Vec::new();
+
+    for format in &[Format::Toml, Format::Json, Format::Yaml] {
+        match parse_as(payload, *format, profile) {
+            Ok(file) => return Ok(file),
+            Err(error) => errors.push(format!("as {}: {}", format, error)),
+        }
+    }
+
+    Err(std::io::Error::new(
+        ErrorKind::InvalidData,
+        format!(
+            "couldn't parse the config in any format\n{}",
+            errors.join("\n")
+        ),
+    ))
+}
+
+/// Turns an already parsed Assuo patch file into an AssuoFile, with the overrides of the given profile merged in.
+fn from_value(mut value: Value, profile: Option<&str>) -> Result<AssuoFile, toml::de::Error> {
+    apply_profile(&mut value, profile).map_err(toml::de::Error::custom)?;
+    apply_default_way(&mut value).map_err(toml::de::Error::custom)?;
+    value.try_into()
+}
+
+/// Merges the overrides of the named profile into the file. The `[profiles]` table is taken out whether a profile
+/// is named or not, so that none of it is mistaken for the file itself.
+fn apply_profile(value: &mut Value, profile: Option<&str>) -> Result<(), String> {
+    let table = match value {
+        Value::Table(table) => table,
+        _ => return Ok(()),
+    };
+
+    let mut profiles = match table.remove("profiles") {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => {
+            return Err(String::from(
+                "expected [profiles] to be a table of profiles",
+            ))
+        }
+        None => toml::value::Table::new(),
+    };
+
+    let name = match profile {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    let mut overrides = match profiles.remove(name) {
+        Some(Value::Table(overrides)) => overrides,
+        Some(_) => return Err(format!("expected [profiles.{}] to be a table", name)),
+        None => return Err(format!("there's no profile named '{}'", name)),
+    };
+
+    if let Some(source) = overrides.remove("source") {
+        table.insert(String::from("source"), source);
+    }
+
+    if let Some(hash) = overrides.remove("expect_output_sha256") {
+        table.insert(String::from("expect_output_sha256"), hash);
+    }
+
+    if let Some(disable) = overrides.remove("disable") {
+        let labels = match disable {
+            Value::Array(labels) => labels,
+            _ => {
+                return Err(format!(
+                    "expected 'disable' in [profiles.{}] to be an array of labels",
+                    name
+                ))
+            }
+        };
+
+        let mut patches = match table.get_mut("patch") {
+            Some(Value::Array(patches)) => Some(patches),
+            _ => None,
+        };
+
+        for label in labels {
+            let label = match label {
+                Value::String(label) => label,
+                _ => {
+                    return Err(format!(
+                        "expected 'disable' in [profiles.{}] to be an array of labels",
+                        name
+                    ))
+                }
+            };
+
+            let is_labeled =
+                |patch: &Value| patch.get("label").and_then(Value::as_str) == Some(label.as_str());
+            match patches.as_mut() {
+                Some(patches) if patches.iter().any(is_labeled) => {
+                    patches.retain(|patch| !is_labeled(patch))
+                }
+                _ => {
+                    return Err(format!(
+                        "profile '{}' disables '{}', but no patch has that label",
+                        name, label
+                    ))
+                }
+            }
+        }
+    }
+
+    match overrides.remove("patch") {
+        Some(Value::Array(extra)) => match table
+            .entry(String::from("patch"))
+            .or_insert_with(|| Value::Array(Vec::new()))
+        {
+            Value::Array(patches) => patches.extend(extra),
+            _ => return Err(String::from("expected [[patch]] to be an array of tables")),
+        },
+        Some(_) => {
+            return Err(format!(
+                "expected [[profiles.{}.patch]] to be an array of tables",
+                name
+            ))
+        }
+        None => {}
+    }
+
+    // anything else would otherwise be quietly ignored, which would make a typo look like it did nothing
+    if let Some(key) = overrides.keys().next() {
+        return Err(format!("profiles can't override '{}'", key));
+    }
+
+    Ok(())
+}
+
+/// Gives every patch that doesn't have a `way` the `default_way` of the file, if it has one.
+fn apply_default_way(value: &mut Value) -> Result<(), &'static str> {
+    let table = match value {
+        Value::Table(table) => table,
+        _ => return Ok(()),
+    };
+
+    let default_way = match table.remove("default_way") {
+        Some(Value::String(way)) if way == "pre" || way == "post" => Value::String(way),
+        Some(_) => return Err("didn't get 'pre' or 'post' for 'default_way'"),
+        None => return Ok(()),
+    };
+
+    if let Some(Value::Array(patches)) = table.get_mut("patch") {
+        for patch in patches {
+            if let Value::Table(patch) = patch {
+                patch.entry("way").or_insert_with(|| default_way.clone());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Tries to deserialize a string to an AssuoFile, like [`try_parse`]. Rather than stopping at the first problem
+/// with the source or a patch, this keeps going and reports every problem it can find.
+///
+/// # Example
+/// ```
+/// use assuo::models::try_parse_collecting;
+///
+/// let source = r#"
+/// [source]
+/// txet = "Hello, World!"
+///
+/// [[patch]]
+/// do = "insert"
+/// way = "post"
+/// source = { text = "!" }
+/// "#;
+///
+/// assert_eq!(try_parse_collecting(source).unwrap_err().len(), 2)
+/// ```
+pub fn try_parse_collecting(payload: &str) -> Result<AssuoFile, Vec<String>> {
+    // the `toml::Value` deserializer is used purely so that errors come out as `toml::de::Error`s
+    let mut value = match toml::from_str::<Value>(payload) {
+        Ok(value) => value,
+        Err(error) => return Err(vec![error.to_string()]),
+    };
+
+    // `toml::Value` doesn't know where anything came from, so where the fields of patches are is read separately
+    let spans = toml::from_str::<PatchSpans>(payload)
+        .map(|spans| spans.patch)
+        .unwrap_or_default();
+
+    let mut errors = Vec::new();
+
+    if let Err(error) = apply_profile(&mut value, None) {
+        errors.push(error);
+    }
+
+    if let Err(error) = apply_default_way(&mut value) {
+        errors.push(String::from(error));
+    }
+
+    let mut table = match value {
+        Value::Table(table) => table,
+        _ => return Err(vec![String::from("didn't get a table as payload")]),
+    };
+
+    let source = match table.remove("source") {
+        Some(source) => match AssuoSource::deserialize_toml::<Value>(source) {
+            Ok(source) => Some(source),
+            Err(error) => {
+                errors.push(format!("source: {}", error));
+                None
+            }
+        },
+        None => Some(AssuoSource::default()),
+    };
+
+    let patch = match table.remove("patch") {
+        Some(Value::Array(array)) => {
+            let mut patches = Vec::with_capacity(array.len());
+            for (index, patch) in array.into_iter().enumerate() {
+                match AssuoPatch::deserialize_toml_collecting::<Value>(patch) {
+                    Ok(patch) => patches.push(patch),
+                    Err(patch_errors) => {
+                        errors.extend(patch_errors.into_iter().map(|(field, error)| {
+                            match spans.get(index).and_then(|spans| span_of(spans, field)) {
+                                Some(start) => format!(
+                                    "patch #{}: {} at {}",
+                                    index,
+                                    error,
+                                    line_and_column(payload, start)
+                                ),
+                                None => format!("patch #{}: {}", index, error),
+                            }
+                        }))
+                    }
+                }
+            }
+            Some(patches)
+        }
+        Some(_) => {
+            errors.push(String::from("expected [[patch]] to be an array of tables"));
+            None
+        }
+        None => None,
+    };
+
+    let expect_output_sha256 = match table.remove("expect_output_sha256") {
+        Some(Value::String(hash)) => Some(hash),
+        Some(_) => {
+            errors.push(String::from("expected expect_output_sha256 to be a string"));
+            None
+        }
+        None => None,
+    };
+
+    let footer = match table.remove("footer") {
+        Some(footer) => match Footer::deserialize(footer) {
+            Ok(footer) => Some(footer),
+            Err(error) => {
+                errors.push(format!("footer: {}", error));
+                None
+            }
+        },
+        None => None,
+    };
+
+    match source {
+        Some(source) if errors.is_empty() => Ok(AssuoFile {
+            source,
+            patch,
+            expect_output_sha256,
+            footer,
+        }),
+        _ => Err(errors),
+    }
+}
+
+/// Where every field of every patch starts in a TOML payload, by the name of the field.
+#[derive(Deserialize)]
+struct PatchSpans {
+    #[serde(default)]
+    patch: Vec<HashMap<String, toml::Spanned<Value>>>,
+}
+
+/// Where a field of a patch starts. A field that isn't there is pointed at by where the patch itself starts, which is
+/// where its first field is.
+fn span_of(spans: &HashMap<String, toml::Spanned<Value>>, field: &str) -> Option<usize> {
+    match spans.get(field) {
+        Some(span) => Some(span.start()),
+        None => spans.values().map(toml::Spanned::start).min(),
+    }
+}
+
+/// Describes a byte offset into a payload the same way TOML errors do, like `line 3 column 7`.
+fn line_and_column(payload: &str, offset: usize) -> String {
+    let before = &payload[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    format!("line {} column {}", line, column)
+}
+
+/// Serializes an AssuoFile back into an Assuo patch file, such that it can be read again with [`try_parse`].
+///
+/// # Example
+/// ```
+/// use assuo::models::{to_toml, try_parse};
+///
+/// let source = r#"
+/// [source]
+/// text = "Hello, World!"
+/// "#;
+///
+/// let payload = to_toml(&try_parse(source).unwrap()).unwrap();
+/// assert!(try_parse(&payload).is_ok())
+/// ```
+pub fn to_toml(file: &AssuoFile) -> Result<String, toml::ser::Error> {
+    toml::to_string(file)
+}
+
+/// Combines several Assuo patch files into one, with the patches of every file one after another. Since every spot
+/// refers to the source, the files all have to have the same `[source]`, which the combined file keeps. The same goes
+/// for the footer, since the output can only end in one.
+pub fn merge(files: &[AssuoFile]) -> std::io::Result<AssuoFile> {
+    let (first, rest) = files
+        .split_first()
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "there's nothing to merge"))?;
+
+    let mut patches = first.patch.clone().unwrap_or_default();
+    for (index, file) in rest.iter().enumerate() {
+        if file.source != first.source {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "file #{} has a different [source] than the first file",
+                    index + 1
+                ),
+            ));
+        }
+
+        if file.footer != first.footer {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "file #{} has a different footer than the first file",
+                    index + 1
+                ),
+            ));
+        }
+
+        patches.extend(file.patch.iter().flatten().cloned());
+    }
+
+    // the combined file patches differently than any one of them, so none of their outputs can be expected of it, but
+    // it still ends in the footer they all have
+    Ok(AssuoFile {
+        source: first.source.clone(),
+        patch: Some(patches),
+        expect_output_sha256: None,
+        footer: first.footer,
+    })
 }
 
-/// Represents an Assuo patch file. Every Assuo patch file has a primary source that it is based off of,
-/// and a series of patches that it needs to apply to the source.
-#[derive(Debug, Deserialize)]
+/// Represents an Assuo patch file. Every Assuo patch file has a primary source that it is based off of (which is
+/// nothing at all, if it's left out), and a series of patches that it needs to apply to the source.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AssuoFile<S = AssuoSource> {
+    // NOTE: this comes before `source` as TOML can't have a plain value after a table.
+    /// The SHA-256 the patched output should have, in hex. Patching errors if the output has any other, which makes
+    /// a patch file tamper-evident, and catches sources that drifted into giving a different output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect_output_sha256: Option<String>,
+
+    /// A checksum of the patched bytes to append to the output, after every patch has been applied. The output
+    /// `expect_output_sha256` is checked against includes it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub footer: Option<Footer>,
+
     /// The primary source of this Assuo File. All Assuo modifications are based off of this copy.
     /// All `spot` values correlate directly to the offset (in bytes) of the original file, and patches
     /// will be applied in the order they are listed in, in the method described.
     ///
     /// This enforces the idea that if you want to modify your modifications, you have to create a new base.
+    ///
+    /// Files that leave it out start from no bytes at all, for files that make everything with their patches.
+    #[serde(default)]
     pub source: S,
 
     /// A list of patches to apply. Each patch is applied sequentially, and all `spot` values correlate directly to
@@ -42,324 +518,2808 @@ pub struct AssuoFile<S = AssuoSource> {
     pub patch: Option<Vec<AssuoPatch>>,
 }
 
-/// Represents some kind of value Assuo knows how to deal with as a source. Each value can be deciphered into
-/// a series of bytes, of which Assuo knows how to insert into the original source.
+impl AssuoFile {
+    /// Resolves only the base source, and checks every patch's `spot` (and `count`, for removals) against the bounds
+    /// of it. Patch sources are never resolved, so this has none of the side effects of a full run, and reports
+    /// every problem at once rather than stopping at the first one.
+    ///
+    /// Every spot refers to the original source, so these are exactly the bounds `do_patch` holds patches to. The base
+    /// is resolved with the given options, so it's held to the same rules (like [`PatchOptions::allowed_sources`]).
+    pub async fn lint_bounds(&self, options: &PatchOptions) -> std::io::Result<Vec<BoundsFinding>> {
+        let source = self.source.clone().resolve_with(options).await?;
+        let len = source.len();
+        let mut findings = Vec::new();
+
+        for (index, patch) in self.patch.iter().flatten().enumerate() {
+            let label = patch.label().map(String::from);
+
+            // patches for other targets are skipped, so they can't go out of bounds
+            let patch = match patch {
+                AssuoPatch::Gated { cfg, patch } if cfg.matches() => &**patch,
+                AssuoPatch::Gated { .. } => continue,
+                patch => patch,
+            };
+
+            // spots measured in characters or from the end are checked as the bytes from the start they stand for,
+            // and ones that are found are checked where they're found
+            let in_bytes;
+            let patch = match patch.unlabeled() {
+                AssuoPatch::Found { .. } | AssuoPatch::Matched { .. } => {
+                    let what = crate::patch::describe(index, label.as_deref());
+                    match crate::patch::locate(patch.clone(), &source, options, &what).await {
+                        Ok(patch) => {
+                            in_bytes = patch;
+                            in_bytes.unlabeled()
+                        }
+                        Err(error) => {
+                            findings.push(BoundsFinding {
+                                patch: index,
+                                label,
+                                reason: error.to_string(),
+                            });
+                            continue;
+                        }
+                    }
+                }
+                AssuoPatch::InChars(_) | AssuoPatch::FromEnd(_) => {
+                    match crate::patch::in_bytes(patch.clone(), &source) {
+                        Ok(patch) => {
+                            in_bytes = patch;
+                            &in_bytes
+                        }
+                        Err(error) => {
+                            findings.push(BoundsFinding {
+                                patch: index,
+                                label,
+                                reason: error.to_string(),
+                            });
+                            continue;
+                        }
+                    }
+                }
+                patch => patch,
+            };
+
+            let reason = match patch {
+                // empty data (like the base of a file without a source) takes inserts at spot 0, either way
+                AssuoPatch::Insert { spot: 0, .. } if len == 0 => None,
+                AssuoPatch::Insert {
+                    way: Direction::Post,
+                    spot,
+                    ..
+                } if *spot == 0 || *spot > len => Some(format!(
+                    "post insert at spot {} needs a byte before it, but the source is {} bytes long",
+                    spot, len
+                )),
+                AssuoPatch::Insert {
+                    way: Direction::Pre,
+                    spot,
+                    ..
+                } if *spot >= len => Some(format!(
+                    "pre insert at spot {} needs a byte after it, but the source is {} bytes long",
+                    spot, len
+                )),
+                // removing nothing doesn't touch anything, wherever it is
+                AssuoPatch::Remove { count: 0, .. } => None,
+                AssuoPatch::Remove { spot, .. } | AssuoPatch::RemoveUntil { spot, .. }
+                    if *spot >= len =>
+                {
+                    Some(format!(
+                        "removal at spot {} is past the end of the source, which is {} bytes long",
+                        spot, len
+                    ))
+                }
+                AssuoPatch::Remove {
+                    way: Direction::Post,
+                    spot,
+                    count,
+                } if *count >= len - spot => Some(format!(
+                    "post removal of {} bytes after spot {} goes past the end of the source, which is {} bytes long",
+                    count, spot, len
+                )),
+                AssuoPatch::Remove {
+                    way: Direction::Pre,
+                    spot,
+                    count,
+                } if count > spot => Some(format!(
+                    "pre removal of {} bytes before spot {} goes past the start of the source",
+                    count, spot
+                )),
+                AssuoPatch::Remove {
+                    way: Direction::At,
+                    spot,
+                    count,
+                } if *count > len - spot => Some(format!(
+                    "removal of {} bytes at spot {} goes past the end of the source, which is {} bytes long",
+                    count, spot, len
+                )),
+                AssuoPatch::Capture { spot, count, .. }
+                    if !matches!(spot.checked_add(*count), Some(end) if end <= len) =>
+                {
+                    Some(format!(
+                        "capture of {} bytes at spot {} goes past the end of the source, which is {} bytes long",
+                        count, spot, len
+                    ))
+                }
+                _ => None,
+            };
+
+            if let Some(reason) = reason {
+                findings.push(BoundsFinding {
+                    patch: index,
+                    label,
+                    reason,
+                });
+            }
+        }
+
+        Ok(findings)
+    }
+
+    /// Parses every URL in the base and in every patch, including URLs nested in other sources, and reports every
+    /// one that isn't valid. Nothing is resolved, so this never touches the network. Relative URLs are checked against
+    /// [`PatchOptions::base_url`], the same as they would be when resolving.
+    pub fn check_urls(&self, options: &PatchOptions) -> Vec<UrlFinding> {
+        let base = std::iter::once((None, &self.source));
+        let patches = self
+            .patch
+            .iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(index, patch)| patch.source().map(|source| (Some(index), source)));
+
+        let mut findings = Vec::new();
+        for (patch, source) in base.chain(patches) {
+            for url in source.urls() {
+                let parsed = match &options.base_url {
+                    Some(base) => reqwest::Url::parse(base).and_then(|base| base.join(url)),
+                    None => reqwest::Url::parse(url),
+                };
+
+                if let Err(error) = parsed {
+                    findings.push(UrlFinding {
+                        patch,
+                        url: url.to_owned(),
+                        reason: error.to_string(),
+                    });
+                }
+            }
+        }
+
+        findings
+    }
+}
+
+/// A URL that [`AssuoFile::check_urls`] found to be invalid.
+#[derive(Debug)]
+pub struct UrlFinding {
+    /// The index of the patch the URL is in, in the order they are listed in, or `None` if it's in the base.
+    pub patch: Option<usize>,
+    /// The URL, as it was written.
+    pub url: String,
+    /// Why the URL is invalid.
+    pub reason: String,
+}
+
+impl std::fmt::Display for UrlFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.patch {
+            Some(patch) => write!(f, "patch #{}: ", patch)?,
+            None => write!(f, "source: ")?,
+        }
+        write!(f, "invalid url '{}': {}", self.url, self.reason)
+    }
+}
+
+/// A patch that [`AssuoFile::lint_bounds`] found to be out of the bounds of the source.
 #[derive(Debug)]
+pub struct BoundsFinding {
+    /// The index of the offending patch, in the order they are listed in.
+    pub patch: usize,
+    /// The label of the offending patch, if it has one.
+    pub label: Option<String>,
+    /// Why the patch is out of bounds.
+    pub reason: String,
+}
+
+impl std::fmt::Display for BoundsFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            crate::patch::describe(self.patch, self.label.as_deref()),
+            self.reason
+        )
+    }
+}
+
+/// Represents some kind of value Assuo knows how to deal with as a source. Each value can be deciphered into
+/// a series of bytes, of which Assuo knows how to insert into the original source. The default is no bytes at all.
+#[derive(Debug, Clone, PartialEq)]
 pub enum AssuoSource {
     /// A raw amount of bytes. Not recommended to use for performance reasons, but you can if you want to.
+    /// When deserializing, strings in the array are mixed in as their UTF-8 bytes.
     Bytes(Vec<u8>),
     /// Some text. Plain and simple.
     Text(String),
-    /// Reads a file on disk at the given path, and will read the file to inject it.
-    File(String),
-    /// Fetches data at a given URL, and will use the payload to inject it.
-    Url(String),
+    /// Reads a file on disk at the given path, and will read the file to inject it. If a size is expected, the file
+    /// must be exactly that many bytes long, which catches inputs that silently changed. If `strip_bom` is set, a
+    /// leading UTF-8 or UTF-16 BOM is left out. If there's a range (an offset and a length), only that many bytes
+    /// from that offset are read, rather than the whole file.
+    File {
+        path: String,
+        expect_size: Option<u64>,
+        strip_bom: bool,
+        range: Option<(u64, u64)>,
+    },
+    /// Fetches data at a given URL, and will use the payload to inject it. If `strip_bom` is set, a leading UTF-8 or
+    /// UTF-16 BOM is left out. The policy says how long fetching can take, and how many times it's tried again.
+    Url {
+        url: String,
+        strip_bom: bool,
+        policy: FetchPolicy,
+    },
+    /// Resolves the body source, and POSTs it to the given URL with the given headers. The response body is used
+    /// to inject it. Responses without a 2xx status are an error. The policy says how long fetching can take, and
+    /// how many times it's tried again.
+    Post {
+        url: String,
+        body: Box<AssuoSource>,
+        headers: HashMap<String, String>,
+        policy: FetchPolicy,
+    },
+    /// Fetches a file from another machine over SSH, given like `user@host:/path/to/file`, and will use it to inject
+    /// it. Logging in goes through the SSH agent, and then the usual keys in `~/.ssh`. This needs assuo to be built
+    /// with the `ssh` feature.
+    Scp(String),
     /// Reads an Assuo patch file from disk, and after applying that Assuo patch file, uses the resultant data as part
     /// of the modification.
     AssuoFile(String),
     /// Reads an Assuo patch file from the URL specified, and after applying that Assuo patch file, uses the resultant
     /// data as part of the modification.
     AssuoUrl(String),
-}
-
-/// Represents a single action of patching.
-#[derive(Debug)]
-pub enum AssuoPatch<S = AssuoSource> {
-    /// Inserts data at a spot. This entails which direction to insert it in, the spot in the original file to start
-    /// inserting data at, and the source to resolve for the bytes to insert.
-    Insert {
-        way: Direction,
-        spot: usize,
-        source: S,
+    /// Reads a file on disk at the given path, which holds nothing but a `[source]`, and resolves that source. Unlike
+    /// [`AssuoSource::AssuoFile`], no patches are applied.
+    Indirect(String),
+    /// The bytes taken by the `capture` patch of the given name. Captures are taken from the original source before
+    /// anything gets patched, so any patch in the same file can use them.
+    Captured(String),
+    /// The bytes of the original source from `start` up to (but not including) `end`, from before anything gets
+    /// patched. Only patches can use this, since it reads from the source they patch.
+    Base { start: usize, end: usize },
+    /// Opens the zip archive on disk at the given path, and will read the named entry within it to inject it.
+    ZipEntry { archive: String, entry: String },
+    /// Formats the current UTC time with the given strftime-style pattern. If `SOURCE_DATE_EPOCH` is set, that
+    /// time is used instead of the current time, for reproducible builds.
+    Now(String),
+    /// Resolves the inner source as UTF-8 text, and substitutes every `${VAR}` in it with the variable of the same
+    /// name. `${VAR:-default}` falls back to the default when the variable isn't given.
+    Template {
+        inner: Box<AssuoSource>,
+        vars: HashMap<String, String>,
     },
-    /// Removes data at a spot. This entails which direction to remove data in, the spot in the original file to start
-    /// removing data at, and the amount of data to remove.
-    Remove {
-        way: Direction,
-        spot: usize,
-        count: usize,
+    /// Resolves the inner source, and emits its length in bytes as an integer in the given encoding. Errors if the
+    /// length doesn't fit.
+    LenOf {
+        of: Box<AssuoSource>,
+        encoding: LenEncoding,
     },
+    /// Resolves the inner source, and XORs every byte of it with the key, repeating the key as needed. XORing with
+    /// the same key again gives back the original bytes.
+    Xor { of: Box<AssuoSource>, key: Vec<u8> },
+    /// Resolves the inner source, and pads the end of it with the `with` byte until its length is a multiple of `to`.
+    /// Sources that already are aren't padded at all.
+    Pad {
+        of: Box<AssuoSource>,
+        to: usize,
+        with: u8,
+    },
+    /// Resolves the inner source, and uses its bytes in reverse order.
+    Reverse(Box<AssuoSource>),
+    /// Resolves the inner source, and uses only the lines of it that are selected, one after another with their line
+    /// endings. `select` is a list of line numbers and ranges of them counting from 1, like `2-4,7`. Errors if a line
+    /// that's selected isn't there.
+    Lines {
+        of: Box<AssuoSource>,
+        select: String,
+    },
+    /// Resolves the inner source, and percent-encodes every byte of it other than letters, digits, and `-._~`, so
+    /// that it can go in a URL or form payload.
+    UrlEncode(Box<AssuoSource>),
+    /// Resolves the inner source, and decodes every percent-encoded byte in it. Errors if a `%` isn't followed by
+    /// two hex digits.
+    UrlDecode(Box<AssuoSource>),
+    /// Tries to resolve every source in order, and uses the first one that resolves. The sources after it aren't
+    /// resolved at all. If none of them do, the error of the last one is used.
+    FirstOf(Vec<AssuoSource>),
+    /// Resolves `then` if assuo is running where the condition holds, and `else_` otherwise. Only the branch that's
+    /// chosen is resolved.
+    If {
+        cond: Cfg,
+        then: Box<AssuoSource>,
+        else_: Box<AssuoSource>,
+    },
+    /// Resolves every source in order, and uses all of their bytes one after another. This is what an array of
+    /// sources (like `[[source]]`) deserializes to.
+    Concat(Vec<AssuoSource>),
+}
+
+impl Default for AssuoSource {
+    fn default() -> Self {
+        AssuoSource::Bytes(Vec::new())
+    }
+}
+
+impl AssuoSource {
+    /// The name of this kind of source, as written in an Assuo patch file.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AssuoSource::Bytes(_) => "bytes",
+            AssuoSource::Text(_) => "text",
+            AssuoSource::File { .. } => "file",
+            AssuoSource::Url { .. } => "url",
+            AssuoSource::Post { .. } => "post",
+            AssuoSource::Scp(_) => "scp",
+            AssuoSource::AssuoFile(_) => "assuo-file",
+            AssuoSource::AssuoUrl(_) => "assuo-url",
+            AssuoSource::ZipEntry { .. } => "zip",
+            AssuoSource::Now(_) => "now",
+            AssuoSource::Indirect(_) => "indirect",
+            AssuoSource::Captured(_) => "captured",
+            AssuoSource::Base { .. } => "base",
+            AssuoSource::Template { .. } => "template",
+            AssuoSource::LenOf { .. } => "len_of",
+            AssuoSource::Xor { .. } => "xor",
+            AssuoSource::Pad { .. } => "pad",
+            AssuoSource::Reverse(_) => "reverse",
+            AssuoSource::Lines { .. } => "lines",
+            AssuoSource::UrlEncode(_) => "urlencode",
+            AssuoSource::UrlDecode(_) => "urldecode",
+            AssuoSource::FirstOf(_) => "first_of",
+            AssuoSource::If { .. } => "if",
+            AssuoSource::Concat(_) => "concat",
+        }
+    }
+
+    /// Every URL this source would fetch from, including ones in sources nested in it. URLs inside of other Assuo
+    /// patch files aren't included, since those aren't known until they're fetched.
+    pub fn urls(&self) -> Vec<&str> {
+        match self {
+            AssuoSource::Url { url, .. } | AssuoSource::AssuoUrl(url) => vec![url],
+            AssuoSource::Post { url, body, .. } => {
+                let mut urls = vec![url.as_str()];
+                urls.extend(body.urls());
+                urls
+            }
+            AssuoSource::Template { inner: of, .. }
+            | AssuoSource::LenOf { of, .. }
+            | AssuoSource::Xor { of, .. }
+            | AssuoSource::Pad { of, .. }
+            | AssuoSource::Reverse(of)
+            | AssuoSource::Lines { of, .. }
+            | AssuoSource::UrlEncode(of)
+            | AssuoSource::UrlDecode(of) => of.urls(),
+            AssuoSource::FirstOf(sources) | AssuoSource::Concat(sources) => {
+                sources.iter().flat_map(AssuoSource::urls).collect()
+            }
+            AssuoSource::If { then, else_, .. } => {
+                let mut urls = then.urls();
+                urls.extend(else_.urls());
+                urls
+            }
+            AssuoSource::Bytes(_)
+            | AssuoSource::Text(_)
+            | AssuoSource::File { .. }
+            | AssuoSource::Scp(_)
+            | AssuoSource::AssuoFile(_)
+            | AssuoSource::ZipEntry { .. }
+            | AssuoSource::Now(_)
+            | AssuoSource::Indirect(_)
+            | AssuoSource::Captured(_)
+            | AssuoSource::Base { .. } => Vec::new(),
+        }
+    }
+}
+
+/// How [`AssuoSource::LenOf`] writes out a length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LenEncoding {
+    /// A single byte.
+    U8,
+    /// Two bytes, little endian.
+    U16Le,
+    /// Two bytes, big endian.
+    U16Be,
+    /// Four bytes, little endian.
+    U32Le,
+    /// Four bytes, big endian.
+    U32Be,
 }
 
-/// The direction a modification looks in.
-#[derive(Debug)]
-pub enum Direction {
-    /// Before a given spot. For insertions, this would insert data right before the spot. For removals, this would remove
-    /// a certain amount of bytes before the spot.
-    Pre,
-    /// After a given spot. For insertions, this would insert data right after the spot. For removals, this would remove
-    /// a certain amount of bytes after the spot.
-    Post,
+impl LenEncoding {
+    /// The name of this encoding, as written in an Assuo patch file.
+    pub fn name(self) -> &'static str {
+        match self {
+            LenEncoding::U8 => "u8",
+            LenEncoding::U16Le => "u16le",
+            LenEncoding::U16Be => "u16be",
+            LenEncoding::U32Le => "u32le",
+            LenEncoding::U32Be => "u32be",
+        }
+    }
+
+    /// Parses the name of an encoding, as written in an Assuo patch file.
+    pub fn from_name(name: &str) -> Option<LenEncoding> {
+        match name {
+            "u8" => Some(LenEncoding::U8),
+            "u16le" => Some(LenEncoding::U16Le),
+            "u16be" => Some(LenEncoding::U16Be),
+            "u32le" => Some(LenEncoding::U32Le),
+            "u32be" => Some(LenEncoding::U32Be),
+            _ => None,
+        }
+    }
+
+    /// Encodes the length, or returns `None` if it's too big for this encoding.
+    pub fn encode(self, len: usize) -> Option<Vec<u8>> {
+        use std::convert::TryFrom;
+
+        Some(match self {
+            LenEncoding::U8 => vec![u8::try_from(len).ok()?],
+            LenEncoding::U16Le => u16::try_from(len).ok()?.to_le_bytes().to_vec(),
+            LenEncoding::U16Be => u16::try_from(len).ok()?.to_be_bytes().to_vec(),
+            LenEncoding::U32Le => u32::try_from(len).ok()?.to_le_bytes().to_vec(),
+            LenEncoding::U32Be => u32::try_from(len).ok()?.to_be_bytes().to_vec(),
+        })
+    }
+}
+
+/// A checksum of the patched bytes, appended to the output once every patch has been applied. Formats that end in a
+/// checksum of what comes before it can have it filled in by Assuo, rather than kept up to date by hand.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Footer {
+    /// Appends the CRC-32 of the patched bytes (the one zip and PNG use), as 4 bytes.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub crc32: bool,
+    /// Appends the SHA-256 of the patched bytes, as 32 bytes. This comes after the CRC-32, if there's both.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub sha256: bool,
+    /// The order the bytes of the CRC-32 are written in, which is little endian if it's left out. A SHA-256 is always
+    /// written in the order it's printed in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endian: Option<Endian>,
+}
+
+impl Footer {
+    /// The footer for the patched bytes.
+    pub fn of(self, patched: &[u8]) -> Vec<u8> {
+        use sha2::{Digest, Sha256};
+
+        let mut footer = Vec::with_capacity(self.len());
+
+        if self.crc32 {
+            let crc = crc32fast::hash(patched);
+            footer.extend_from_slice(&match self.endian.unwrap_or(Endian::Little) {
+                Endian::Little => crc.to_le_bytes(),
+                Endian::Big => crc.to_be_bytes(),
+            });
+        }
+
+        if self.sha256 {
+            footer.extend_from_slice(&Sha256::digest(patched));
+        }
+
+        footer
+    }
+
+    /// How many bytes the footer is, whatever it's the footer of.
+    pub fn len(self) -> usize {
+        (self.crc32 as usize) * 4 + (self.sha256 as usize) * 32
+    }
+
+    /// Whether the footer doesn't append anything at all.
+    pub fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The order the bytes of a number are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Endian {
+    /// The least significant byte comes first.
+    Little,
+    /// The most significant byte comes first.
+    Big,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// How long fetching a URL can take, how many times it's tried again if it fails, and which redirects it follows.
+/// Whatever a source leaves out comes from [`PatchOptions::fetch_defaults`], and anything left out of both isn't
+/// limited (or retried) at all, other than following at most 10 redirects in a row.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FetchPolicy {
+    /// How long each try can take, from connecting to reading the last byte.
+    pub timeout: Option<Duration>,
+    /// How many more times fetching is tried after it fails.
+    pub retry: Option<u32>,
+    /// Which redirects are followed.
+    pub redirects: Option<Redirects>,
+}
+
+impl FetchPolicy {
+    /// This policy, with anything it leaves out taken from `defaults`.
+    pub fn or(self, defaults: FetchPolicy) -> FetchPolicy {
+        FetchPolicy {
+            timeout: self.timeout.or(defaults.timeout),
+            retry: self.retry.or(defaults.retry),
+            redirects: self.redirects.or(defaults.redirects),
+        }
+    }
+
+    /// Whether this policy leaves everything out.
+    fn is_empty(&self) -> bool {
+        *self == FetchPolicy::default()
+    }
+}
+
+/// Which redirects fetching a URL follows, so that a pinned URL can't quietly lead somewhere else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redirects {
+    /// No redirects are followed, and a response that redirects is an error.
+    None,
+    /// At most this many redirects in a row are followed.
+    Limit(usize),
+    /// Only redirects to the same host as the URL are followed, at most 10 of them in a row.
+    SameHost,
+}
+
+impl Redirects {
+    /// How reqwest is told to follow these redirects.
+    fn to_policy(self) -> reqwest::redirect::Policy {
+        match self {
+            Redirects::None => reqwest::redirect::Policy::none(),
+            // reqwest's own limit counts the URL that was asked for too, so it would follow one less than this
+            Redirects::Limit(limit) => reqwest::redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() > limit {
+                    attempt.error("too many redirects")
+                } else {
+                    attempt.follow()
+                }
+            }),
+            Redirects::SameHost => reqwest::redirect::Policy::custom(|attempt| {
+                let same_host = attempt.previous().first().map(reqwest::Url::host_str)
+                    == Some(attempt.url().host_str());
+
+                if !same_host {
+                    attempt.error("redirected to another host")
+                } else if attempt.previous().len() > 10 {
+                    attempt.error("too many redirects")
+                } else {
+                    attempt.follow()
+                }
+            }),
+        }
+    }
+
+    fn to_toml(self) -> Value {
+        match self {
+            Redirects::None => Value::String(String::from("none")),
+            Redirects::Limit(limit) => {
+                let mut table = toml::value::Table::new();
+                table.insert(String::from("limit"), Value::Integer(limit as i64));
+                Value::Table(table)
+            }
+            Redirects::SameHost => Value::String(String::from("same-host")),
+        }
+    }
+}
+
+/// Reads a duration written like `500ms`, `10s` or `2m`.
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let (amount, unit) = duration.split_at(duration.find(|c: char| !c.is_ascii_digit())?);
+    let amount = amount.parse().ok()?;
+
+    match unit {
+        "ms" => Some(Duration::from_millis(amount)),
+        "s" => Some(Duration::from_secs(amount)),
+        "m" => amount.checked_mul(60).map(Duration::from_secs),
+        _ => None,
+    }
+}
+
+/// Writes a duration the way [`parse_duration`] reads it.
+fn format_duration(duration: Duration) -> String {
+    match duration.as_millis() {
+        millis if millis % 1000 == 0 => format!("{}s", millis / 1000),
+        millis => format!("{}ms", millis),
+    }
+}
+
+/// Represents a single action of patching.
+#[derive(Debug, Clone)]
+pub enum AssuoPatch<S = AssuoSource> {
+    /// Inserts data at a spot. This entails which direction to insert it in, the spot in the original file to start
+    /// inserting data at, and the source to resolve for the bytes to insert.
+    Insert {
+        way: Direction,
+        spot: usize,
+        source: S,
+    },
+    /// Removes data at a spot. This entails which direction to remove data in, the spot in the original file to start
+    /// removing data at, and the amount of data to remove.
+    Remove {
+        way: Direction,
+        spot: usize,
+        count: usize,
+    },
+    /// Removes data from a spot up to (but not including) the next occurrence of some anchor bytes. A post removal
+    /// starts at the byte at the spot and searches forward, while a pre removal ends right before the spot and
+    /// searches backward. If the anchor can't be found, it's an error, unless `to_end` is set, in which case
+    /// everything up to the end (or start) of the data is removed.
+    RemoveUntil {
+        way: Direction,
+        spot: usize,
+        until: S,
+        to_end: bool,
+    },
+    /// Takes `count` bytes of the original source, starting at the byte at the spot, and names them so that a
+    /// `captured` source can use them. Nothing gets changed by this.
+    Capture {
+        name: String,
+        spot: usize,
+        count: usize,
+    },
+    /// The same as the patch inside, except that its `spot` (and `count`) are measured in UTF-8 characters of the
+    /// original source rather than bytes. The original source has to be valid UTF-8.
+    InChars(Box<AssuoPatch<S>>),
+    /// The same as the patch inside, except that its `spot` counts back from the end of the original source rather
+    /// than forward from the start, so that a spot of 0 is the very end. This is what a spot like `"end-3"` is read as.
+    FromEnd(Box<AssuoPatch<S>>),
+    /// The same as the patch inside, except that its spot is wherever `find` starts in the original source, which
+    /// has to be exactly once, so that the patch can't end up somewhere it wasn't meant to. This is what a spot like
+    /// `{ find = "marker" }` is read as.
+    Found { find: S, patch: Box<AssuoPatch<S>> },
+    /// The same as the patch inside, except that its spot is wherever the regex matches the original source, which it
+    /// has to exactly once. This is what a spot like `{ regex = "\\bfn \\w+" }` is read as, and it needs assuo to be
+    /// built with the `regex` feature.
+    Matched {
+        regex: String,
+        patch: Box<AssuoPatch<S>>,
+    },
+    /// The same as the patch inside, with a label that says what it's for. The label doesn't change what the patch
+    /// does, but it's named alongside the patch's index in errors and findings about it.
+    Labeled {
+        label: String,
+        patch: Box<AssuoPatch<S>>,
+    },
+    /// The same as the patch inside, except that it's skipped entirely unless assuo is running on the target the
+    /// `cfg` describes. A skipped patch doesn't resolve anything.
+    Gated { cfg: Cfg, patch: Box<AssuoPatch<S>> },
+}
+
+/// The target a patch is for, for patches that only apply on some, or the condition an `if` source checks. Anything
+/// left out matches every target.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Cfg {
+    /// The operating system, named like [`std::env::consts::OS`], such as `linux` or `windows`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+
+    /// The CPU architecture, named like [`std::env::consts::ARCH`], such as `x86_64` or `aarch64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+
+    /// The name of an environment variable that has to be set, such as `PROD`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+}
+
+impl Cfg {
+    /// Whether assuo is running on the target this describes.
+    pub fn matches(&self) -> bool {
+        let matches = |wanted: &Option<String>, actual: &str| match wanted {
+            Some(wanted) => wanted == actual,
+            None => true,
+        };
+
+        matches(&self.os, std::env::consts::OS)
+            && matches(&self.arch, std::env::consts::ARCH)
+            && match &self.env {
+                Some(name) => std::env::var_os(name).is_some(),
+                None => true,
+            }
+    }
+}
+
+impl<S> AssuoPatch<S> {
+    /// The source this patch resolves, if it has one.
+    pub(crate) fn source(&self) -> Option<&S> {
+        match self {
+            AssuoPatch::Insert { source, .. } => Some(source),
+            AssuoPatch::RemoveUntil { until, .. } => Some(until),
+            AssuoPatch::Remove { .. } | AssuoPatch::Capture { .. } => None,
+            AssuoPatch::InChars(patch)
+            | AssuoPatch::FromEnd(patch)
+            | AssuoPatch::Found { patch, .. }
+            | AssuoPatch::Matched { patch, .. }
+            | AssuoPatch::Labeled { patch, .. }
+            | AssuoPatch::Gated { patch, .. } => patch.source(),
+        }
+    }
+
+    /// The spot of this patch, however it's measured. Spots that are found don't have one until they've been found.
+    pub fn spot(&self) -> Option<usize> {
+        match self {
+            AssuoPatch::Insert { spot, .. }
+            | AssuoPatch::Remove { spot, .. }
+            | AssuoPatch::RemoveUntil { spot, .. }
+            | AssuoPatch::Capture { spot, .. } => Some(*spot),
+            AssuoPatch::Found { .. } | AssuoPatch::Matched { .. } => None,
+            AssuoPatch::InChars(patch)
+            | AssuoPatch::FromEnd(patch)
+            | AssuoPatch::Labeled { patch, .. }
+            | AssuoPatch::Gated { patch, .. } => patch.spot(),
+        }
+    }
+
+    /// The label of this patch, if it has one.
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            AssuoPatch::Labeled { label, .. } => Some(label),
+            AssuoPatch::InChars(patch)
+            | AssuoPatch::FromEnd(patch)
+            | AssuoPatch::Found { patch, .. }
+            | AssuoPatch::Matched { patch, .. }
+            | AssuoPatch::Gated { patch, .. } => patch.label(),
+            _ => None,
+        }
+    }
+
+    /// What this patch finds to work out its spot, if its spot is found that way.
+    pub fn find(&self) -> Option<&S> {
+        match self {
+            AssuoPatch::Found { find, .. } => Some(find),
+            AssuoPatch::InChars(patch)
+            | AssuoPatch::FromEnd(patch)
+            | AssuoPatch::Labeled { patch, .. } => patch.find(),
+            // patches that are skipped don't need to find anything
+            AssuoPatch::Gated { cfg, patch } if cfg.matches() => patch.find(),
+            _ => None,
+        }
+    }
+
+    /// The regex this patch matches to work out its spot, if its spot is found that way.
+    pub fn regex(&self) -> Option<&str> {
+        match self {
+            AssuoPatch::Matched { regex, .. } => Some(regex),
+            AssuoPatch::InChars(patch)
+            | AssuoPatch::FromEnd(patch)
+            | AssuoPatch::Labeled { patch, .. } => patch.regex(),
+            // patches that are skipped don't need to match anything
+            AssuoPatch::Gated { cfg, patch } if cfg.matches() => patch.regex(),
+            _ => None,
+        }
+    }
+
+    /// The same patch with every source swapped out for what `map` gives for it.
+    pub fn map_sources<T>(self, map: &mut impl FnMut(S) -> T) -> AssuoPatch<T> {
+        match self {
+            AssuoPatch::Insert { way, spot, source } => AssuoPatch::Insert {
+                way,
+                spot,
+                source: map(source),
+            },
+            AssuoPatch::Remove { way, spot, count } => AssuoPatch::Remove { way, spot, count },
+            AssuoPatch::RemoveUntil {
+                way,
+                spot,
+                until,
+                to_end,
+            } => AssuoPatch::RemoveUntil {
+                way,
+                spot,
+                until: map(until),
+                to_end,
+            },
+            AssuoPatch::Capture { name, spot, count } => AssuoPatch::Capture { name, spot, count },
+            AssuoPatch::InChars(patch) => AssuoPatch::InChars(Box::new(patch.map_sources(map))),
+            AssuoPatch::FromEnd(patch) => AssuoPatch::FromEnd(Box::new(patch.map_sources(map))),
+            AssuoPatch::Found { find, patch } => AssuoPatch::Found {
+                find: map(find),
+                patch: Box::new(patch.map_sources(map)),
+            },
+            AssuoPatch::Matched { regex, patch } => AssuoPatch::Matched {
+                regex,
+                patch: Box::new(patch.map_sources(map)),
+            },
+            AssuoPatch::Labeled { label, patch } => AssuoPatch::Labeled {
+                label,
+                patch: Box::new(patch.map_sources(map)),
+            },
+            AssuoPatch::Gated { cfg, patch } => AssuoPatch::Gated {
+                cfg,
+                patch: Box::new(patch.map_sources(map)),
+            },
+        }
+    }
+
+    /// This patch without its label, if it has one.
+    pub fn unlabeled(&self) -> &AssuoPatch<S> {
+        match self {
+            AssuoPatch::Labeled { patch, .. } => patch.unlabeled(),
+            patch => patch,
+        }
+    }
+}
+
+/// The direction a modification looks in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Before a given spot. For insertions, this would insert data right before the spot. For removals, this would remove
+    /// a certain amount of bytes before the spot.
+    Pre,
+    /// After a given spot. For insertions, this would insert data right after the spot. For removals, this would remove
+    /// a certain amount of bytes after the spot.
+    Post,
+    /// Right at a given spot. Only removals can go this way, and they remove a certain amount of bytes starting with
+    /// the byte at the spot.
+    At,
+}
+
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Direction::Pre => "pre",
+            Direction::Post => "post",
+            Direction::At => "at",
+        })
+    }
+}
+
+// some mildly ugly stuff
+
+/// When one trait needs to perform some kind of computation and resolve into another, this trait can be used.
+#[async_trait]
+pub trait Resolvable<R> {
+    /// Resolves with the default options.
+    async fn resolve(self) -> std::io::Result<R>
+    where
+        Self: Sized + Send,
+    {
+        self.resolve_with(&PatchOptions::default()).await
+    }
+
+    /// Resolves with the given options, which are passed along to anything resolved as part of this.
+    async fn resolve_with(self, options: &PatchOptions) -> std::io::Result<R>;
+}
+
+#[async_trait]
+impl Resolvable<Vec<u8>> for AssuoSource {
+    async fn resolve_with(self, options: &PatchOptions) -> std::io::Result<Vec<u8>> {
+        // a list of sources is only what's in it, so only the sources in it go into the manifest
+        let manifest = match &options.manifest {
+            Some(manifest) if !matches!(self, AssuoSource::Concat(_)) => {
+                Some((manifest, self.kind(), serde_json::to_value(&self)?))
+            }
+            _ => None,
+        };
+
+        let bytes = self.resolve_unrecorded(options).await?;
+
+        if let Some((manifest, kind, spec)) = manifest {
+            manifest.record(kind, spec, &bytes);
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl AssuoSource {
+    /// Resolves the source, without recording it into the manifest.
+    async fn resolve_unrecorded(self, options: &PatchOptions) -> std::io::Result<Vec<u8>> {
+        fn err(kind: ErrorKind, reason: &'static str) -> std::io::Error {
+            std::io::Error::new(kind, reason)
+        }
+
+        // a list of sources isn't a kind of its own, so it's up to the sources in it
+        let is_concat = matches!(self, AssuoSource::Concat(_));
+
+        if !is_concat && !options.allows(self.kind()) {
+            return Err(std::io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("source kind '{}' isn't allowed", self.kind()),
+            ));
+        }
+
+        // TODO: clean this up
+        match self {
+            AssuoSource::Bytes(bytes) => Ok(bytes),
+            AssuoSource::Text(string) => Ok(string.into_bytes()),
+            AssuoSource::File {
+                path,
+                expect_size,
+                strip_bom,
+                range,
+            } => {
+                let (bytes, file_size) = match range {
+                    // only the range gets read, so that a few bytes can be taken out of a huge file cheaply
+                    Some((offset, length)) => {
+                        use std::io::{Read, Seek, SeekFrom};
+
+                        let mut file = std::fs::File::open(&path)?;
+                        let file_size = file.metadata()?.len();
+
+                        match offset.checked_add(length) {
+                            Some(end) if end <= file_size => {}
+                            _ => {
+                                return Err(std::io::Error::new(
+                                    ErrorKind::InvalidInput,
+                                    format!(
+                                        "the {} bytes at offset {} go past the end of '{}', which is {} bytes",
+                                        length, offset, path, file_size
+                                    ),
+                                ))
+                            }
+                        }
+
+                        file.seek(SeekFrom::Start(offset))?;
+                        let mut bytes = vec![0; length as usize];
+                        file.read_exact(&mut bytes)?;
+                        (bytes, file_size)
+                    }
+                    None => {
+                        let bytes = std::fs::read_to_string(&path)
+                            .and_then(|string| Ok(string.into_bytes()))?;
+                        let file_size = bytes.len() as u64;
+                        (bytes, file_size)
+                    }
+                };
+
+                // the expected size is the size of the file, BOM and all
+                match expect_size {
+                    Some(size) if size != file_size => Err(std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "expected '{}' to be {} bytes, but it's {} bytes",
+                            path, size, file_size
+                        ),
+                    )),
+                    _ if strip_bom => Ok(without_bom(bytes, &path, options)),
+                    _ => Ok(bytes),
+                }
+            }
+            AssuoSource::Url {
+                url,
+                strip_bom,
+                policy,
+            } => {
+                // data URIs carry their data with them, so there's nothing to fetch
+                let bytes = if let Some(uri) = url.strip_prefix("data:") {
+                    decode_data_uri(uri)?
+                } else if let Some(bytes) = mock_response(&url, options) {
+                    bytes
+                } else {
+                    let request = reqwest::Client::new().get(parse_url(&url, options)?);
+                    fetch(request, "GET", &HashMap::new(), policy, options).await?
+                };
+
+                if strip_bom {
+                    Ok(without_bom(bytes, &url, options))
+                } else {
+                    Ok(bytes)
+                }
+            }
+            AssuoSource::Post {
+                url,
+                body,
+                headers,
+                policy,
+            } => {
+                let url = parse_url(&url, options)?;
+                let body = body.resolve_with(options).await?;
+
+                let mut request = reqwest::Client::new().post(url).body(body);
+                for (name, value) in &headers {
+                    request = request.header(name.as_str(), value.as_str());
+                }
+
+                fetch(request, "POST", &headers, policy, options).await
+            }
+            AssuoSource::AssuoFile(file_path) => {
+                let payload = match std::fs::read_to_string(file_path)
+                    .and_then(|string| Ok(string.into_bytes()))
+                    .and_then(|bytes| {
+                        String::from_utf8(bytes).map_err(|_| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                "couldnt parse bytes into string",
+                            )
+                        })
+                    })
+                    .and_then(|payload| {
+                        try_parse(&payload).map_err(|_| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                "couldnt parse asuo config file",
+                            )
+                        })
+                    }) {
+                    Ok(payload) => payload,
+                    Err(error) => return Err(error),
+                };
+
+                patch_nested(payload, options).await
+            }
+            AssuoSource::AssuoUrl(url) => {
+                let mocked = mock_response(&url, options);
+                let url = parse_url(&url, options)?;
+
+                let bytes = match mocked {
+                    Some(bytes) => bytes,
+                    None => {
+                        let request = reqwest::Client::new().get(url.clone());
+                        fetch(
+                            request,
+                            "GET",
+                            &HashMap::new(),
+                            FetchPolicy::default(),
+                            options,
+                        )
+                        .await?
+                    }
+                };
+
+                let payload = match String::from_utf8(bytes)
+                    .map_err(|_| err(ErrorKind::InvalidData, "invalid string"))
+                    .and_then(|string| Ok(string.into_bytes()))
+                    .and_then(|bytes| {
+                        String::from_utf8(bytes).map_err(|_| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                "couldnt parse bytes into string",
+                            )
+                        })
+                    })
+                    .and_then(|payload| {
+                        try_parse(&payload).map_err(|_| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                "couldnt parse asuo config file",
+                            )
+                        })
+                    }) {
+                    Ok(payload) => payload,
+                    Err(error) => return Err(error),
+                };
+
+                // relative urls in the file are relative to where it was fetched from
+                let options = PatchOptions {
+                    base_url: Some(url.to_string()),
+                    ..options.clone()
+                };
+
+                patch_nested(payload, &options).await
+            }
+            AssuoSource::ZipEntry { archive, entry } => {
+                let archive = std::fs::File::open(archive)?;
+                let mut archive = zip::ZipArchive::new(archive)
+                    .map_err(|_| err(ErrorKind::InvalidData, "couldn't read zip archive"))?;
+
+                let mut entry = match archive.by_name(&entry) {
+                    Ok(entry) => entry,
+                    Err(zip::result::ZipError::FileNotFound) => {
+                        return Err(err(ErrorKind::NotFound, "entry not found in zip archive"))
+                    }
+                    Err(_) => return Err(err(ErrorKind::InvalidData, "couldn't read zip entry")),
+                };
+
+                let mut bytes = Vec::with_capacity(entry.size() as usize);
+                std::io::Read::read_to_end(&mut entry, &mut bytes)?;
+                Ok(bytes)
+            }
+            AssuoSource::Indirect(path) => {
+                // an indirect source that leads back to itself would never finish resolving
+                let canonical = std::fs::canonicalize(&path)?;
+                if options.indirect_files.contains(&canonical) {
+                    return Err(std::io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("indirect source '{}' leads back to itself", path),
+                    ));
+                }
+
+                let payload = std::fs::read_to_string(&canonical)?;
+                let source = match toml::from_str::<Value>(&payload) {
+                    Ok(Value::Table(mut table)) => table.remove("source"),
+                    _ => None,
+                };
+
+                let source = source
+                    .ok_or_else(|| {
+                        err(
+                            ErrorKind::InvalidData,
+                            "expected [source] in indirect source file",
+                        )
+                    })
+                    .and_then(|source| {
+                        AssuoSource::deserialize_toml::<Value>(source).map_err(|error| {
+                            std::io::Error::new(
+                                ErrorKind::InvalidData,
+                                format!("couldn't parse indirect source file: {}", error),
+                            )
+                        })
+                    })?;
+
+                let mut options = options.clone();
+                options.indirect_files.push(canonical);
+                source.resolve_with(&options).await
+            }
+            #[cfg(feature = "ssh")]
+            // ssh2 blocks, which would hold up every other source being fetched at the same time
+            AssuoSource::Scp(target) => tokio::task::spawn_blocking(move || scp(&target))
+                .await
+                .unwrap_or_else(|error| std::panic::resume_unwind(error.into_panic())),
+            #[cfg(not(feature = "ssh"))]
+            AssuoSource::Scp(_) => Err(err(
+                ErrorKind::Other,
+                "scp sources need assuo to be built with the `ssh` feature",
+            )),
+            AssuoSource::Captured(name) => options.captures.get(&name).cloned().ok_or_else(|| {
+                std::io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("nothing was captured as '{}'", name),
+                )
+            }),
+            AssuoSource::Base { start, end } => {
+                let base = options.base.as_ref().ok_or_else(|| {
+                    err(
+                        ErrorKind::InvalidInput,
+                        "base sources can only be used in patches, since they read from the source being patched",
+                    )
+                })?;
+
+                match base.get(start..end) {
+                    Some(bytes) if start <= end => Ok(bytes.to_vec()),
+                    _ => Err(std::io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "base range {}..{} isn't within the source, which is {} bytes long",
+                            start,
+                            end,
+                            base.len()
+                        ),
+                    )),
+                }
+            }
+            // the time is different every run, so it's replayed from the lock when there is one
+            AssuoSource::Now(format) => match &options.lock {
+                Some(lock) => lock.replay_or(format!("now = {:?}", format), || now(&format)),
+                None => now(&format),
+            },
+            AssuoSource::Template { inner, vars } => {
+                let text = String::from_utf8(inner.resolve_with(options).await?)
+                    .map_err(|_| err(ErrorKind::InvalidData, "template wasn't valid UTF-8"))?;
+
+                Ok(substitute(&text, &vars)?.into_bytes())
+            }
+            AssuoSource::LenOf { of, encoding } => {
+                let len = of.resolve_with(options).await?.len();
+
+                encoding.encode(len).ok_or_else(|| {
+                    std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("length {} doesn't fit in a {}", len, encoding.name()),
+                    )
+                })
+            }
+            AssuoSource::Xor { of, key } => {
+                if key.is_empty() {
+                    return Err(err(ErrorKind::InvalidInput, "xor key can't be empty"));
+                }
+
+                let mut bytes = of.resolve_with(options).await?;
+                for (byte, key) in bytes.iter_mut().zip(key.iter().cycle()) {
+                    *byte ^= key;
+                }
+
+                Ok(bytes)
+            }
+            AssuoSource::Pad { of, to, with } => {
+                if to == 0 {
+                    return Err(err(ErrorKind::InvalidInput, "can't pad to a multiple of 0"));
+                }
+
+                let mut bytes = of.resolve_with(options).await?;
+                let missing = (to - bytes.len() % to) % to;
+                bytes.resize(bytes.len() + missing, with);
+                Ok(bytes)
+            }
+            AssuoSource::Reverse(of) => {
+                let mut bytes = of.resolve_with(options).await?;
+                bytes.reverse();
+                Ok(bytes)
+            }
+            AssuoSource::Lines { of, select } => {
+                let ranges = line_ranges(&select)?;
+                let bytes = of.resolve_with(options).await?;
+
+                // every line keeps its line ending, and the last one doesn't need one
+                let mut lines = Vec::new();
+                let mut start = 0;
+                for (index, byte) in bytes.iter().enumerate() {
+                    if *byte == b'\n' {
+                        lines.push(&bytes[start..=index]);
+                        start = index + 1;
+                    }
+                }
+                if start < bytes.len() {
+                    lines.push(&bytes[start..]);
+                }
+
+                let mut selected = Vec::new();
+                for (first, last) in ranges {
+                    let range = lines.get(first - 1..last).ok_or_else(|| {
+                        std::io::Error::new(
+                            ErrorKind::InvalidInput,
+                            format!(
+                                "can't select line {}, since there are only {} lines",
+                                first.max(lines.len() + 1),
+                                lines.len()
+                            ),
+                        )
+                    })?;
+                    selected.extend(range.iter().copied().flatten());
+                }
+
+                Ok(selected)
+            }
+            AssuoSource::UrlEncode(of) => {
+                let bytes = of.resolve_with(options).await?;
+                Ok(percent_encoding::percent_encode(&bytes, URL_ENCODE_SET)
+                    .to_string()
+                    .into_bytes())
+            }
+            AssuoSource::UrlDecode(of) => url_decode(&of.resolve_with(options).await?),
+            AssuoSource::FirstOf(sources) => {
+                let mut last_error = err(ErrorKind::InvalidInput, "first_of needs a source");
+                for (index, source) in sources.into_iter().enumerate() {
+                    match source.resolve_with(options).await {
+                        Ok(bytes) => {
+                            if index > 0 {
+                                options.warnings.push(Warning::FellBack { to: index });
+                            }
+                            return Ok(bytes);
+                        }
+                        Err(error) => last_error = error,
+                    }
+                }
+
+                Err(last_error)
+            }
+            AssuoSource::If { cond, then, else_ } => {
+                if cond.matches() {
+                    then.resolve_with(options).await
+                } else {
+                    else_.resolve_with(options).await
+                }
+            }
+            AssuoSource::Concat(sources) => {
+                let mut bytes = Vec::new();
+                for source in sources {
+                    bytes.extend(source.resolve_with(options).await?);
+                }
+
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+/// Reads which lines a `lines` source selects, like `2-4,7`, as the first and last line of every range of them.
+fn line_ranges(select: &str) -> std::io::Result<Vec<(usize, usize)>> {
+    let invalid = || {
+        std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "expected lines to select like '2-4,7', counting from 1, but got '{}'",
+                select
+            ),
+        )
+    };
+
+    let line = |line: &str| match line.trim().parse::<usize>() {
+        Ok(line) if line > 0 => Ok(line),
+        _ => Err(invalid()),
+    };
+
+    select
+        .split(',')
+        .map(|range| {
+            let mut bounds = range.splitn(2, '-');
+            let first = line(bounds.next().unwrap_or_default())?;
+            let last = match bounds.next() {
+                Some(last) => line(last)?,
+                None => first,
+            };
+
+            if last < first {
+                return Err(invalid());
+            }
+
+            Ok((first, last))
+        })
+        .collect()
+}
+
+/// Every byte that `urlencode` encodes, which is all of them but the unreserved characters of RFC 3986.
+const URL_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Decodes every percent-encoded byte, erroring on a `%` that isn't followed by two hex digits.
+fn url_decode(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    for (index, _) in bytes.iter().enumerate().filter(|(_, byte)| **byte == b'%') {
+        let escape = &bytes[index + 1..bytes.len().min(index + 3)];
+        if escape.len() != 2 || !escape.iter().all(u8::is_ascii_hexdigit) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "invalid percent-encoding at byte {}: expected 2 hex digits after the '%'",
+                    index
+                ),
+            ));
+        }
+    }
+
+    Ok(percent_encoding::percent_decode(bytes).collect())
+}
+
+/// What the URL responds with, if it's mocked in the options. Without the `testing` feature, nothing is.
+#[allow(unused_variables)]
+fn mock_response(url: &str, options: &PatchOptions) -> Option<Vec<u8>> {
+    #[cfg(feature = "testing")]
+    return options.mock_responses.get(url);
+
+    #[cfg(not(feature = "testing"))]
+    None
+}
+
+/// Parses a URL, resolving it against [`PatchOptions::base_url`] if there is one. URLs that aren't relative are the
+/// same either way.
+fn parse_url(url: &str, options: &PatchOptions) -> std::io::Result<reqwest::Url> {
+    let parsed = match &options.base_url {
+        Some(base) => reqwest::Url::parse(base).and_then(|base| base.join(url)),
+        None => reqwest::Url::parse(url),
+    };
+
+    parsed.map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "the url was invalid"))
+}
+
+/// Decodes a data URI (RFC 2397), given everything after the `data:` scheme, like `text/plain;base64,SGVsbG8=`.
+fn decode_data_uri(uri: &str) -> std::io::Result<Vec<u8>> {
+    let invalid = |reason: &str| {
+        std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid data uri: {}", reason),
+        )
+    };
+
+    let comma = uri
+        .find(',')
+        .ok_or_else(|| invalid("expected a ',' before the data"))?;
+    let (media_type, data) = (&uri[..comma], &uri[comma + 1..]);
+
+    let (media_type, is_base64) = match media_type.strip_suffix(";base64") {
+        Some(media_type) => (media_type, true),
+        None => (media_type, false),
+    };
+
+    // the media type doesn't change how the data is read, but it should still be one
+    let mime = media_type.split(';').next().unwrap_or_default();
+    if !mime.is_empty() && mime.split('/').filter(|part| !part.is_empty()).count() != 2 {
+        return Err(invalid(
+            "expected the media type to look like 'type/subtype'",
+        ));
+    }
+
+    let data = percent_encoding::percent_decode_str(data).collect::<Vec<u8>>();
+
+    if is_base64 {
+        base64::decode(&data).map_err(|error| invalid(&error.to_string()))
+    } else {
+        Ok(data)
+    }
+}
+
+/// Substitutes every `${VAR}` and `${VAR:-default}` in the template with its variable.
+fn substitute(template: &str, vars: &HashMap<String, String>) -> std::io::Result<String> {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+
+        let end = rest[start..].find('}').ok_or_else(|| {
+            std::io::Error::new(ErrorKind::InvalidData, "unterminated '${' in template")
+        })?;
+        let token = &rest[start + 2..start + end];
+
+        let (name, default) = match token.find(":-") {
+            Some(split) => (&token[..split], Some(&token[split + 2..])),
+            None => (token, None),
+        };
+
+        match vars.get(name).map(String::as_str).or(default) {
+            Some(value) => result.push_str(value),
+            None => {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("unknown variable '{}' in template", name),
+                ))
+            }
+        }
+
+        rest = &rest[start + end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[async_trait]
+impl Resolvable<AssuoFile<Vec<u8>>> for AssuoFile {
+    // impl<S: Send + Resolvable<Vec<u8>>> Resolvable<AssuoFile<Vec<u8>>> for AssuoFile<S> {
+    async fn resolve_with(self, options: &PatchOptions) -> std::io::Result<AssuoFile<Vec<u8>>> {
+        let resolved_source = self.source.resolve_with(options).await?;
+
+        Ok(AssuoFile {
+            source: resolved_source,
+            patch: self.patch,
+            expect_output_sha256: self.expect_output_sha256,
+            footer: self.footer,
+        })
+    }
+}
+
+#[async_trait]
+impl Resolvable<AssuoPatch<Vec<u8>>> for AssuoPatch {
+    // impl<S: Send + Resolvable<Vec<u8>>> Resolvable<AssuoPatch<Vec<u8>>> for AssuoPatch<S> {
+    async fn resolve_with(self, options: &PatchOptions) -> std::io::Result<AssuoPatch<Vec<u8>>> {
+        Ok(match self {
+            AssuoPatch::Insert { way, spot, source } => {
+                let source = source.resolve_with(options).await?;
+                AssuoPatch::<Vec<u8>>::Insert { way, spot, source }
+            }
+            AssuoPatch::Remove { way, spot, count } => {
+                AssuoPatch::<Vec<u8>>::Remove { way, spot, count }
+            }
+            AssuoPatch::RemoveUntil {
+                way,
+                spot,
+                until,
+                to_end,
+            } => {
+                let until = until.resolve_with(options).await?;
+                AssuoPatch::<Vec<u8>>::RemoveUntil {
+                    way,
+                    spot,
+                    until,
+                    to_end,
+                }
+            }
+            AssuoPatch::Capture { name, spot, count } => {
+                AssuoPatch::<Vec<u8>>::Capture { name, spot, count }
+            }
+            AssuoPatch::InChars(patch) => {
+                AssuoPatch::<Vec<u8>>::InChars(Box::new(patch.resolve_with(options).await?))
+            }
+            AssuoPatch::FromEnd(patch) => {
+                AssuoPatch::<Vec<u8>>::FromEnd(Box::new(patch.resolve_with(options).await?))
+            }
+            AssuoPatch::Found { find, patch } => AssuoPatch::<Vec<u8>>::Found {
+                find: find.resolve_with(options).await?,
+                patch: Box::new(patch.resolve_with(options).await?),
+            },
+            AssuoPatch::Matched { regex, patch } => AssuoPatch::<Vec<u8>>::Matched {
+                regex,
+                patch: Box::new(patch.resolve_with(options).await?),
+            },
+            AssuoPatch::Labeled { label, patch } => AssuoPatch::<Vec<u8>>::Labeled {
+                label,
+                patch: Box::new(patch.resolve_with(options).await?),
+            },
+            // a patch that gets skipped shouldn't go fetching anything
+            AssuoPatch::Gated { cfg, patch } if !cfg.matches() => AssuoPatch::<Vec<u8>>::Gated {
+                cfg,
+                patch: Box::new(patch.map_sources(&mut |_| Vec::new())),
+            },
+            AssuoPatch::Gated { cfg, patch } => AssuoPatch::<Vec<u8>>::Gated {
+                cfg,
+                patch: Box::new(patch.resolve_with(options).await?),
+            },
+        })
+    }
+}
+
+// == serialization, in the same shape we deserialize from ==
+
+impl Serialize for AssuoSource {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if let AssuoSource::Concat(sources) = self {
+            return sources.serialize(serializer);
+        }
+
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            AssuoSource::Bytes(bytes) => map.serialize_entry("bytes", bytes)?,
+            AssuoSource::Text(string) => map.serialize_entry("text", string)?,
+            AssuoSource::File {
+                path,
+                expect_size,
+                strip_bom,
+                range,
+            } => {
+                map.serialize_entry("file", path)?;
+                if let Some(size) = expect_size {
+                    map.serialize_entry("expect_size", size)?;
+                }
+                if *strip_bom {
+                    map.serialize_entry("strip_bom", strip_bom)?;
+                }
+                if let Some((offset, length)) = range {
+                    map.serialize_entry("offset", offset)?;
+                    map.serialize_entry("length", length)?;
+                }
+            }
+            AssuoSource::Url {
+                url,
+                strip_bom,
+                policy,
+            } => {
+                map.serialize_entry("url", url)?;
+                if *strip_bom {
+                    map.serialize_entry("strip_bom", strip_bom)?;
+                }
+                if let Some(timeout) = policy.timeout {
+                    map.serialize_entry("timeout", &format_duration(timeout))?;
+                }
+                if let Some(retry) = policy.retry {
+                    map.serialize_entry("retry", &retry)?;
+                }
+                if let Some(redirects) = policy.redirects {
+                    map.serialize_entry("redirects", &redirects.to_toml())?;
+                }
+            }
+            AssuoSource::Post {
+                url,
+                body,
+                headers,
+                policy,
+            } => {
+                #[derive(Serialize)]
+                struct Post<'a> {
+                    url: &'a str,
+                    #[serde(skip_serializing_if = "Option::is_none")]
+                    timeout: Option<String>,
+                    #[serde(skip_serializing_if = "Option::is_none")]
+                    retry: Option<u32>,
+                    #[serde(skip_serializing_if = "Option::is_none")]
+                    redirects: Option<Value>,
+                    headers: &'a HashMap<String, String>,
+                    body: &'a AssuoSource,
+                }
+
+                map.serialize_entry(
+                    "post",
+                    &Post {
+                        url,
+                        timeout: policy.timeout.map(format_duration),
+                        retry: policy.retry,
+                        redirects: policy.redirects.map(Redirects::to_toml),
+                        headers,
+                        body,
+                    },
+                )?
+            }
+            AssuoSource::Scp(target) => map.serialize_entry("scp", target)?,
+            AssuoSource::AssuoFile(path) => map.serialize_entry("assuo-file", path)?,
+            AssuoSource::AssuoUrl(url) => map.serialize_entry("assuo-url", url)?,
+            AssuoSource::ZipEntry { archive, entry } => {
+                #[derive(Serialize)]
+                struct ZipEntry<'a> {
+                    archive: &'a str,
+                    entry: &'a str,
+                }
+
+                map.serialize_entry("zip", &ZipEntry { archive, entry })?
+            }
+            AssuoSource::Now(format) => map.serialize_entry("now", format)?,
+            AssuoSource::Indirect(path) => map.serialize_entry("indirect", path)?,
+            AssuoSource::Captured(name) => map.serialize_entry("captured", name)?,
+            AssuoSource::Base { start, end } => {
+                #[derive(Serialize)]
+                struct Base {
+                    start: usize,
+                    end: usize,
+                }
+
+                map.serialize_entry(
+                    "base",
+                    &Base {
+                        start: *start,
+                        end: *end,
+                    },
+                )?
+            }
+            AssuoSource::Template { inner, vars } => {
+                #[derive(Serialize)]
+                struct Template<'a> {
+                    #[serde(flatten)]
+                    inner: &'a AssuoSource,
+                    vars: &'a HashMap<String, String>,
+                }
+
+                map.serialize_entry("template", &Template { inner, vars })?
+            }
+            AssuoSource::LenOf { of, encoding } => {
+                // TOML wants plain values before tables
+                map.serialize_entry("as", encoding.name())?;
+                map.serialize_entry("len_of", of)?;
+            }
+            AssuoSource::Xor { of, key } => {
+                #[derive(Serialize)]
+                struct Xor<'a> {
+                    key: &'a [u8],
+                    of: &'a AssuoSource,
+                }
+
+                map.serialize_entry("xor", &Xor { key, of })?
+            }
+            AssuoSource::Pad { of, to, with } => {
+                #[derive(Serialize)]
+                struct Pad<'a> {
+                    to: usize,
+                    with: u8,
+                    of: &'a AssuoSource,
+                }
+
+                map.serialize_entry(
+                    "pad",
+                    &Pad {
+                        to: *to,
+                        with: *with,
+                        of,
+                    },
+                )?
+            }
+            AssuoSource::Reverse(of) => map.serialize_entry("reverse", of)?,
+            AssuoSource::Lines { of, select } => {
+                #[derive(Serialize)]
+                struct Lines<'a> {
+                    select: &'a str,
+                    of: &'a AssuoSource,
+                }
+
+                map.serialize_entry("lines", &Lines { select, of })?
+            }
+            AssuoSource::UrlEncode(of) => map.serialize_entry("urlencode", of)?,
+            AssuoSource::UrlDecode(of) => map.serialize_entry("urldecode", of)?,
+            AssuoSource::FirstOf(sources) => map.serialize_entry("first_of", sources)?,
+            AssuoSource::If { cond, then, else_ } => {
+                #[derive(Serialize)]
+                struct If<'a> {
+                    cond: &'a Cfg,
+                    then: &'a AssuoSource,
+                    #[serde(rename = "else")]
+                    else_: &'a AssuoSource,
+                }
+
+                map.serialize_entry("if", &If { cond, then, else_ })?
+            }
+            AssuoSource::Concat(_) => unreachable!("serialized as a sequence above"),
+        }
+        map.end()
+    }
+}
+
+impl<T: Serialize> Serialize for AssuoPatch<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut patch = serializer.serialize_struct("AssuoPatch", 6)?;
+        serialize_patch_fields(self, &mut patch, false, false)?;
+        patch.end()
+    }
+}
+
+/// Writes out every field of a patch. TOML wants plain values before tables, so the source always goes last. Spots
+/// are written like `end-3` when they're counted back `from_end`, and are left out when they're `found` (by what they
+/// find, or by a regex), since those are written as a table once everything else is.
+fn serialize_patch_fields<T: Serialize, S: SerializeStruct>(
+    patch: &AssuoPatch<T>,
+    fields: &mut S,
+    from_end: bool,
+    found: bool,
+) -> Result<(), S::Error> {
+    let spot_value = |spot: &usize| match (from_end, *spot) {
+        (false, spot) => Value::Integer(spot as i64),
+        (true, 0) => Value::String(String::from("end")),
+        (true, spot) => Value::String(format!("end-{}", spot)),
+    };
+
+    let spot_field = |fields: &mut S, spot: &usize| match found {
+        true => Ok(()),
+        false => fields.serialize_field("spot", &spot_value(spot)),
+    };
+
+    match patch {
+        AssuoPatch::Insert { way, spot, source } => {
+            fields.serialize_field("do", "insert")?;
+            fields.serialize_field("way", way)?;
+            spot_field(fields, spot)?;
+            fields.serialize_field("source", source)?;
+        }
+        AssuoPatch::Remove { way, spot, count } => {
+            fields.serialize_field("do", "remove")?;
+            fields.serialize_field("way", way)?;
+            spot_field(fields, spot)?;
+            fields.serialize_field("count", count)?;
+        }
+        AssuoPatch::RemoveUntil {
+            way,
+            spot,
+            until,
+            to_end,
+        } => {
+            fields.serialize_field("do", "remove")?;
+            fields.serialize_field("way", way)?;
+            spot_field(fields, spot)?;
+            if *to_end {
+                fields.serialize_field("to_end", to_end)?;
+            }
+            fields.serialize_field("until", until)?;
+        }
+        AssuoPatch::Capture { name, spot, count } => {
+            fields.serialize_field("do", "capture")?;
+            fields.serialize_field("name", name)?;
+            spot_field(fields, spot)?;
+            fields.serialize_field("count", count)?;
+        }
+        AssuoPatch::InChars(inner) => {
+            fields.serialize_field("unit", "char")?;
+            serialize_patch_fields(inner, fields, from_end, found)?;
+        }
+        AssuoPatch::FromEnd(inner) => serialize_patch_fields(inner, fields, true, found)?,
+        AssuoPatch::Found { find, patch } => {
+            #[derive(Serialize)]
+            struct Find<'a, T> {
+                find: &'a T,
+            }
+
+            serialize_patch_fields(patch, fields, from_end, true)?;
+            fields.serialize_field("spot", &Find { find })?;
+        }
+        AssuoPatch::Matched { regex, patch } => {
+            #[derive(Serialize)]
+            struct Regex<'a> {
+                regex: &'a str,
+            }
+
+            serialize_patch_fields(patch, fields, from_end, true)?;
+            fields.serialize_field("spot", &Regex { regex })?;
+        }
+        AssuoPatch::Labeled { label, patch } => {
+            fields.serialize_field("label", label)?;
+            serialize_patch_fields(patch, fields, from_end, found)?;
+        }
+        AssuoPatch::Gated { cfg, patch } => {
+            serialize_patch_fields(patch, fields, from_end, found)?;
+            fields.serialize_field("cfg", cfg)?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Serialize for Direction {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(match self {
+            Direction::Pre => "pre",
+            Direction::Post => "post",
+            Direction::At => "at",
+        })
+    }
+}
+
+// == ugly deserialization stuff below ==
+// todo: cleanup
+
+pub trait TomlDeserialize<'de>: Sized {
+    fn deserialize_toml<D>(value: Value) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>;
+}
+
+impl<'de, S: TomlDeserialize<'de>> Deserialize<'de> for AssuoPatch<S> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        AssuoPatch::deserialize_toml_collecting::<D>(value)
+            .map_err(|mut errors| errors.swap_remove(0).1)
+    }
+}
+
+impl<'de, S: TomlDeserialize<'de>> AssuoPatch<S> {
+    /// Deserializes a patch, checking every field even when an earlier one is wrong so that all of the problems
+    /// with it can be reported at once. On error, there is always at least one error in the `Vec`, each with the
+    /// name of the field it's about.
+    fn deserialize_toml_collecting<D>(value: Value) -> Result<Self, Vec<(&'static str, D::Error)>>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let mut table = match value {
+            Value::Table(table) => table,
+            _ => {
+                return Err(vec![(
+                    "patch",
+                    Error::custom("didn't get a table as payload"),
+                )])
+            }
+        };
+
+        let mut errors = Vec::new();
+
+        #[derive(PartialEq)]
+        enum Action {
+            Insert,
+            Remove,
+            Capture,
+        }
+
+        let action = match table.get("do") {
+            Some(Value::String(action)) => {
+                // uppercase because docs have it like this,
+                // TODO PERF: explore micro-optimization with branch prediction if it should be uppercase or lowercase
+                if action.eq_ignore_ascii_case("INSERT") {
+                    Some(Action::Insert)
+                } else if action.eq_ignore_ascii_case("REMOVE") {
+                    Some(Action::Remove)
+                } else if action.eq_ignore_ascii_case("CAPTURE") {
+                    Some(Action::Capture)
+                } else {
+                    errors.push((
+                        "do",
+                        Error::custom("expected either 'insert' or 'remove' or 'capture' for 'do'"),
+                    ));
+                    None
+                }
+            }
+            Some(_) => {
+                errors.push((
+                    "do",
+                    Error::custom("expected string for action 'do', didn't get that"),
+                ));
+                None
+            }
+            None => {
+                errors.push((
+                    "do",
+                    Error::custom("didn't get key 'do' with insert or remove"),
+                ));
+                None
+            }
+        };
+
+        // both insert and remove need 'way' and 'spot', but captures don't go in any direction
+        let way = match table.get("way") {
+            _ if action == Some(Action::Capture) => None,
+            Some(Value::String(way)) => match way.as_str() {
+                "pre" => Some(Direction::Pre),
+                "post" => Some(Direction::Post),
+                "at" => Some(Direction::At),
+                _ => {
+                    errors.push((
+                        "way",
+                        Error::custom("didn't get 'pre', 'post' or 'at' for 'way'"),
+                    ));
+                    None
+                }
+            },
+            Some(_) => {
+                errors.push(("way", Error::custom("didn't get string for way")));
+                None
+            }
+            None => {
+                errors.push(("way", Error::custom("didn't get 'way'")));
+                None
+            }
+        };
+
+        // only removals of a count of bytes have a start of their own, anything else goes before or after the spot
+        if way == Some(Direction::At)
+            && (action != Some(Action::Remove) || table.contains_key("until"))
+        {
+            errors.push((
+                "way",
+                Error::custom("only removals of a count can go 'at' a spot"),
+            ));
+        }
+
+        // spots can also be counted from the start or back from the end, like `start+2` or `end-3`, or be wherever
+        // something is found, like `{ find = "marker" }`, or wherever a regex matches, like `{ regex = "fn \\w+" }`
+        let mut from_end = false;
+        let mut found = None;
+        let mut matched = None;
+        let spot = match table.get("spot") {
+            Some(Value::Integer(value)) => Some(*value as usize),
+            Some(Value::Table(spot)) => match (spot.get("find"), spot.get("regex")) {
+                (Some(find), None) if spot.len() == 1 => {
+                    // a plain string is the text to find, like a `text` source
+                    let find = match find {
+                        Value::String(text) => {
+                            let mut source = toml::value::Table::new();
+                            source.insert(String::from("text"), Value::String(text.clone()));
+                            Value::Table(source)
+                        }
+                        find => find.clone(),
+                    };
+
+                    match S::deserialize_toml::<D>(find) {
+                        Ok(find) => {
+                            found = Some(find);
+                            Some(0)
+                        }
+                        Err(error) => {
+                            errors.push((
+                                "spot",
+                                Error::custom(format!(
+                                    "expected 'find' to be text or a source, but {}",
+                                    error
+                                )),
+                            ));
+                            None
+                        }
+                    }
+                }
+                (None, Some(Value::String(regex))) if spot.len() == 1 => {
+                    matched = Some(regex.clone());
+                    Some(0)
+                }
+                _ => {
+                    errors.push((
+                        "spot",
+                        Error::custom(
+                            "expected a spot table to only have a source to 'find', or a string 'regex'",
+                        ),
+                    ));
+                    None
+                }
+            },
+            Some(Value::String(expression)) => {
+                let expression = expression.replace(' ', "");
+                let parsed = match expression.as_str() {
+                    "start" => Some((false, 0)),
+                    "end" => Some((true, 0)),
+                    expression => expression
+                        .strip_prefix("start+")
+                        .map(|offset| (false, offset))
+                        .or_else(|| expression.strip_prefix("end-").map(|offset| (true, offset)))
+                        .and_then(|(end, offset)| offset.parse().ok().map(|offset| (end, offset))),
+                };
+
+                match parsed {
+                    Some((end, offset)) => {
+                        from_end = end;
+                        Some(offset)
+                    }
+                    None => {
+                        errors.push((
+                            "spot",
+                            Error::custom(
+                                "expected spot to be an integer, or like 'end', 'end-N' or 'start+N'",
+                            ),
+                        ));
+                        None
+                    }
+                }
+            }
+            Some(_) => {
+                errors.push(("spot", Error::custom("spot wasn't an integer")));
+                None
+            }
+            None => {
+                errors.push(("spot", Error::custom("didn't get 'spot'")));
+                None
+            }
+        };
+
+        let patch = match action {
+            Some(Action::Insert) => match table.remove("source") {
+                Some(source) => match S::deserialize_toml::<D>(source) {
+                    Ok(source) => way.zip(spot).map(|(way, spot)| AssuoPatch::<S>::Insert {
+                        way,
+                        spot,
+                        source,
+                    }),
+                    Err(error) => {
+                        errors.push(("source", error));
+                        None
+                    }
+                },
+                None => {
+                    errors.push((
+                        "source",
+                        Error::custom("expected source to be specified, it wasn't"),
+                    ));
+                    None
+                }
+            },
+            // removals either remove a `count` of bytes, or remove `until` some anchor
+            Some(Action::Remove) => match table.remove("until") {
+                Some(until) => {
+                    let to_end = match table.get("to_end") {
+                        Some(Value::Boolean(to_end)) => *to_end,
+                        Some(_) => {
+                            errors.push((
+                                "to_end",
+                                Error::custom("expected to_end to be boolean, it wasn't"),
+                            ));
+                            false
+                        }
+                        None => false,
+                    };
+
+                    match S::deserialize_toml::<D>(until) {
+                        Ok(until) => {
+                            way.zip(spot)
+                                .map(|(way, spot)| AssuoPatch::<S>::RemoveUntil {
+                                    way,
+                                    spot,
+                                    until,
+                                    to_end,
+                                })
+                        }
+                        Err(error) => {
+                            errors.push(("until", error));
+                            None
+                        }
+                    }
+                }
+                None => match table.get("count") {
+                    Some(Value::Integer(count)) => {
+                        way.zip(spot).map(|(way, spot)| AssuoPatch::<S>::Remove {
+                            way,
+                            spot,
+                            count: *count as usize,
+                        })
+                    }
+                    Some(_) => {
+                        errors.push((
+                            "count",
+                            Error::custom("expected count to be integer, it wasn't"),
+                        ));
+                        None
+                    }
+                    None => {
+                        errors.push((
+                            "count",
+                            Error::custom("expected count or until to be specified, neither were"),
+                        ));
+                        None
+                    }
+                },
+            },
+            Some(Action::Capture) => {
+                let name = match table.remove("name") {
+                    Some(Value::String(name)) => Some(name),
+                    Some(_) => {
+                        errors.push((
+                            "name",
+                            Error::custom("expected name to be string, it wasn't"),
+                        ));
+                        None
+                    }
+                    None => {
+                        errors.push((
+                            "name",
+                            Error::custom("expected name to be specified, it wasn't"),
+                        ));
+                        None
+                    }
+                };
+
+                let count = match table.get("count") {
+                    Some(Value::Integer(count)) => Some(*count as usize),
+                    Some(_) => {
+                        errors.push((
+                            "count",
+                            Error::custom("expected count to be integer, it wasn't"),
+                        ));
+                        None
+                    }
+                    None => {
+                        errors.push((
+                            "count",
+                            Error::custom("expected count to be specified, it wasn't"),
+                        ));
+                        None
+                    }
+                };
+
+                name.zip(spot)
+                    .zip(count)
+                    .map(|((name, spot), count)| AssuoPatch::<S>::Capture { name, spot, count })
+            }
+            None => None,
+        };
+
+        let in_chars = match table.get("unit") {
+            Some(Value::String(unit)) if unit == "char" => true,
+            Some(Value::String(unit)) if unit == "byte" => false,
+            None => false,
+            Some(_) => {
+                errors.push((
+                    "unit",
+                    Error::custom("expected either 'byte' or 'char' for 'unit'"),
+                ));
+                false
+            }
+        };
+
+        let patch = match patch {
+            Some(patch) if from_end => Some(AssuoPatch::FromEnd(Box::new(patch))),
+            patch => patch,
+        };
+
+        let patch = match (patch, found) {
+            (Some(_), Some(_)) if in_chars => {
+                errors.push((
+                    "unit",
+                    Error::custom(
+                        "spots found with 'find' are in bytes, so 'unit' can't be 'char'",
+                    ),
+                ));
+                None
+            }
+            (Some(patch), Some(find)) => Some(AssuoPatch::Found {
+                find,
+                patch: Box::new(patch),
+            }),
+            (patch, _) => patch,
+        };
+
+        let patch = match (patch, matched) {
+            (Some(_), Some(_)) if in_chars => {
+                errors.push((
+                    "unit",
+                    Error::custom(
+                        "spots found with a 'regex' are in bytes, so 'unit' can't be 'char'",
+                    ),
+                ));
+                None
+            }
+            (Some(patch), Some(regex)) => Some(AssuoPatch::Matched {
+                regex,
+                patch: Box::new(patch),
+            }),
+            (patch, _) => patch,
+        };
+
+        let patch = match patch {
+            Some(patch) if in_chars => Some(AssuoPatch::InChars(Box::new(patch))),
+            patch => patch,
+        };
+
+        let patch = match (patch, table.get("label")) {
+            (Some(patch), Some(Value::String(label))) => Some(AssuoPatch::Labeled {
+                label: label.clone(),
+                patch: Box::new(patch),
+            }),
+            (_, Some(Value::String(_))) => None,
+            (_, Some(_)) => {
+                errors.push((
+                    "label",
+                    Error::custom("expected label to be string, it wasn't"),
+                ));
+                None
+            }
+            (patch, None) => patch,
+        };
+
+        let patch = match (patch, table.get("cfg")) {
+            (Some(patch), Some(Value::Table(cfg))) => match cfg_from_toml(cfg) {
+                Ok(cfg) => Some(AssuoPatch::Gated {
+                    cfg,
+                    patch: Box::new(patch),
+                }),
+                Err(error) => {
+                    errors.push(("cfg", Error::custom(error)));
+                    None
+                }
+            },
+            (_, Some(Value::Table(_))) => None,
+            (_, Some(_)) => {
+                errors.push((
+                    "cfg",
+                    Error::custom("expected cfg to be a table, it wasn't"),
+                ));
+                None
+            }
+            (patch, None) => patch,
+        };
+
+        match patch {
+            Some(patch) if errors.is_empty() => Ok(patch),
+            _ => Err(errors),
+        }
+    }
+}
+
+/// Reads the target a patch is for, like `{ os = "windows", arch = "x86_64" }`, or the condition of an `if` source.
+fn cfg_from_toml(table: &toml::value::Table) -> Result<Cfg, &'static str> {
+    let mut cfg = Cfg::default();
+    for (key, value) in table {
+        let value = match value {
+            Value::String(value) => Some(value.clone()),
+            _ => return Err("expected the os, arch and env of cfg to be strings"),
+        };
+
+        match key.as_str() {
+            "os" => cfg.os = value,
+            "arch" => cfg.arch = value,
+            "env" => cfg.env = value,
+            _ => return Err("expected cfg to only have an 'os', an 'arch' and an 'env'"),
+        }
+    }
+
+    Ok(cfg)
+}
+
+/// Fetches a file over SSH, where the target is like `user@host:/path/to/file`, or `user@host:port:/path/to/file` for
+/// a port other than 22. Without a user, the current one is used. The host has to be in `~/.ssh/known_hosts` already,
+/// with the key it answers with.
+#[cfg(feature = "ssh")]
+fn scp(target: &str) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let invalid = || {
+        std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "expected scp source '{}' to be like user@host:/path",
+                target
+            ),
+        )
+    };
+
+    let mut parts = target.splitn(2, ':');
+    let login = parts.next().ok_or_else(invalid)?;
+    let rest = parts.next().ok_or_else(invalid)?;
+
+    let mut parts = rest.splitn(2, ':');
+    let (port, path) = match (parts.next().map(str::parse), parts.next()) {
+        (Some(Ok(port)), Some(path)) => (port, path),
+        _ => (22, rest),
+    };
+
+    if path.is_empty() {
+        return Err(invalid());
+    }
+
+    let (user, host) = match login.find('@') {
+        Some(at) => (String::from(&login[..at]), &login[at + 1..]),
+        None => (
+            std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .map_err(|_| invalid())?,
+            login,
+        ),
+    };
+
+    let tcp = std::net::TcpStream::connect((host, port)).map_err(|error| {
+        std::io::Error::new(
+            ErrorKind::NotConnected,
+            format!("couldn't connect to '{}': {}", host, error),
+        )
+    })?;
+
+    let mut session = ssh2::Session::new()?;
+    session.set_tcp_stream(tcp);
+    session.handshake().map_err(|error| {
+        std::io::Error::new(
+            ErrorKind::NotConnected,
+            format!("couldn't start an ssh session with '{}': {}", host, error),
+        )
+    })?;
+
+    let ssh = std::env::var_os("HOME")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_default()
+        .join(".ssh");
+
+    // logging in to a host that isn't the one it says it is would hand it our keys, and get whatever it wants back
+    let mut known_hosts = session.known_hosts()?;
+    let known_hosts_file = ssh.join("known_hosts");
+    if known_hosts_file.exists() {
+        known_hosts.read_file(&known_hosts_file, ssh2::KnownHostFileKind::OpenSSH)?;
+    }
+
+    let (key, _) = session.host_key().ok_or_else(|| {
+        std::io::Error::new(
+            ErrorKind::NotConnected,
+            format!("'{}' didn't send a host key", host),
+        )
+    })?;
+
+    match known_hosts.check_port(host, port, key) {
+        ssh2::CheckResult::Match => {}
+        ssh2::CheckResult::Mismatch => {
+            return Err(std::io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "the host key of '{}' doesn't match the one in ~/.ssh/known_hosts",
+                    host
+                ),
+            ))
+        }
+        ssh2::CheckResult::NotFound => {
+            return Err(std::io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "'{}' isn't in ~/.ssh/known_hosts, so its host key can't be checked",
+                    host
+                ),
+            ))
+        }
+        ssh2::CheckResult::Failure => {
+            return Err(std::io::Error::new(
+                ErrorKind::NotConnected,
+                format!("couldn't check the host key of '{}'", host),
+            ))
+        }
+    }
+
+    // the agent gets the first try, and then every usual key that exists
+    let _ = session.userauth_agent(&user);
+    if !session.authenticated() {
+        for key in &["id_ed25519", "id_ecdsa", "id_rsa"] {
+            let key = ssh.join(key);
+            if key.exists()
+                && session
+                    .userauth_pubkey_file(&user, None, &key, None)
+                    .is_ok()
+            {
+                break;
+            }
+        }
+    }
+
+    if !session.authenticated() {
+        return Err(std::io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!(
+                "couldn't log in to '{}' as '{}' with the ssh agent or any key in ~/.ssh",
+                host, user
+            ),
+        ));
+    }
+
+    let (mut channel, stat) = session
+        .scp_recv(std::path::Path::new(path))
+        .map_err(|error| {
+            std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("couldn't fetch '{}' from '{}': {}", path, host, error),
+            )
+        })?;
+
+    let mut bytes = Vec::with_capacity(stat.size() as usize);
+    channel.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Formats the current UTC time with the given strftime-style pattern, or the time in `SOURCE_DATE_EPOCH` if it's set.
+fn now(format: &str) -> std::io::Result<Vec<u8>> {
+    use chrono::format::{Item, StrftimeItems};
+    use chrono::{TimeZone, Utc};
+
+    // formatting with an invalid pattern panics, so it's checked upfront
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "invalid time format",
+        ));
+    }
+
+    let now = match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => match epoch
+            .parse()
+            .ok()
+            .and_then(|seconds| Utc.timestamp_opt(seconds, 0).single())
+        {
+            Some(epoch) => epoch,
+            None => {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "invalid SOURCE_DATE_EPOCH",
+                ))
+            }
+        },
+        Err(_) => Utc::now(),
+    };
+
+    Ok(now.format(format).to_string().into_bytes())
 }
 
-// some mildly ugly stuff
+/// Patches an Assuo patch file that's used as a source. Only the patches of the file being patched are snapshotted
+/// and have where their bytes came from recorded, not the ones of the files it uses as sources.
+async fn patch_nested(file: AssuoFile, options: &PatchOptions) -> std::io::Result<Vec<u8>> {
+    if options.snapshots.is_none() && options.provenance.is_none() {
+        return crate::patch::do_patch_with_options(file, options).await;
+    }
 
-/// When one trait needs to perform some kind of computation and resolve into another, this trait can be used.
-#[async_trait]
-pub trait Resolvable<R> {
-    async fn resolve(self) -> std::io::Result<R>;
+    let options = PatchOptions {
+        snapshots: None,
+        provenance: None,
+        ..options.clone()
+    };
+
+    crate::patch::do_patch_with_options(file, &options).await
 }
 
-#[async_trait]
-impl Resolvable<Vec<u8>> for AssuoSource {
-    async fn resolve(self) -> std::io::Result<Vec<u8>> {
-        fn err(kind: ErrorKind, reason: &'static str) -> std::io::Error {
-            std::io::Error::new(kind, reason)
-        }
+/// Sends a request, and reads the body of the response. Responses without a 2xx status are an error. `method` and
+/// `headers` are what the request was built with, which [`PatchOptions::trace_http`] is told about.
+///
+/// Only tries that could go differently the next time are retried: ones that couldn't connect, that took too long, or
+/// that the server had a problem with (a 5xx status). Anything else would fail the same way again.
+async fn fetch(
+    request: reqwest::RequestBuilder,
+    method: &'static str,
+    headers: &HashMap<String, String>,
+    policy: FetchPolicy,
+    options: &PatchOptions,
+) -> std::io::Result<Vec<u8>> {
+    let policy = policy.or(options.fetch_defaults);
+    let mut retries = policy.retry.unwrap_or(0);
 
-        // TODO: clean this up
-        match self {
-            AssuoSource::Bytes(bytes) => Ok(bytes),
-            AssuoSource::Text(string) => Ok(string.into_bytes()),
-            AssuoSource::File(file_path) => {
-                std::fs::read_to_string(file_path).and_then(|string| Ok(string.into_bytes()))
-            }
-            AssuoSource::Url(url) => {
-                let url = match reqwest::Url::parse(&url) {
-                    Ok(url) => url,
-                    Err(error) => {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::InvalidData,
-                            "the url was invalid",
-                        ))
-                    }
-                };
+    loop {
+        // every body we send is already in memory, so the request can always be built again
+        let attempt = request.try_clone().ok_or_else(|| {
+            std::io::Error::new(ErrorKind::InvalidData, "couldn't build the request")
+        })?;
 
-                let response = match reqwest::get(url).await {
-                    Ok(response) => response,
-                    Err(error) => {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::NotConnected,
-                            "couldn't GET the url",
-                        ))
-                    }
-                };
+        // the deadline is for everything (nested files included), so no attempt can go on past it
+        let timeout = match options.deadline {
+            Some(deadline) => {
+                let left = deadline
+                    .checked_duration_since(Instant::now())
+                    .unwrap_or_default();
 
-                let bytes = match response.bytes().await {
-                    Ok(bytes) => bytes,
-                    Err(error) => {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::NotConnected,
-                            "couldn't read bytes from peer",
-                        ))
-                    }
-                };
+                if left == Duration::default() {
+                    return Err(std::io::Error::new(
+                        ErrorKind::TimedOut,
+                        "the deadline passed before the url could be fetched",
+                    ));
+                }
 
-                Ok(bytes.to_vec())
+                Some(policy.timeout.map_or(left, |timeout| timeout.min(left)))
             }
-            AssuoSource::AssuoFile(file_path) => {
-                let payload = match std::fs::read_to_string(file_path)
-                    .and_then(|string| Ok(string.into_bytes()))
-                    .and_then(|bytes| {
-                        String::from_utf8(bytes).map_err(|_| {
-                            std::io::Error::new(
-                                std::io::ErrorKind::InvalidData,
-                                "couldnt parse bytes into string",
-                            )
-                        })
-                    })
-                    .and_then(|payload| {
-                        try_parse(&payload).map_err(|_| {
-                            std::io::Error::new(
-                                std::io::ErrorKind::InvalidData,
-                                "couldnt parse asuo config file",
-                            )
-                        })
-                    }) {
-                    Ok(payload) => payload,
-                    Err(error) => return Err(error),
-                };
+            None => policy.timeout,
+        };
 
-                crate::patch::do_patch(payload).await
+        match fetch_once(attempt, method, headers, timeout, policy.redirects, options).await {
+            Err(error)
+                if retries > 0
+                    && matches!(error.kind(), ErrorKind::NotConnected | ErrorKind::TimedOut) =>
+            {
+                retries -= 1
             }
-            AssuoSource::AssuoUrl(url) => {
-                let url = match reqwest::Url::parse(&url) {
-                    Ok(url) => url,
-                    Err(error) => {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::InvalidData,
-                            "the url was invalid",
-                        ))
-                    }
-                };
+            Ok(Err(status)) if retries > 0 && status.is_server_error() => retries -= 1,
+            Ok(Ok(bytes)) => return Ok(bytes),
+            // redirects that weren't followed end up here too, since they aren't a 2xx either
+            Ok(Err(status)) => {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} request to the url responded with {}", method, status),
+                ))
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
 
-                let response = match reqwest::get(url).await {
-                    Ok(response) => response,
-                    Err(error) => {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::NotConnected,
-                            "couldn't GET the url",
-                        ))
-                    }
+/// Sends the request once, giving up once it takes longer than the timeout (if there is one). Redirects are followed
+/// as they say, or like reqwest does by default if they aren't given. A response without a 2xx status gives back the
+/// status instead of the body.
+async fn fetch_once(
+    request: reqwest::RequestBuilder,
+    method: &'static str,
+    headers: &HashMap<String, String>,
+    timeout: Option<Duration>,
+    redirects: Option<Redirects>,
+    options: &PatchOptions,
+) -> std::io::Result<Result<Vec<u8>, reqwest::StatusCode>> {
+    let mut trace = options.trace_http.map(|_| HttpTrace {
+        method,
+        url: String::new(),
+        headers: headers
+            .iter()
+            .map(|(name, value)| {
+                // credentials shouldn't end up in logs
+                let credentials = ["authorization", "proxy-authorization", "cookie"];
+                let value = if credentials
+                    .iter()
+                    .any(|credential| name.eq_ignore_ascii_case(credential))
+                {
+                    String::from("<redacted>")
+                } else {
+                    value.clone()
                 };
 
-                let bytes = match response.bytes().await {
-                    Ok(bytes) => bytes,
-                    Err(error) => {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::NotConnected,
-                            "couldn't read bytes from peer",
-                        ))
-                    }
-                };
+                (name.clone(), value)
+            })
+            .collect(),
+        status: None,
+        size: None,
+    });
 
-                let bytes = bytes.to_vec();
+    let fetched = async {
+        let request = request.build().map_err(|_| {
+            std::io::Error::new(ErrorKind::InvalidData, "couldn't build the request")
+        })?;
 
-                let payload = match String::from_utf8(bytes)
-                    .map_err(|_| err(ErrorKind::InvalidData, "invalid string"))
-                    .and_then(|string| Ok(string.into_bytes()))
-                    .and_then(|bytes| {
-                        String::from_utf8(bytes).map_err(|_| {
-                            std::io::Error::new(
-                                std::io::ErrorKind::InvalidData,
-                                "couldnt parse bytes into string",
-                            )
-                        })
-                    })
-                    .and_then(|payload| {
-                        try_parse(&payload).map_err(|_| {
-                            std::io::Error::new(
-                                std::io::ErrorKind::InvalidData,
-                                "couldnt parse asuo config file",
-                            )
-                        })
-                    }) {
-                    Ok(payload) => payload,
-                    Err(error) => return Err(error),
-                };
+        if let Some(trace) = &mut trace {
+            let mut url = request.url().clone();
+            if !url.username().is_empty() || url.password().is_some() {
+                let _ = url.set_username("redacted");
+                let _ = url.set_password(None);
+            }
+
+            trace.url = url.to_string();
+        }
+
+        // without a TLS library, reqwest would only say that it couldn't connect, which doesn't say why
+        if request.url().scheme() == "https"
+            && !cfg!(any(feature = "rustls", feature = "native-tls"))
+        {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "https urls can only be fetched when assuo is built with the `rustls` or `native-tls` feature",
+            ));
+        }
+
+        let client = match redirects {
+            Some(redirects) => reqwest::Client::builder()
+                .redirect(redirects.to_policy())
+                .build()
+                .map_err(|_| {
+                    std::io::Error::new(ErrorKind::InvalidData, "couldn't build the request")
+                })?,
+            None => reqwest::Client::new(),
+        };
 
-                crate::patch::do_patch(payload).await
+        let response = client.execute(request).await.map_err(|error| {
+            if error.is_redirect() {
+                std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{} request to the url redirected somewhere its `redirects` don't allow",
+                        method
+                    ),
+                )
+            } else {
+                std::io::Error::new(
+                    ErrorKind::NotConnected,
+                    format!("couldn't {} the url", method),
+                )
             }
+        })?;
+
+        if let Some(trace) = &mut trace {
+            trace.status = Some(response.status().as_u16());
+        }
+
+        if !response.status().is_success() {
+            return Ok(Err(response.status()));
+        }
+
+        let bytes = response.bytes().await.map_err(|_| {
+            std::io::Error::new(ErrorKind::NotConnected, "couldn't read bytes from peer")
+        })?;
+
+        if let Some(trace) = &mut trace {
+            trace.size = Some(bytes.len());
         }
+
+        Ok(Ok(bytes.to_vec()))
+    };
+
+    let fetched = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, fetched).await {
+            Ok(fetched) => fetched,
+            Err(_) => Err(std::io::Error::new(
+                ErrorKind::TimedOut,
+                format!(
+                    "{} request to the url took longer than {:?}",
+                    method, timeout
+                ),
+            )),
+        },
+        None => fetched.await,
+    };
+
+    if let (Some(trace_http), Some(trace)) = (options.trace_http, &trace) {
+        trace_http(trace);
     }
+
+    fetched
 }
 
-#[async_trait]
-impl Resolvable<AssuoFile<Vec<u8>>> for AssuoFile {
-    // impl<S: Send + Resolvable<Vec<u8>>> Resolvable<AssuoFile<Vec<u8>>> for AssuoFile<S> {
-    async fn resolve(self) -> std::io::Result<AssuoFile<Vec<u8>>> {
-        let resolved_source = self.source.resolve().await?;
+/// Leaves out the UTF-8 or UTF-16 BOM the bytes start with, if they start with one, and warns that it did. `from` is
+/// the file or url the bytes are from.
+fn without_bom(mut bytes: Vec<u8>, from: &str, options: &PatchOptions) -> Vec<u8> {
+    let bom = if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        3
+    } else if bytes.starts_with(&[0xFF, 0xFE]) || bytes.starts_with(&[0xFE, 0xFF]) {
+        2
+    } else {
+        0
+    };
 
-        Ok(AssuoFile {
-            source: resolved_source,
-            patch: self.patch,
-        })
+    if bom > 0 {
+        options.warnings.push(Warning::StrippedBom {
+            from: String::from(from),
+        });
     }
+
+    bytes.drain(..bom);
+    bytes
 }
 
-#[async_trait]
-impl Resolvable<AssuoPatch<Vec<u8>>> for AssuoPatch {
-    // impl<S: Send + Resolvable<Vec<u8>>> Resolvable<AssuoPatch<Vec<u8>>> for AssuoPatch<S> {
-    async fn resolve(self) -> std::io::Result<AssuoPatch<Vec<u8>>> {
-        Ok(match self {
-            AssuoPatch::Insert { way, spot, source } => {
-                let source = source.resolve().await?;
-                AssuoPatch::<Vec<u8>>::Insert { way, spot, source }
+/// Takes the `timeout`, `retry` and `redirects` of a source that fetches something out of its table.
+fn fetch_policy_from_toml<E: serde::de::Error>(
+    table: &mut toml::value::Table,
+) -> Result<FetchPolicy, E> {
+    let timeout = match table.remove("timeout") {
+        Some(toml::Value::String(timeout)) => match parse_duration(&timeout) {
+            Some(timeout) => Some(timeout),
+            None => {
+                return Err(E::custom(
+                    "expected 'timeout' to be a duration like '500ms', '10s' or '2m'",
+                ))
             }
-            AssuoPatch::Remove { way, spot, count } => {
-                AssuoPatch::<Vec<u8>>::Remove { way, spot, count }
-            }
-        })
-    }
-}
+        },
+        Some(_) => return Err(E::custom("expected 'timeout' to be a string")),
+        None => None,
+    };
 
-// == ugly serialization stuff below ==
-// todo: cleanup
+    let retry = match table.remove("retry") {
+        Some(toml::Value::Integer(retry)) if retry >= 0 && retry <= u32::MAX as i64 => {
+            Some(retry as u32)
+        }
+        Some(_) => return Err(E::custom("expected 'retry' to be a positive integer")),
+        None => None,
+    };
 
-pub trait TomlDeserialize<'de>: Sized {
-    fn deserialize_toml<D>(value: Value) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>;
+    let redirects = match table.remove("redirects") {
+        Some(toml::Value::String(redirects)) if redirects == "none" => Some(Redirects::None),
+        Some(toml::Value::String(redirects)) if redirects == "same-host" => {
+            Some(Redirects::SameHost)
+        }
+        Some(toml::Value::Table(redirects)) => match redirects.get("limit") {
+            Some(toml::Value::Integer(limit)) if *limit >= 0 && redirects.len() == 1 => {
+                Some(Redirects::Limit(*limit as usize))
+            }
+            _ => {
+                return Err(E::custom(
+                    "expected 'redirects' to only have a positive integer 'limit'",
+                ))
+            }
+        },
+        Some(_) => {
+            return Err(E::custom(
+                "expected 'redirects' to be 'none', 'same-host', or a table with a 'limit'",
+            ))
+        }
+        None => None,
+    };
+
+    Ok(FetchPolicy {
+        timeout,
+        retry,
+        redirects,
+    })
 }
 
-impl<'de, S: TomlDeserialize<'de>> Deserialize<'de> for AssuoPatch<S> {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let table = match Value::deserialize(deserializer)? {
-            Value::Table(table) => table,
-            _ => return Err(Error::custom("didn't get a table as payload")),
-        };
+/// The most bytes a single repeat group in a bytes array can make, which is 1 GiB. A typo in the count shouldn't be
+/// able to take all of the memory there is.
+const MAX_REPEATED_LEN: usize = 1 << 30;
 
-        let action = table.get("do");
-        let is_insert = if let Some(action) = action {
-            let action = match action {
-                Value::String(string) => string,
-                _ => {
-                    return Err(Error::custom(
-                        "expected string for action 'do', didn't get that",
-                    ))
+/// Reads an array of bytes, where strings in the array are mixed in as their UTF-8 bytes.
+fn bytes_from_toml<E: serde::de::Error>(array: Vec<Value>) -> Result<Vec<u8>, E> {
+    let mut bytes = Vec::with_capacity(array.len());
+    for element in array {
+        match element {
+            toml::Value::Integer(i) => {
+                if i >= 0 && i <= 255 {
+                    bytes.push(i as u8);
+                } else {
+                    return Err(E::custom(
+                        "when converting byte to int, out of bounds [0, 255]",
+                    ));
                 }
-            };
-
-            // uppercase because docs have it like this,
-            // TODO PERF: explore micro-optimization with branch prediction if it should be uppercase or lowercase
-            if action.eq_ignore_ascii_case("INSERT") {
-                true
-            } else if action.eq_ignore_ascii_case("REMOVE") {
-                false
-            } else {
-                return Err(Error::custom(
-                    "expected either 'insert' or 'remove' for 'do'",
-                ));
             }
-        } else {
-            return Err(Error::custom("didn't get key 'do' with insert or remove"));
-        };
-
-        // both insert and remove need 'way' and 'spot'
-        let way = match table.get("way") {
-            Some(way) => way,
-            None => return Err(Error::custom("didn't get 'way'")),
-        };
+            // strings get mixed in as their UTF-8 bytes
+            toml::Value::String(string) => bytes.extend(string.into_bytes()),
+            // and `[count, [...]]` is what's in the inner array, `count` times over
+            toml::Value::Array(group) => {
+                let (count, repeated) = match group.as_slice() {
+                    [Value::Integer(count), Value::Array(repeated)] => (*count, repeated.clone()),
+                    _ => return Err(E::custom(
+                        "when reading bytes array, expected a repeat group like [count, [bytes]]",
+                    )),
+                };
 
-        let way = match way {
-            toml::Value::String(string) => string,
-            _ => return Err(Error::custom("didn't get string for way")),
-        };
+                if count < 0 {
+                    return Err(E::custom(
+                        "when reading bytes array, a repeat group can't repeat a negative amount of times",
+                    ));
+                }
 
-        let way = match way.as_str() {
-            "pre" => Direction::Pre,
-            "post" => Direction::Post,
-            _ => return Err(Error::custom("didn't get 'pre' or 'post' for 'way'")),
-        };
+                let repeated = bytes_from_toml::<E>(repeated)?;
+                let len = (repeated.len() as i64)
+                    .checked_mul(count)
+                    .filter(|len| *len <= MAX_REPEATED_LEN as i64);
 
-        let spot = match table.get("spot") {
-            Some(spot) => spot,
-            None => return Err(Error::custom("didn't get 'spot'")),
-        };
+                if len.is_none() {
+                    return Err(E::custom(format!(
+                        "when reading bytes array, a repeat group can't make more than {} bytes",
+                        MAX_REPEATED_LEN
+                    )));
+                }
 
-        let spot = match spot {
-            toml::Value::Integer(value) => value.clone() as usize,
-            _ => return Err(Error::custom("spot wasn't an integer")),
+                bytes.extend(repeated.repeat(count as usize));
+            }
+            _ => {
+                return Err(E::custom(
+                    "when reading bytes array, didn't get number, string or repeat group in array",
+                ))
+            }
         };
+    }
+    Ok(bytes)
+}
 
-        if is_insert {
-            // TODO: don't clone, and just consume the table
-            let source = match table.get("source") {
-                Some(value) => value,
-                None => return Err(Error::custom("expected source to be specified, it wasn't")),
+/// Reads a table where every value is a string.
+fn strings_from_toml<E: serde::de::Error>(
+    table: toml::value::Table,
+    error: &'static str,
+) -> Result<HashMap<String, String>, E> {
+    let mut strings = HashMap::with_capacity(table.len());
+    for (name, value) in table {
+        match value {
+            toml::Value::String(value) => {
+                strings.insert(name, value);
             }
-            .clone();
-
-            let source = S::deserialize_toml::<D>(source)?;
-
-            Ok(AssuoPatch::<S>::Insert { way, spot, source })
-        } else {
-            let count = match table.get("count") {
-                Some(value) => value,
-                None => return Err(Error::custom("expected count to be specified, it wasn't")),
-            };
-
-            let count = match count {
-                Value::Integer(count) => count.clone(),
-                _ => return Err(Error::custom("expected count to be integer, it wasn't")),
-            } as usize;
-
-            Ok(AssuoPatch::<S>::Remove { way, spot, count })
+            _ => return Err(E::custom(error)),
         }
     }
+    Ok(strings)
 }
 
 impl<'de> Deserialize<'de> for AssuoSource {
@@ -379,50 +3339,398 @@ impl<'de> TomlDeserialize<'de> for AssuoSource {
     {
         // TODO: this is hideous but it works and it's good enough, so... :yum:
         match value {
+            toml::Value::Table(mut table) if table.contains_key("len_of") => {
+                // unlike the other sources, `len_of` sits next to the `as` key that says how to encode it
+                let encoding = match table.remove("as") {
+                    Some(toml::Value::String(name)) => match LenEncoding::from_name(&name) {
+                        Some(encoding) => encoding,
+                        None => {
+                            return Err(serde::de::Error::custom(
+                                "expected u8/u16le/u16be/u32le/u32be for 'as'",
+                            ))
+                        }
+                    },
+                    _ => return Err(serde::de::Error::custom("expected string 'as' for len_of")),
+                };
+
+                let of = AssuoSource::deserialize_toml::<D>(table.remove("len_of").unwrap())?;
+
+                if !table.is_empty() {
+                    return Err(serde::de::Error::custom(
+                        "didn't expect anything other than 'len_of' and 'as'",
+                    ));
+                }
+
+                Ok(AssuoSource::LenOf {
+                    of: Box::new(of),
+                    encoding,
+                })
+            }
+            toml::Value::Table(mut table)
+                if [
+                    "expect_size",
+                    "strip_bom",
+                    "offset",
+                    "length",
+                    "timeout",
+                    "retry",
+                    "redirects",
+                ]
+                .iter()
+                .any(|key| table.contains_key(*key)) =>
+            {
+                // like `len_of`, `file` can have other keys next to it, with the size the file should be, whether
+                // to strip a BOM, and the range of it to read. `url` can have the BOM one, and its fetch policy.
+                let mut take_size = |key: &str| match table.remove(key) {
+                    Some(toml::Value::Integer(size)) if size >= 0 => Ok(Some(size as u64)),
+                    None => Ok(None),
+                    _ => Err(serde::de::Error::custom(format!(
+                        "expected '{}' to be a positive integer",
+                        key
+                    ))),
+                };
+
+                let expect_size = take_size("expect_size")?;
+                let range = match (take_size("offset")?, take_size("length")?) {
+                    (Some(offset), Some(length)) => Some((offset, length)),
+                    (None, None) => None,
+                    _ => {
+                        return Err(serde::de::Error::custom(
+                            "expected both 'offset' and 'length' for a range of a file",
+                        ))
+                    }
+                };
+
+                let strip_bom = match table.remove("strip_bom") {
+                    Some(toml::Value::Boolean(strip_bom)) => strip_bom,
+                    None => false,
+                    _ => {
+                        return Err(serde::de::Error::custom(
+                            "expected 'strip_bom' to be a boolean",
+                        ))
+                    }
+                };
+
+                let policy = fetch_policy_from_toml(&mut table)?;
+
+                let source = match (table.remove("file"), table.remove("url")) {
+                    (Some(toml::Value::String(path)), None) if policy.is_empty() => AssuoSource::File {
+                        path,
+                        expect_size,
+                        strip_bom,
+                        range,
+                    },
+                    (None, Some(toml::Value::String(url)))
+                        if expect_size.is_none() && range.is_none() =>
+                    {
+                        AssuoSource::Url {
+                            url,
+                            strip_bom,
+                            policy,
+                        }
+                    }
+                    _ => {
+                        return Err(serde::de::Error::custom(
+                            "expected string 'file' next to 'expect_size', 'offset' and 'length', string 'url' next to 'timeout', 'retry' and 'redirects', or either next to 'strip_bom'",
+                        ))
+                    }
+                };
+
+                if !table.is_empty() {
+                    return Err(serde::de::Error::custom(
+                        "didn't expect anything other than 'file' or 'url', 'expect_size', 'strip_bom', 'offset', 'length', 'timeout' and 'retry'",
+                    ));
+                }
+
+                Ok(source)
+            }
             toml::Value::Table(table) => {
                 if table.len() != 1 {
                     Err(serde::de::Error::custom("more than 1"))
                 } else {
                     let (name, inner) = table.into_iter().nth(0).unwrap();
                     match inner {
-                        toml::Value::Array(array) => {
-                            if name != "bytes" {
-                                Err(serde::de::Error::custom("got array but didn't get bytes"))
-                            } else {
-                                let mut bytes = Vec::with_capacity(array.len());
-                                for element in array {
-                                    let byte = match element {
-                                        toml::Value::Integer(i) => {
-                                            if i >= 0 && i <= 255 {
-                                                i as u8
-                                            } else {
-                                                return Err(serde::de::Error::custom("when converting byte to int, out of bounds [0, 255]"));
-                                            }
-                                        }
-                                        _ => return Err(serde::de::Error::custom(
-                                            "when reading bytes array, didn't get number in array",
-                                        )),
-                                    };
-                                    bytes.push(byte);
+                        toml::Value::Array(array) => match name.as_str() {
+                            "bytes" => Ok(AssuoSource::Bytes(bytes_from_toml(array)?)),
+                            "first_of" => {
+                                let mut sources = Vec::with_capacity(array.len());
+                                for source in array {
+                                    sources.push(AssuoSource::deserialize_toml::<D>(source)?);
                                 }
-                                Ok(AssuoSource::Bytes(bytes))
+                                Ok(AssuoSource::FirstOf(sources))
                             }
-                        }
+                            _ => Err(serde::de::Error::custom(
+                                "got array but didn't get bytes/first_of",
+                            )),
+                        },
                         toml::Value::String(string) => match name.as_str() {
                             "text" => Ok(AssuoSource::Text(string)),
-                            "url" => Ok(AssuoSource::Url(string)),
-                            "file" => Ok(AssuoSource::File(string)),
+                            "url" => Ok(AssuoSource::Url {
+                                url: string,
+                                strip_bom: false,
+                                policy: FetchPolicy::default(),
+                            }),
+                            "file" => Ok(AssuoSource::File {
+                                path: string,
+                                expect_size: None,
+                                strip_bom: false,
+                                range: None,
+                            }),
                             "assuo-url" => Ok(AssuoSource::AssuoUrl(string)),
                             "assuo-file" => Ok(AssuoSource::AssuoFile(string)),
-                            _ => Err(serde::de::Error::custom(
-                                "didn't get key text/url/file/assuo-url/assuo-file",
-                            )),
+                            "now" => Ok(AssuoSource::Now(string)),
+                            "indirect" => Ok(AssuoSource::Indirect(string)),
+                            "captured" => Ok(AssuoSource::Captured(string)),
+                            "scp" => Ok(AssuoSource::Scp(string)),
+                            _ => Err(serde::de::Error::custom(concat!(
+                                "didn't get key text/url/file/assuo-url/assuo-file/",
+                                "now/indirect/captured/scp",
+                            ))),
+                        },
+                        toml::Value::Table(mut table) => match name.as_str() {
+                            "zip" => {
+                                let mut take = |key: &str| match table.remove(key) {
+                                    Some(toml::Value::String(string)) => Ok(string),
+                                    _ => Err(serde::de::Error::custom(format!(
+                                        "expected string '{}' for zip",
+                                        key
+                                    ))),
+                                };
+
+                                let archive = take("archive")?;
+                                let entry = take("entry")?;
+                                Ok(AssuoSource::ZipEntry { archive, entry })
+                            }
+                            "post" => {
+                                let url = match table.remove("url") {
+                                    Some(toml::Value::String(url)) => url,
+                                    _ => {
+                                        return Err(serde::de::Error::custom(
+                                            "expected string 'url' for post",
+                                        ))
+                                    }
+                                };
+
+                                let body = match table.remove("body") {
+                                    Some(body) => AssuoSource::deserialize_toml::<D>(body)?,
+                                    None => AssuoSource::Bytes(Vec::new()),
+                                };
+
+                                let headers = match table.remove("headers") {
+                                    Some(toml::Value::Table(headers)) => strings_from_toml(
+                                        headers,
+                                        "expected post headers to be strings",
+                                    )?,
+                                    Some(_) => {
+                                        return Err(serde::de::Error::custom(
+                                            "expected table 'headers' for post",
+                                        ))
+                                    }
+                                    None => HashMap::new(),
+                                };
+
+                                let policy = fetch_policy_from_toml(&mut table)?;
+
+                                Ok(AssuoSource::Post {
+                                    url,
+                                    body: Box::new(body),
+                                    headers,
+                                    policy,
+                                })
+                            }
+                            "template" => {
+                                let vars = match table.remove("vars") {
+                                    Some(toml::Value::Table(vars)) => strings_from_toml(
+                                        vars,
+                                        "expected template vars to be strings",
+                                    )?,
+                                    Some(_) => {
+                                        return Err(serde::de::Error::custom(
+                                            "expected table 'vars' for template",
+                                        ))
+                                    }
+                                    None => HashMap::new(),
+                                };
+
+                                // whatever is left over is the source being templated
+                                let inner =
+                                    AssuoSource::deserialize_toml::<D>(toml::Value::Table(table))?;
+
+                                Ok(AssuoSource::Template {
+                                    inner: Box::new(inner),
+                                    vars,
+                                })
+                            }
+                            "xor" => {
+                                let key = match table.remove("key") {
+                                    Some(toml::Value::Array(key)) => bytes_from_toml(key)?,
+                                    _ => {
+                                        return Err(serde::de::Error::custom(
+                                            "expected bytes array 'key' for xor",
+                                        ))
+                                    }
+                                };
+
+                                let of = match table.remove("of") {
+                                    Some(of) => AssuoSource::deserialize_toml::<D>(of)?,
+                                    None => {
+                                        return Err(serde::de::Error::custom(
+                                            "expected source 'of' for xor",
+                                        ))
+                                    }
+                                };
+
+                                Ok(AssuoSource::Xor {
+                                    of: Box::new(of),
+                                    key,
+                                })
+                            }
+                            "pad" => {
+                                let to = match table.remove("to") {
+                                    Some(toml::Value::Integer(to)) if to > 0 => to as usize,
+                                    _ => {
+                                        return Err(serde::de::Error::custom(
+                                            "expected positive integer 'to' for pad",
+                                        ))
+                                    }
+                                };
+
+                                let with = match table.remove("with") {
+                                    Some(toml::Value::Integer(with))
+                                        if (0..=255).contains(&with) =>
+                                    {
+                                        with as u8
+                                    }
+                                    None => 0,
+                                    _ => {
+                                        return Err(serde::de::Error::custom(
+                                            "expected byte 'with' for pad, in bounds [0, 255]",
+                                        ))
+                                    }
+                                };
+
+                                let of = match table.remove("of") {
+                                    Some(of) => AssuoSource::deserialize_toml::<D>(of)?,
+                                    None => {
+                                        return Err(serde::de::Error::custom(
+                                            "expected source 'of' for pad",
+                                        ))
+                                    }
+                                };
+
+                                Ok(AssuoSource::Pad {
+                                    of: Box::new(of),
+                                    to,
+                                    with,
+                                })
+                            }
+                            "reverse" => {
+                                let of =
+                                    AssuoSource::deserialize_toml::<D>(toml::Value::Table(table))?;
+                                Ok(AssuoSource::Reverse(Box::new(of)))
+                            }
+                            "lines" => {
+                                let select = match table.remove("select") {
+                                    Some(toml::Value::String(select)) => select,
+                                    _ => {
+                                        return Err(serde::de::Error::custom(
+                                            "expected string 'select' for lines",
+                                        ))
+                                    }
+                                };
+
+                                let of = match table.remove("of") {
+                                    Some(of) => AssuoSource::deserialize_toml::<D>(of)?,
+                                    None => {
+                                        return Err(serde::de::Error::custom(
+                                            "expected source 'of' for lines",
+                                        ))
+                                    }
+                                };
+
+                                Ok(AssuoSource::Lines {
+                                    of: Box::new(of),
+                                    select,
+                                })
+                            }
+                            "urlencode" => {
+                                let of =
+                                    AssuoSource::deserialize_toml::<D>(toml::Value::Table(table))?;
+                                Ok(AssuoSource::UrlEncode(Box::new(of)))
+                            }
+                            "urldecode" => {
+                                let of =
+                                    AssuoSource::deserialize_toml::<D>(toml::Value::Table(table))?;
+                                Ok(AssuoSource::UrlDecode(Box::new(of)))
+                            }
+                            "if" => {
+                                let cond = match table.remove("cond") {
+                                    Some(toml::Value::Table(cond)) => {
+                                        cfg_from_toml(&cond).map_err(serde::de::Error::custom)?
+                                    }
+                                    _ => {
+                                        return Err(serde::de::Error::custom(
+                                            "expected table 'cond' for if",
+                                        ))
+                                    }
+                                };
+
+                                let mut take = |key: &str| match table.remove(key) {
+                                    Some(source) => AssuoSource::deserialize_toml::<D>(source),
+                                    None => Err(serde::de::Error::custom(format!(
+                                        "expected source '{}' for if",
+                                        key
+                                    ))),
+                                };
+
+                                let then = take("then")?;
+                                let else_ = take("else")?;
+
+                                if !table.is_empty() {
+                                    return Err(serde::de::Error::custom(
+                                        "expected only 'cond', 'then' and 'else' for if",
+                                    ));
+                                }
+
+                                Ok(AssuoSource::If {
+                                    cond,
+                                    then: Box::new(then),
+                                    else_: Box::new(else_),
+                                })
+                            }
+                            "base" => {
+                                let mut take = |key: &str| match table.remove(key) {
+                                    Some(toml::Value::Integer(offset)) if offset >= 0 => {
+                                        Ok(offset as usize)
+                                    }
+                                    _ => Err(serde::de::Error::custom(format!(
+                                        "expected positive integer '{}' for base",
+                                        key
+                                    ))),
+                                };
+
+                                let start = take("start")?;
+                                let end = take("end")?;
+                                Ok(AssuoSource::Base { start, end })
+                            }
+                            _ => Err(serde::de::Error::custom(concat!(
+                                "didn't get key post/zip/template/xor/pad/reverse/lines/",
+                                "urlencode/urldecode/if/base",
+                            ))),
                         },
                         _ => Err(serde::de::Error::custom("invalid value")),
                     }
                 }
             }
-            _ => Err(serde::de::Error::custom("not table")),
+            // an array of sources, like `[[source]]`, is all of them one after another
+            toml::Value::Array(array) => {
+                let mut sources = Vec::with_capacity(array.len());
+                for source in array {
+                    sources.push(AssuoSource::deserialize_toml::<D>(source)?);
+                }
+                Ok(AssuoSource::Concat(sources))
+            }
+            _ => Err(serde::de::Error::custom("not table or array")),
         }
     }
 }
@@ -1,46 +1,923 @@
 //! This module contains all algorithm related things for applying patches.
 
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
 use crate::models::Resolvable;
-use crate::models::{AssuoFile, AssuoPatch, Direction};
+use crate::models::{AssuoFile, AssuoPatch, AssuoSource, Direction, FetchPolicy, Footer};
+
+/// Options that change how patches get applied. The defaults are what [`do_patch`] uses.
+#[derive(Debug, Default, Clone)]
+pub struct PatchOptions {
+    /// When set, multiple inserts at the exact same spot in the same direction are an error, rather than being
+    /// applied in the order they are listed in.
+    pub strict_spots: bool,
+
+    /// When set, only sources of these kinds (named as they are in an Assuo patch file, like `text` or `url`) can be
+    /// resolved. This applies to sources nested in other sources, and to everything in other Assuo patch files too.
+    pub allowed_sources: Option<HashSet<String>>,
+
+    /// When set, this gets called after every patch gets applied, with what the buffer looks like at that point.
+    /// This is only meant for debugging the patching algorithm.
+    pub after_patch: Option<fn(&PatchSnapshot)>,
+
+    /// When set, this gets called for every HTTP request made while resolving sources, once it's done (or has
+    /// failed). This is meant for debugging sources that fetch things.
+    pub trace_http: Option<fn(&HttpTrace)>,
+
+    /// The files of the `indirect` sources currently being resolved, outermost first. An `indirect` source that
+    /// leads back to one of these is an error, rather than never finishing. This is usually left empty.
+    pub indirect_files: Vec<std::path::PathBuf>,
+
+    /// The bytes taken by `capture` patches, by name, which `captured` sources read from. Patching fills these in
+    /// from the patches it's given, so this is usually left empty.
+    pub captures: HashMap<String, Vec<u8>>,
+
+    /// The original source of the file being patched, which `base` sources read from. Patching fills this in, so this
+    /// is usually left empty.
+    pub base: Option<Arc<Vec<u8>>>,
+
+    /// When set, resolving the base or the source of any one patch (everything from connecting to reading the last
+    /// byte) can only take this long, after which it's given up on with a [`TimedOut`](std::io::ErrorKind::TimedOut)
+    /// error.
+    pub source_timeout: Option<Duration>,
+
+    /// When set, every URL has to be fetched by this time, including ones in Assuo patch files nested in this one
+    /// (like with `assuo-url` sources), after which fetching gives up with a
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut) error. Unlike [`PatchOptions::source_timeout`], this is one budget
+    /// for everything, however deeply it's nested.
+    pub deadline: Option<Instant>,
+
+    /// How long fetching a URL can take, and how many times it's tried again, for sources that don't say so
+    /// themselves.
+    pub fetch_defaults: FetchPolicy,
+
+    /// When set, the base or the source of any patch resolving to no bytes at all (like an empty file, or a response
+    /// without a body) is an error, rather than quietly making the output wrong. Files that leave out their source
+    /// start from no bytes on purpose, so that isn't an error.
+    pub no_empty_sources: bool,
+
+    /// When set, an insert whose source can't be resolved inserts no bytes instead, with a warning, rather than
+    /// failing the whole run. The base not resolving is still an error. This is meant for outputs where some sources
+    /// are only nice to have.
+    pub continue_on_resolve_error: bool,
+
+    /// When set, patches with the exact same source (like the same `file`, or the same `text`) only resolve it once,
+    /// and share what it resolved to.
+    pub dedupe_sources: bool,
+
+    /// When set, patches are applied in order of their spots (once they're all in bytes from the start), rather than
+    /// in the order they are listed in, and are numbered in that order too. Patches at the same spot keep the order
+    /// they are listed in. Spots always refer to the original source, so a file that patches fine either way patches
+    /// to the same thing, and this only gives patches a canonical order.
+    pub sort_patches: bool,
+
+    /// When set, sources that resolve to something different every run (like `now`) are replayed from here if they
+    /// were resolved before, and recorded here if they weren't, so that runs can be reproduced exactly.
+    pub lock: Option<Lock>,
+
+    /// When set, every source that gets resolved (including ones nested in other sources) is recorded here, along
+    /// with a hash of what it resolved to, so that what went into the output can be audited afterwards.
+    pub manifest: Option<Manifest>,
+
+    /// When set, what the buffer looks like after every patch is recorded here, so that how the output came together
+    /// can be looked at one patch at a time. Patches of Assuo patch files used as sources aren't recorded.
+    pub snapshots: Option<Snapshots>,
+
+    /// When set, where every byte of the output came from is recorded here, so that which patch put a byte there can
+    /// be found out. This is for the output before it's post processed, and Assuo patch files used as sources count as
+    /// one source, rather than having their own bytes explained.
+    pub provenance: Option<Provenance>,
+
+    /// When set, relative URLs (like `parts/header.bin`) are resolved against this URL, the way a browser resolves
+    /// links in a page. Assuo patch files fetched by `assuo-url` sources resolve theirs against where they were
+    /// fetched from instead, so that they can refer to files next to them on the same server.
+    pub base_url: Option<String>,
+
+    /// What URLs respond with, for URLs that shouldn't really be fetched. This is meant for testing patch files
+    /// without a server, and needs the `testing` feature.
+    #[cfg(feature = "testing")]
+    pub mock_responses: MockResponses,
+
+    /// Where warnings end up, for things that are worth knowing about but aren't errors. Clones of the options share
+    /// the same warnings.
+    pub warnings: Warnings,
+}
+
+/// Something worth knowing about that happened while patching, but isn't an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The patch at this index, in the order they are listed in, didn't change anything, like an insert of nothing
+    /// or a removal of nothing.
+    NoOpPatch { patch: usize },
+    /// A BOM was left out of the file or url, because it had `strip_bom` set.
+    StrippedBom { from: String },
+    /// A `first_of` source couldn't resolve the sources before the one at this index, so that one was used instead.
+    FellBack { to: usize },
+    /// The source of the insert at this index couldn't be resolved, so it inserted nothing instead, because
+    /// [`PatchOptions::continue_on_resolve_error`] was set.
+    SkippedSource { patch: usize, error: String },
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::NoOpPatch { patch } => write!(f, "patch #{} doesn't change anything", patch),
+            Warning::StrippedBom { from } => write!(f, "left out the BOM of '{}'", from),
+            Warning::FellBack { to } => write!(
+                f,
+                "first_of fell back to source #{}, since the ones before it failed",
+                to
+            ),
+            Warning::SkippedSource { patch, error } => write!(
+                f,
+                "patch #{} inserted nothing, since its source couldn't be resolved: {}",
+                patch, error
+            ),
+        }
+    }
+}
+
+/// The warnings of a patch run, which can be added to from anywhere the options are given to.
+#[derive(Debug, Default, Clone)]
+pub struct Warnings(Arc<Mutex<Vec<Warning>>>);
+
+impl Warnings {
+    /// Adds a warning.
+    pub fn push(&self, warning: Warning) {
+        self.0.lock().unwrap().push(warning);
+    }
+
+    /// Takes every warning so far, in the order they happened in, leaving none behind.
+    pub fn take(&self) -> Vec<Warning> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// What sources that resolve to something different every run resolved to, by the source, so that they can be
+/// replayed. This is kept in a lockfile between runs. Clones share the same values.
+#[derive(Debug, Default, Clone)]
+pub struct Lock(Arc<Mutex<BTreeMap<String, Vec<u8>>>>);
+
+impl Lock {
+    /// Reads a lock written by [`Lock::to_toml`].
+    pub fn from_toml(payload: &str) -> std::io::Result<Self> {
+        let invalid = |reason: String| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("couldn't read the lock: {}", reason),
+            )
+        };
+
+        let encoded = toml::from_str::<BTreeMap<String, String>>(payload)
+            .map_err(|error| invalid(error.to_string()))?;
+
+        let mut values = BTreeMap::new();
+        for (source, value) in encoded {
+            let value = base64::decode(&value).map_err(|error| invalid(error.to_string()))?;
+            values.insert(source, value);
+        }
+
+        Ok(Lock(Arc::new(Mutex::new(values))))
+    }
+
+    /// Writes out every value, base64 encoded, by the source it's from.
+    pub fn to_toml(&self) -> String {
+        let encoded = self
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(source, value)| (source.clone(), base64::encode(value)))
+            .collect::<BTreeMap<_, _>>();
+
+        toml::to_string(&encoded).unwrap()
+    }
+
+    /// Replays what the source resolved to, if it's been resolved before. Otherwise, it's resolved with `resolve`,
+    /// and what it resolves to is recorded.
+    pub(crate) fn replay_or(
+        &self,
+        source: String,
+        resolve: impl FnOnce() -> std::io::Result<Vec<u8>>,
+    ) -> std::io::Result<Vec<u8>> {
+        if let Some(value) = self.0.lock().unwrap().get(&source) {
+            return Ok(value.clone());
+        }
+
+        let value = resolve()?;
+        self.0.lock().unwrap().insert(source, value.clone());
+        Ok(value)
+    }
+}
+
+/// Every source resolved in a patch run, in the order they finished resolving in. Clones share the same entries.
+#[derive(Debug, Default, Clone)]
+pub struct Manifest(Arc<Mutex<Vec<ManifestEntry>>>);
+
+/// A source that was resolved, in a [`Manifest`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestEntry {
+    /// The kind of source it was, like `text` or `url`.
+    pub kind: &'static str,
+    /// The source, in the same shape it's written in an Assuo patch file.
+    pub spec: serde_json::Value,
+    /// How many bytes it resolved to.
+    pub length: usize,
+    /// The SHA-256 of what it resolved to, in lowercase hex.
+    pub sha256: String,
+}
+
+impl Manifest {
+    /// Records that a source resolved to `bytes`.
+    pub fn record(&self, kind: &'static str, spec: serde_json::Value, bytes: &[u8]) {
+        use sha2::{Digest, Sha256};
+
+        self.0.lock().unwrap().push(ManifestEntry {
+            kind,
+            spec,
+            length: bytes.len(),
+            sha256: format!("{:x}", Sha256::digest(bytes)),
+        });
+    }
+
+    /// Every entry so far.
+    pub fn entries(&self) -> Vec<ManifestEntry> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Writes out every entry as a JSON list.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&*self.0.lock().unwrap()).unwrap()
+    }
+}
+
+/// What the buffer looked like after every patch of a patch run, in the order they were applied in. Clones share the
+/// same snapshots.
+#[derive(Debug, Default, Clone)]
+pub struct Snapshots(Arc<Mutex<Vec<Vec<u8>>>>);
+
+impl Snapshots {
+    /// Records what the buffer looks like after the next patch.
+    pub fn record(&self, buffer: &[u8]) {
+        self.0.lock().unwrap().push(buffer.to_vec());
+    }
+
+    /// Takes every snapshot so far.
+    pub fn take(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// Where a byte of the patched output came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// The byte was at this offset in the base.
+    Base(usize),
+    /// The byte was inserted by the patch at this index, in the order they were applied in.
+    Patch(usize),
+}
+
+/// Where every byte of the output of a patch run came from, given to [`PatchOptions::provenance`]. Clones share the
+/// same provenance.
+#[derive(Debug, Default, Clone)]
+pub struct Provenance(Arc<Mutex<Vec<Origin>>>);
+
+impl Provenance {
+    /// Records where every byte of the output came from, replacing whatever was recorded before.
+    pub fn record(&self, origins: &[Origin]) {
+        *self.0.lock().unwrap() = origins.to_vec();
+    }
+
+    /// Takes where every byte of the output came from, in the order the bytes are in.
+    pub fn take(&self) -> Vec<Origin> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// What URLs respond with instead of being fetched, given to [`PatchOptions::mock_responses`]. Only `url` and
+/// `assuo-url` sources, which fetch with a GET, are answered from here. Clones share the same responses.
+#[cfg(feature = "testing")]
+#[derive(Debug, Default, Clone)]
+pub struct MockResponses(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+
+#[cfg(feature = "testing")]
+impl MockResponses {
+    /// Makes the URL respond with `bytes`, rather than being fetched.
+    pub fn respond(&self, url: &str, bytes: impl Into<Vec<u8>>) {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(String::from(url), bytes.into());
+    }
+
+    /// What the URL responds with, if it's mocked.
+    pub fn get(&self, url: &str) -> Option<Vec<u8>> {
+        self.0.lock().unwrap().get(url).cloned()
+    }
+}
+
+/// What the buffer looks like in the middle of patching, given to [`PatchOptions::after_patch`].
+#[derive(Debug)]
+pub struct PatchSnapshot<'a> {
+    /// The index of the patch that was just applied, in the order they are listed in.
+    pub patch: usize,
+    /// For every byte of the buffer, the spots in the original source it stands for. Bytes that were inserted by a
+    /// patch only have `std::usize::MAX`.
+    pub indexes: &'a [Vec<usize>],
+    /// The buffer, with every patch up to and including this one applied.
+    pub buffer: &'a [u8],
+}
+
+/// An HTTP request made while resolving sources, given to [`PatchOptions::trace_http`].
+#[derive(Debug)]
+pub struct HttpTrace {
+    /// The method of the request, like `GET` or `POST`.
+    pub method: &'static str,
+    /// The url the request was sent to, with any username and password in it left out.
+    pub url: String,
+    /// The headers given to the request, with the values of ones that carry credentials (like `Authorization` and
+    /// `Cookie`) left out.
+    pub headers: Vec<(String, String)>,
+    /// The status of the response, unless there wasn't one.
+    pub status: Option<u16>,
+    /// How many bytes the body of the response was, unless it wasn't read.
+    pub size: Option<usize>,
+}
+
+impl std::fmt::Display for HttpTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.method, self.url)?;
+        for (name, value) in &self.headers {
+            write!(f, " [{}: {}]", name, value)?;
+        }
+
+        match (self.status, self.size) {
+            (Some(status), Some(size)) => write!(f, " -> {}, {} bytes", status, size),
+            (Some(status), None) => write!(f, " -> {}", status),
+            (None, _) => write!(f, " -> no response"),
+        }
+    }
+}
+
+impl PatchOptions {
+    /// Whether sources of the given kind can be resolved.
+    pub fn allows(&self, kind: &str) -> bool {
+        match &self.allowed_sources {
+            Some(allowed) => allowed.contains(kind),
+            None => true,
+        }
+    }
+}
+
+/// A transformation over the final patched bytes, like minifying or signing them, given to [`do_patch_with`].
+pub type PostProcessor = Box<dyn Fn(Vec<u8>) -> std::io::Result<Vec<u8>> + Send + Sync>;
+
+/// How long each phase of a patch run took, in the order they ran in.
+#[derive(Debug, Default)]
+pub struct Profile {
+    /// Every phase, named by the phases it's nested in and itself, separated by `;`, like `resolve;patch_0`.
+    pub spans: Vec<(String, Duration)>,
+}
+
+impl Profile {
+    fn record(&mut self, name: String, start: Instant) {
+        self.spans.push((name, start.elapsed()));
+    }
+
+    /// Writes out every phase in the folded stack format that flamegraph tooling reads, with the time each phase
+    /// took in microseconds.
+    pub fn to_folded(&self) -> String {
+        self.spans
+            .iter()
+            .map(|(name, duration)| format!("assuo;{} {}\n", name, duration.as_micros()))
+            .collect()
+    }
+}
 
 /// Given an AssuoFile, will perform all patches on the given assuo file and return the patched file.
 pub async fn do_patch(file: AssuoFile) -> std::io::Result<Vec<u8>> {
+    do_patch_with_options(file, &PatchOptions::default()).await
+}
+
+/// Like [`do_patch_with_options`], but stops as soon as `cancel` completes. Whatever was in flight at the time (like
+/// fetching a slow URL) is dropped, and an [`Interrupted`](std::io::ErrorKind::Interrupted) error is returned.
+pub async fn do_patch_until<C: Future>(
+    file: AssuoFile,
+    options: &PatchOptions,
+    cancel: C,
+) -> std::io::Result<Vec<u8>> {
+    Cancellable {
+        work: Box::pin(do_patch_with_options(file, options)),
+        cancel: Box::pin(cancel),
+    }
+    .await
+}
+
+/// Resolves something, giving up once it takes longer than [`PatchOptions::source_timeout`]. `what` names it in the
+/// error, if it times out.
+async fn resolve_in_time<R, T: Resolvable<R>>(
+    resolvable: T,
+    options: &PatchOptions,
+    what: &str,
+) -> std::io::Result<R> {
+    let timeout = match options.source_timeout {
+        Some(timeout) => timeout,
+        None => return resolvable.resolve_with(options).await,
+    };
+
+    match tokio::time::timeout(timeout, resolvable.resolve_with(options)).await {
+        Ok(resolved) => resolved,
+        Err(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("resolving {} took longer than {:?}", what, timeout),
+        )),
+    }
+}
+
+/// Errors if a source resolved to no bytes, when [`PatchOptions::no_empty_sources`] is set. `what` names the source,
+/// and `kind` is what kind of source it is.
+fn check_not_empty(
+    bytes: &[u8],
+    options: &PatchOptions,
+    what: &str,
+    kind: &str,
+) -> std::io::Result<()> {
+    if options.no_empty_sources && bytes.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} resolved to no bytes, from a '{}' source", what, kind),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Appends the footer of the patched bytes onto them, if the file has one.
+fn append_footer(patched: &mut Vec<u8>, footer: Option<Footer>) {
+    if let Some(footer) = footer {
+        let footer = footer.of(patched);
+        patched.extend_from_slice(&footer);
+    }
+}
+
+/// Errors if the output doesn't have the SHA-256 the file expects it to, if it expects one.
+fn check_output_sha256(output: &[u8], expected: Option<&str>) -> std::io::Result<()> {
+    use sha2::{Digest, Sha256};
+
+    let expected = match expected {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+
+    let actual = format!("{:x}", Sha256::digest(output));
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "expected the output to have a SHA-256 of {}, but it has {}",
+                expected, actual
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Polls `work` until it's done, unless `cancel` is done first.
+struct Cancellable<W, C> {
+    work: Pin<Box<W>>,
+    cancel: Pin<Box<C>>,
+}
+
+impl<T, W: Future<Output = std::io::Result<T>>, C: Future> Future for Cancellable<W, C> {
+    type Output = std::io::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.cancel.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "patching was cancelled",
+            )));
+        }
+
+        self.work.as_mut().poll(cx)
+    }
+}
+
+/// Given an AssuoFile, will perform all patches on the given assuo file with the given options and return the
+/// patched file.
+pub async fn do_patch_with_options(
+    file: AssuoFile,
+    options: &PatchOptions,
+) -> std::io::Result<Vec<u8>> {
+    do_patch_profiled(file, options, &mut Profile::default()).await
+}
+
+/// Like [`do_patch_with_options`], but the patched file is given to `post_process` (if there is one), and what it
+/// returns is returned instead, along with every warning of the run. Only the final output is post processed, not
+/// the output of any Assuo patch files used as sources.
+pub async fn do_patch_with(
+    file: AssuoFile,
+    options: &PatchOptions,
+    post_process: Option<PostProcessor>,
+) -> std::io::Result<(Vec<u8>, Vec<Warning>)> {
+    // the warnings of this run are kept apart from any the given options already have
+    let options = PatchOptions {
+        warnings: Warnings::default(),
+        ..options.clone()
+    };
+
+    let patched = do_patch_with_options(file, &options).await?;
+
+    let patched = match post_process {
+        Some(post_process) => post_process(patched)?,
+        None => patched,
+    };
+
+    Ok((patched, options.warnings.take()))
+}
+
+/// Like [`do_patch_with_options`], but also records how long resolving and applying each patch took into `profile`.
+pub async fn do_patch_profiled(
+    file: AssuoFile,
+    options: &PatchOptions,
+    profile: &mut Profile,
+) -> std::io::Result<Vec<u8>> {
+    // resolve the base
+    let start = Instant::now();
+    let kind = file.source.kind();
+    // a left out source is no bytes on purpose, rather than because something went wrong resolving it
+    let left_out = file.source == AssuoSource::default();
+    let source = resolve_in_time(file.source, options, "the source").await?;
+    if !left_out {
+        check_not_empty(&source, options, "the source", kind)?;
+    }
+    profile.record(String::from("resolve;source"), start);
+
+    let mut patched =
+        do_patch_onto(source, file.patch.unwrap_or_default(), options, profile).await?;
+    append_footer(&mut patched, file.footer);
+    check_output_sha256(&patched, file.expect_output_sha256.as_deref())?;
+    Ok(patched)
+}
+
+/// Applies the patches onto the given base, rather than onto the source of some AssuoFile. Spots refer to the given
+/// base. How long resolving and applying each patch took is recorded into `profile`.
+pub async fn do_patch_onto(
+    base: Vec<u8>,
+    patches: Vec<AssuoPatch>,
+    options: &PatchOptions,
+    profile: &mut Profile,
+) -> std::io::Result<Vec<u8>> {
+    let mut buffer = base;
+    patch_buffer(&mut buffer, patches, options, profile).await?;
+    Ok(buffer)
+}
+
+/// Like [`do_patch`], but the patched file is written into `buffer` rather than into a new `Vec`. The buffer is
+/// cleared first, so reusing one buffer across many calls saves allocating a new one for every call. If patching
+/// fails, whatever is left in the buffer shouldn't be used.
+pub async fn do_patch_into(file: AssuoFile, buffer: &mut Vec<u8>) -> std::io::Result<()> {
+    let options = PatchOptions::default();
+    let source = resolve_in_time(file.source, &options, "the source").await?;
+
+    buffer.clear();
+    buffer.extend_from_slice(&source);
+
+    let patches = file.patch.unwrap_or_default();
+    patch_buffer(buffer, patches, &options, &mut Profile::default()).await?;
+    append_footer(buffer, file.footer);
+    check_output_sha256(buffer, file.expect_output_sha256.as_deref())
+}
+
+/// The length of what [`do_patch`] gives for the file, found out without patching it. Every source still gets
+/// resolved, but rather than splicing anything in or out, how many bytes get inserted and removed is added up. A
+/// footer always adds the same number of bytes, so it's added on without being worked out.
+///
+/// A removal always removes `count` of the bytes that are left, never ones an earlier patch already removed, so
+/// removals that overlap in the original source still add up the same as they patch. How much a removal until some
+/// anchor removes depends on what's around it though, so files with those get patched for real. Removals that go past
+/// an end of the data are only an error here if they'd remove more bytes than there are in total.
+pub async fn patched_len(file: AssuoFile) -> std::io::Result<usize> {
+    let options = PatchOptions::default();
+    let source = resolve_in_time(file.source, &options, "the source").await?;
+
+    let patches = file.patch.unwrap_or_default();
+    let (base, patches, options) =
+        resolve_patches(source, patches, &options, &mut Profile::default()).await?;
+    let footer = file.footer.map_or(0, Footer::len);
+
+    if patches
+        .iter()
+        .any(|patch| matches!(patch.unlabeled(), AssuoPatch::RemoveUntil { .. }))
+    {
+        let mut patcher = Patcher::reporting_to(base, options.warnings);
+        for patch in patches {
+            patcher.apply(patch)?;
+        }
+
+        return Ok(patcher.finish().len() + footer);
+    }
+
+    let mut len = base.len();
+    for (index, patch) in patches.iter().enumerate() {
+        match patch.unlabeled() {
+            AssuoPatch::Insert { source, .. } => len += source.len(),
+            AssuoPatch::Remove { count, .. } => {
+                len = len.checked_sub(*count).ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "{}: removes {} bytes, but only {} are left",
+                            describe(index, patch.label()),
+                            count,
+                            len
+                        ),
+                    )
+                })?
+            }
+            _ => {}
+        }
+    }
+
+    Ok(len + footer)
+}
+
+/// Applies the patches onto whatever is in the buffer, where spots refer to the buffer as it is before patching.
+async fn patch_buffer(
+    buffer: &mut Vec<u8>,
+    patches: Vec<AssuoPatch>,
+    options: &PatchOptions,
+    profile: &mut Profile,
+) -> std::io::Result<()> {
     // in the future, it would be nice to be able to apply patches as they come along so that everything is
     // non-blocking and fast, but for now, it's much simpler to "resolve everything -> apply patches"
+    let base = std::mem::take(buffer);
+    let (base, mut patches, options) = resolve_patches(base, patches, options, profile).await?;
 
-    // resolve the base
-    let mut file = file.resolve().await?;
+    // sorting is stable, so patches at the same spot stay in the order they are listed in
+    if options.sort_patches {
+        patches.sort_by_key(|patch| patch.spot());
+    }
 
-    // resolve every patch
-    let mut patches = Vec::new();
-    if let Some(patch) = file.patch {
-        for patch in patch {
-            patches.push(patch.resolve().await?);
+    let mut patcher = Patcher::reporting_to(base, options.warnings.clone());
+
+    // everything that gets inserted is known by now, so the buffer only has to grow the once
+    patcher.reserve(patches.iter().map(inserted_len).sum());
+
+    // now, we apply each patch sequentially
+    for (index, patch) in patches.into_iter().enumerate() {
+        let start = Instant::now();
+        patcher.apply(patch)?;
+        profile.record(format!("apply;patch_{}", index), start);
+
+        if options.after_patch.is_some() || options.snapshots.is_some() {
+            // the snapshot should show everything so far, so the inserts being held back have to go in now
+            patcher.flush();
+        }
+
+        if let Some(after_patch) = options.after_patch {
+            after_patch(&PatchSnapshot {
+                patch: index,
+                indexes: &patcher.indexes,
+                buffer: &patcher.buffer,
+            });
         }
+
+        if let Some(snapshots) = &options.snapshots {
+            snapshots.record(&patcher.buffer);
+        }
+    }
+
+    if let Some(provenance) = &options.provenance {
+        provenance.record(patcher.origins());
+    }
+
+    let start = Instant::now();
+    *buffer = patcher.finish();
+    profile.record(String::from("apply;flush"), start);
+
+    Ok(())
+}
+
+/// Gets every patch ready to be applied onto the base, with spots in bytes from the start and every source resolved.
+/// The base is handed back once nothing needs it anymore, along with the options the patches were resolved with.
+async fn resolve_patches(
+    base: Vec<u8>,
+    patches: Vec<AssuoPatch>,
+    options: &PatchOptions,
+    profile: &mut Profile,
+) -> std::io::Result<(Vec<u8>, Vec<AssuoPatch<Vec<u8>>>, PatchOptions)> {
+    // patches for this target are the same as ones without a target, and the rest are left to be skipped
+    let patches = patches.into_iter().map(|patch| match patch {
+        AssuoPatch::Gated { cfg, patch } if cfg.matches() => *patch,
+        patch => patch,
+    });
+
+    // spots that are found, and ones measured in characters, refer to the original source too, so they can all be
+    // turned into bytes upfront
+    let mut located = Vec::new();
+    for (index, patch) in patches.enumerate() {
+        let what = describe(index, patch.label());
+        located.push(locate(patch, &base, options, &what).await?);
+    }
+
+    let patches = located
+        .into_iter()
+        .map(|patch| in_bytes(patch, &base))
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    if options.strict_spots {
+        check_spots(patches.iter())?;
     }
 
+    // captures and `base` sources refer to the original source, so they're all taken before anything gets spliced
+    let mut options = options.clone();
+    options.captures.extend(take_captures(&base, &patches)?);
+
+    options.base = Some(Arc::new(base));
+
+    // resolve every patch
+    let patches = {
+        let mut resolved = Vec::new();
+        let mut deduped = HashMap::<String, Vec<u8>>::new();
+        for (index, patch) in patches.into_iter().enumerate() {
+            let start = Instant::now();
+            let what = format!("the source of {}", describe(index, patch.label()));
+            let kind = match &patch {
+                AssuoPatch::Insert { source, .. } => source.kind(),
+                _ => "",
+            };
+
+            // sources are the same when they're written the same, which their JSON (with its keys sorted) says
+            let key = match patch.source() {
+                Some(source) if options.dedupe_sources => serde_json::to_value(source)
+                    .ok()
+                    .map(|source| source.to_string()),
+                _ => None,
+            };
+
+            if let Some(bytes) = key.as_ref().and_then(|key| deduped.get(key)) {
+                resolved.push(patch.map_sources(&mut |_| bytes.clone()));
+                profile.record(format!("resolve;patch_{}", index), start);
+                continue;
+            }
+
+            let emptied = if options.continue_on_resolve_error {
+                emptied(&patch)
+            } else {
+                None
+            };
+
+            let patch = match (resolve_in_time(patch, &options, &what).await, emptied) {
+                (Ok(patch), _) => {
+                    if let (Some(key), Some(bytes)) = (key, patch.source()) {
+                        deduped.insert(key, bytes.clone());
+                    }
+                    patch
+                }
+                (Err(error), Some(emptied)) => {
+                    options.warnings.push(Warning::SkippedSource {
+                        patch: index,
+                        error: error.to_string(),
+                    });
+                    emptied
+                }
+                (Err(error), None) => return Err(error),
+            };
+            if let AssuoPatch::Insert { source, .. } = &patch {
+                check_not_empty(source, &options, &what, kind)?;
+            }
+
+            resolved.push(patch);
+            profile.record(format!("resolve;patch_{}", index), start);
+        }
+        resolved
+    };
+
+    // nothing else holds onto the base once everything is resolved, so it doesn't need to be copied back out
+    let base = options.base.take().unwrap();
+    let base = Arc::try_unwrap(base).unwrap_or_else(|base| base.to_vec());
+
+    Ok((base, patches, options))
+}
+
+/// The same insert as this patch, but with no bytes as its source, for when its source can't be resolved. Patches that
+/// aren't inserts don't have one.
+fn emptied(patch: &AssuoPatch) -> Option<AssuoPatch<Vec<u8>>> {
+    Some(match patch {
+        AssuoPatch::Insert { way, spot, .. } => AssuoPatch::Insert {
+            way: *way,
+            spot: *spot,
+            source: Vec::new(),
+        },
+        AssuoPatch::InChars(patch) => AssuoPatch::InChars(Box::new(emptied(patch)?)),
+        AssuoPatch::FromEnd(patch) => AssuoPatch::FromEnd(Box::new(emptied(patch)?)),
+        AssuoPatch::Labeled { label, patch } => AssuoPatch::Labeled {
+            label: label.clone(),
+            patch: Box::new(emptied(patch)?),
+        },
+        AssuoPatch::Gated { cfg, patch } => AssuoPatch::Gated {
+            cfg: cfg.clone(),
+            patch: Box::new(emptied(patch)?),
+        },
+        // spots that are found have all been found by now
+        AssuoPatch::Remove { .. }
+        | AssuoPatch::RemoveUntil { .. }
+        | AssuoPatch::Capture { .. }
+        | AssuoPatch::Found { .. }
+        | AssuoPatch::Matched { .. } => return None,
+    })
+}
+
+/// Applies already resolved patches onto a base one at a time, so that what the data looks like in between patches
+/// can be looked at. This is what [`do_patch`] uses once every source is resolved.
+///
+/// Spots of every patch refer to the base as it was before any patch was applied. Patches measured in characters
+/// have to be turned into bytes first, and `capture` patches don't do anything here, since captures are taken from
+/// the base upfront with [`take_captures`]. Inserting somewhere inside of a range that an earlier patch removed is an
+/// error, since that spot isn't anywhere anymore.
+pub struct Patcher {
+    buffer: Vec<u8>,
     // so right now i'm just going for simplicity rather than speed, so i just need a method that works for these patches
     // one ideal thing to do is to maintain another Vec with a Vec of indexes that is in the original file
     // really bad in terms of performance, *but* it is simple for finding the index something should be at
+    indexes: Vec<Vec<usize>>,
+    // where each byte of the buffer came from, which unlike `indexes` also knows which patch inserted a byte
+    origins: Vec<Origin>,
+    // which patch removed each byte of the base that's been removed, by where it was in the base
+    removed_by: HashMap<usize, usize>,
+    pending: Option<Pending>,
+    applied: usize,
+    warnings: Warnings,
+}
 
-    let mut indexes = Vec::with_capacity(file.source.len());
-    for i in 0..file.source.len() {
-        indexes.push(vec![i]);
+impl Patcher {
+    /// Prepares to patch the given base.
+    pub fn new(base: Vec<u8>) -> Self {
+        Patcher::reporting_to(base, Warnings::default())
     }
 
-    fn get_index(indexes: &Vec<Vec<usize>>, i: usize) -> usize {
-        for (idx, index) in indexes.iter().enumerate() {
-            if index.contains(&i) {
-                return idx;
-            }
+    fn reporting_to(base: Vec<u8>, warnings: Warnings) -> Self {
+        let indexes = (0..base.len()).map(|i| vec![i]).collect();
+        let origins = (0..base.len()).map(Origin::Base).collect();
+
+        Patcher {
+            buffer: base,
+            indexes,
+            origins,
+            removed_by: HashMap::new(),
+            pending: None,
+            applied: 0,
+            warnings,
         }
+    }
+
+    /// Applies the next patch. If this fails, the patcher shouldn't be used any further.
+    pub fn apply(&mut self, patch: AssuoPatch<Vec<u8>>) -> std::io::Result<()> {
+        let index = self.applied;
+        self.applied += 1;
 
-        panic!("assuo patch out of bounds?");
+        // errors say which patch they're about, by its label too if it has one
+        let label = patch.label().map(String::from);
+        self.apply_at(index, patch).map_err(|error| {
+            std::io::Error::new(
+                error.kind(),
+                format!("{}: {}", describe(index, label.as_deref()), error),
+            )
+        })
     }
 
-    // now, we apply each patch sequentially, maintaining the indexes vec as we go
-    for patch in patches {
+    fn apply_at(&mut self, index: usize, patch: AssuoPatch<Vec<u8>>) -> std::io::Result<()> {
+        let no_op = match &patch {
+            AssuoPatch::Insert { source, .. } => source.is_empty(),
+            AssuoPatch::Remove { count, .. } => *count == 0,
+            _ => false,
+        };
+
+        if no_op {
+            self.warnings.push(Warning::NoOpPatch { patch: index });
+        }
+
         match patch {
+            AssuoPatch::Insert {
+                way: Direction::At, ..
+            }
+            | AssuoPatch::RemoveUntil {
+                way: Direction::At, ..
+            } => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "only removals of a count can go 'at' a spot",
+                ))
+            }
             AssuoPatch::Insert { way, spot, source } => {
                 // So to visualize this algorithm, let's say we have the following string:
                 //
@@ -65,52 +942,764 @@ pub async fn do_patch(file: AssuoFile) -> std::io::Result<Vec<u8>> {
                 //
                 // Pre inserts will need to look for the "y" (which it is pointing at already) and
                 // insert before that.
-                let insertion_point = get_index(
-                    &indexes,
-                    match way {
-                        Direction::Post => spot - 1,
+                self.check_not_removed(spot)?;
+
+                // empty data (like the base of a file without a source) has no bytes to go before or after, so
+                // inserts at spot 0 of it go at the very start, and no other spot is anywhere
+                let insertion_point = if self.indexes.is_empty() {
+                    if spot != 0 {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            format!("targets spot {}, but the data is empty", spot),
+                        ));
+                    }
+
+                    0
+                } else {
+                    let before = match way {
+                        Direction::Post => spot.checked_sub(1).ok_or_else(|| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::InvalidInput,
+                                "post insert at spot 0 has no byte before it to go after",
+                            )
+                        })?,
                         Direction::Pre => spot,
-                    },
-                );
+                        Direction::At => unreachable!(),
+                    };
+
+                    let insertion_point =
+                        get_index(&self.indexes, before).ok_or_else(|| out_of_bounds(spot))?;
 
-                let insertion_point = match way {
-                    Direction::Post => insertion_point + 1,
-                    Direction::Pre => insertion_point,
+                    match way {
+                        Direction::Post => insertion_point + 1,
+                        Direction::Pre => insertion_point,
+                        Direction::At => unreachable!(),
+                    }
                 };
 
-                indexes.splice(
-                    insertion_point..insertion_point,
-                    (0..source.len()).map(|_| vec![std::usize::MAX]),
-                );
+                let origins = vec![Origin::Patch(index); source.len()];
+                match &mut self.pending {
+                    Some(group) if group.point == insertion_point => match way {
+                        Direction::Post => {
+                            group.bytes.splice(0..0, source);
+                            group.origins.splice(0..0, origins);
+                        }
+                        Direction::Pre => {
+                            group.bytes.extend(source);
+                            group.origins.extend(origins);
+                        }
+                        Direction::At => unreachable!(),
+                    },
+                    _ => {
+                        // once the group before us gets spliced in, everything after it shifts over
+                        let insertion_point = match &self.pending {
+                            Some(group) if group.point < insertion_point => {
+                                insertion_point + group.bytes.len()
+                            }
+                            _ => insertion_point,
+                        };
 
-                file.source.splice(insertion_point..insertion_point, source);
+                        self.flush();
+                        self.pending = Some(Pending {
+                            point: insertion_point,
+                            bytes: source,
+                            origins,
+                        });
+                    }
+                }
             }
+            // removing nothing doesn't need the spot to be anywhere in particular
+            AssuoPatch::Remove { count: 0, .. } => {}
             AssuoPatch::Remove { way, spot, count } => {
-                let insertion_point = get_index(&indexes, spot);
+                // removals need the buffer to be fully up to date
+                self.flush();
+
+                if self.indexes.is_empty() {
+                    return Err(empty_data());
+                }
+
+                // a post removal starts after the byte at the spot, and the others start or stop right at it
+                self.check_not_removed(match way {
+                    Direction::Post => spot.saturating_add(1),
+                    Direction::Pre | Direction::At => spot,
+                })?;
+
+                let at = get_index(&self.indexes, spot).ok_or_else(|| out_of_bounds(spot))?;
 
-                let insertion_point = match way {
-                    Direction::Post => insertion_point + 1,
-                    Direction::Pre => insertion_point - count,
+                // there may not be `count` bytes left on that side of the spot, since earlier patches can remove some
+                let start = match way {
+                    Direction::Post if self.buffer.len() - (at + 1) >= count => at + 1,
+                    Direction::Pre if at >= count => at - count,
+                    Direction::At if self.buffer.len() - at >= count => at,
+                    _ => {
+                        let (side, end, left) = match way {
+                            Direction::Post => ("after", "end", self.buffer.len() - (at + 1)),
+                            Direction::Pre => ("before", "start", at),
+                            Direction::At => ("from", "end", self.buffer.len() - at),
+                        };
+
+                        let removal =
+                            format!("{} removal of {} bytes {} spot {}", way, count, side, spot);
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            format!(
+                                "{} goes past the {} of the data, which only has {} bytes {} it",
+                                removal, end, left, side
+                            ),
+                        ));
+                    }
                 };
 
-                let fold = indexes[insertion_point..(insertion_point + count)]
-                    .iter()
-                    .fold(Vec::new(), |mut acc, elem| {
-                        for element in elem {
-                            if !acc.contains(element) {
-                                acc.push(*element);
-                            }
-                        }
-                        acc
-                    });
+                let removed = self.remove(start..(start + count));
+                self.record_removed(index, removed);
+            }
+            AssuoPatch::RemoveUntil {
+                way,
+                spot,
+                until,
+                to_end,
+            } => {
+                self.flush();
+
+                if until.is_empty() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "can't remove until an empty anchor",
+                    ));
+                }
+
+                if self.indexes.is_empty() {
+                    return Err(empty_data());
+                }
+
+                // a post removal starts at the byte at the spot, and a pre removal stops right before it
+                self.check_not_removed(spot)?;
+                let at = get_index(&self.indexes, spot).ok_or_else(|| out_of_bounds(spot))?;
+
+                let found = match way {
+                    Direction::Post => self.buffer[at..]
+                        .windows(until.len())
+                        .position(|window| window == until.as_slice())
+                        .map(|offset| at + offset),
+                    Direction::Pre => self.buffer[..at]
+                        .windows(until.len())
+                        .rposition(|window| window == until.as_slice())
+                        .map(|offset| offset + until.len()),
+                    Direction::At => unreachable!(),
+                };
+
+                let range = match (way, found) {
+                    (Direction::Post, Some(end)) => at..end,
+                    (Direction::Pre, Some(start)) => start..at,
+                    (Direction::Post, None) if to_end => at..self.buffer.len(),
+                    (Direction::Pre, None) if to_end => 0..at,
+                    (Direction::At, _) => unreachable!(),
+                    (_, None) => {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            format!(
+                                "couldn't find what to remove until, {} of spot {}",
+                                way, spot
+                            ),
+                        ))
+                    }
+                };
+
+                if range.is_empty() {
+                    self.warnings.push(Warning::NoOpPatch { patch: index });
+                }
+
+                let removed = self.remove(range);
+                self.record_removed(index, removed);
+            }
+            // captures were all taken before anything got patched, but one that comes after a removal of where it
+            // is can't be meant to be there
+            AssuoPatch::Capture { spot, .. } => self.check_not_removed(spot)?,
+            AssuoPatch::Labeled { patch, .. } => return self.apply_at(index, *patch),
+            AssuoPatch::Gated { cfg, patch } if cfg.matches() => {
+                return self.apply_at(index, *patch)
+            }
+            // patches for other targets are skipped
+            AssuoPatch::Gated { .. } => {}
+            AssuoPatch::InChars(_)
+            | AssuoPatch::FromEnd(_)
+            | AssuoPatch::Found { .. }
+            | AssuoPatch::Matched { .. } => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "every spot has to be turned into bytes from the start before patching",
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Makes room for `additional` more bytes, like the total of what the patches left to apply insert, so that
+    /// applying them doesn't have to grow the data over and over.
+    pub fn reserve(&mut self, additional: usize) {
+        self.buffer.reserve(additional);
+        self.indexes.reserve(additional);
+        self.origins.reserve(additional);
+    }
+
+    /// What the data looks like with every patch so far applied.
+    pub fn buffer(&mut self) -> &[u8] {
+        self.flush();
+        &self.buffer
+    }
+
+    /// Where every byte of the data with every patch so far applied came from.
+    pub fn origins(&mut self) -> &[Origin] {
+        self.flush();
+        &self.origins
+    }
+
+    /// Takes every warning so far, like for patches that didn't change anything.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        self.warnings.take()
+    }
+
+    /// Finishes patching, returning the patched data.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.flush();
+        self.buffer
+    }
+
+    fn flush(&mut self) {
+        if let Some(Pending {
+            point,
+            bytes,
+            origins,
+        }) = self.pending.take()
+        {
+            self.indexes.splice(
+                point..point,
+                (0..bytes.len()).map(|_| vec![std::usize::MAX]),
+            );
+            self.origins.splice(point..point, origins);
+            self.buffer.splice(point..point, bytes);
+        }
+    }
+
+    // the spots that got removed still need to point somewhere, so they're folded into whatever is right after the
+    // removed range (or right before it, if nothing is after it). this keeps `indexes` the same length as the buffer.
+    // every spot that got removed (including ones folded in by earlier removals) is given back.
+    fn remove(&mut self, range: std::ops::Range<usize>) -> Vec<usize> {
+        let removed = self
+            .indexes
+            .drain(range.clone())
+            .flatten()
+            .collect::<Vec<_>>();
+        self.origins.drain(range.clone());
+        self.buffer.drain(range.clone());
+
+        let neighbour = if range.start < self.indexes.len() {
+            self.indexes.get_mut(range.start)
+        } else {
+            self.indexes.last_mut()
+        };
+
+        if let Some(neighbour) = neighbour {
+            neighbour.extend(removed.iter().copied());
+        }
+
+        removed
+    }
+
+    // if both bytes around a spot were removed by the same patch, the spot is somewhere in the middle of what it
+    // removed, and there's no telling where that's meant to be now
+    fn check_not_removed(&self, spot: usize) -> std::io::Result<()> {
+        if let (Some(before), Some(after)) = (
+            spot.checked_sub(1)
+                .and_then(|before| self.removed_by.get(&before)),
+            self.removed_by.get(&spot),
+        ) {
+            if before == after {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "targets spot {}, which was removed by patch #{}",
+                        spot, after
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // bytes that an earlier patch already removed are remembered as removed by that one
+    fn record_removed(&mut self, patch: usize, removed: Vec<usize>) {
+        for offset in removed {
+            if offset != usize::MAX {
+                self.removed_by.entry(offset).or_insert(patch);
+            }
+        }
+    }
+}
+
+// every spot of the data that's left has been removed, or there never were any, so there's nothing to remove from
+fn empty_data() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "can't remove anything from data that's empty",
+    )
+}
+
+/// How many bytes the patch inserts, if it gets applied at all.
+fn inserted_len(patch: &AssuoPatch<Vec<u8>>) -> usize {
+    match patch {
+        AssuoPatch::Insert { source, .. } => source.len(),
+        AssuoPatch::Labeled { patch, .. } => inserted_len(patch),
+        AssuoPatch::Gated { cfg, patch } if cfg.matches() => inserted_len(patch),
+        _ => 0,
+    }
+}
+
+fn get_index(indexes: &Vec<Vec<usize>>, i: usize) -> Option<usize> {
+    indexes.iter().position(|index| index.contains(&i))
+}
+
+// every spot of the original source is somewhere in `indexes`, even once it's removed, so one that isn't is past the end
+fn out_of_bounds(spot: usize) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("spot {} is past the end of the source", spot),
+    )
+}
+
+// inserts that land in the same gap of the buffer are coalesced into a single pending group, and only get
+// spliced in once a patch lands somewhere else. this saves us from shifting the tail of the buffer for every
+// single insert when a bunch of them target the same spot.
+//
+// the pending group is not present in `indexes` or `buffer` yet, it sits at the gap `point` (in terms of
+// the buffer *without* the group). if another insert lands in that same gap, we know exactly where it goes:
+//
+// - a post insert at that gap would've been placed right after the byte before the gap, so it goes in front
+//   of the group
+// - a pre insert at that gap would've been placed right before the byte after the gap, so it goes behind
+//   the group
+struct Pending {
+    point: usize,
+    bytes: Vec<u8>,
+    origins: Vec<Origin>,
+}
+
+/// Takes the range of every `capture` patch out of the original source, by name. These are what `captured` sources
+/// read from, and are taken for you when patching, so this is only needed to resolve sources on their own.
+pub fn take_captures<S: Clone>(
+    source: &[u8],
+    patches: &[AssuoPatch<S>],
+) -> std::io::Result<HashMap<String, Vec<u8>>> {
+    let mut captures = HashMap::new();
+
+    for mut patch in patches {
+        // labels, and gates for this target, don't change what's captured
+        loop {
+            patch = match patch {
+                AssuoPatch::Labeled { patch, .. } => patch,
+                AssuoPatch::Gated { cfg, patch } if cfg.matches() => patch,
+                _ => break,
+            };
+        }
+
+        let in_bytes_;
+        let patch = match patch {
+            AssuoPatch::InChars(_) | AssuoPatch::FromEnd(_) => {
+                in_bytes_ = in_bytes(patch.clone(), source)?;
+                &in_bytes_
+            }
+            _ => patch,
+        };
+
+        if let AssuoPatch::Capture { name, spot, count } = patch {
+            let bytes = spot
+                .checked_add(*count)
+                .and_then(|end| source.get(*spot..end))
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "capture '{}' of {} bytes at spot {} goes past the end of the source",
+                            name, count, spot
+                        ),
+                    )
+                })?;
+
+            if captures.insert(name.clone(), bytes.to_vec()).is_some() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("'{}' is captured more than once", name),
+                ));
+            }
+        }
+    }
+
+    Ok(captures)
+}
+
+/// Turns a patch with a spot that's found into the same patch with the spot where what it finds (or what its regex
+/// matches) starts in the original source. That has to be in it exactly once. `what` names the patch. Patches with
+/// spots of their own are left alone.
+pub(crate) async fn locate(
+    patch: AssuoPatch,
+    source: &[u8],
+    options: &PatchOptions,
+    what: &str,
+) -> std::io::Result<AssuoPatch> {
+    let invalid = |reason: String| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("couldn't find the spot of {}, {}", what, reason),
+        )
+    };
+
+    if let Some(regex) = patch.regex() {
+        let spot = match_regex(regex, source).map_err(invalid)?;
+        return Ok(found_at(patch, spot));
+    }
+
+    let find = match patch.find() {
+        Some(find) => find.clone(),
+        None => return Ok(patch),
+    };
+
+    let find = resolve_in_time(find, options, &format!("what {} finds", what)).await?;
+
+    if find.is_empty() {
+        return Err(invalid(String::from("since what it finds is empty")));
+    }
+
+    let mut found = source
+        .windows(find.len())
+        .enumerate()
+        .filter(|(_, window)| *window == find.as_slice())
+        .map(|(spot, _)| spot);
+
+    match (found.next(), found.count()) {
+        (Some(spot), 0) => Ok(found_at(patch, spot)),
+        (None, _) => Err(invalid(String::from(
+            "since what it finds isn't in the source",
+        ))),
+        (Some(_), more) => Err(invalid(format!(
+            "since what it finds is in the source {} times, rather than once",
+            more + 1
+        ))),
+    }
+}
+
+/// Where the only match of the regex in the source starts, or why there isn't one.
+#[cfg(feature = "regex")]
+fn match_regex(regex: &str, source: &[u8]) -> Result<usize, String> {
+    let regex = regex::bytes::Regex::new(regex)
+        .map_err(|error| format!("since its regex is invalid: {}", error))?;
+
+    let mut matches = regex.find_iter(source).map(|found| found.start());
+    match (matches.next(), matches.count()) {
+        (Some(spot), 0) => Ok(spot),
+        (None, _) => Err(String::from("since its regex doesn't match the source")),
+        (Some(_), more) => Err(format!(
+            "since its regex matches the source {} times, rather than once",
+            more + 1
+        )),
+    }
+}
+
+#[cfg(not(feature = "regex"))]
+fn match_regex(_: &str, _: &[u8]) -> Result<usize, String> {
+    Err(String::from(
+        "since spots found with a regex need assuo to be built with the `regex` feature",
+    ))
+}
+
+/// Swaps out a spot that's found for the spot it was found at.
+fn found_at<S>(patch: AssuoPatch<S>, at: usize) -> AssuoPatch<S> {
+    match patch {
+        AssuoPatch::Insert { way, source, .. } => AssuoPatch::Insert {
+            way,
+            spot: at,
+            source,
+        },
+        AssuoPatch::Remove { way, count, .. } => AssuoPatch::Remove {
+            way,
+            spot: at,
+            count,
+        },
+        AssuoPatch::RemoveUntil {
+            way, until, to_end, ..
+        } => AssuoPatch::RemoveUntil {
+            way,
+            spot: at,
+            until,
+            to_end,
+        },
+        AssuoPatch::Capture { name, count, .. } => AssuoPatch::Capture {
+            name,
+            spot: at,
+            count,
+        },
+        AssuoPatch::Found { patch, .. } | AssuoPatch::Matched { patch, .. } => found_at(*patch, at),
+        AssuoPatch::InChars(patch) => AssuoPatch::InChars(Box::new(found_at(*patch, at))),
+        AssuoPatch::FromEnd(patch) => AssuoPatch::FromEnd(Box::new(found_at(*patch, at))),
+        AssuoPatch::Labeled { label, patch } => AssuoPatch::Labeled {
+            label,
+            patch: Box::new(found_at(*patch, at)),
+        },
+        AssuoPatch::Gated { cfg, patch } => AssuoPatch::Gated {
+            cfg,
+            patch: Box::new(found_at(*patch, at)),
+        },
+    }
+}
+
+/// Turns a patch measured in characters, or from the end, into the same patch measured in bytes from the start of the
+/// original source. Patches that are already measured in bytes from the start are left alone.
+pub(crate) fn in_bytes<S>(patch: AssuoPatch<S>, source: &[u8]) -> std::io::Result<AssuoPatch<S>> {
+    let patch = match patch {
+        AssuoPatch::InChars(patch) => *patch,
+        AssuoPatch::Labeled { label, patch } => {
+            return Ok(AssuoPatch::Labeled {
+                label,
+                patch: Box::new(in_bytes(*patch, source)?),
+            })
+        }
+        // patches for other targets are skipped, so their spots don't need to make sense here
+        AssuoPatch::Gated { cfg, patch } if cfg.matches() => {
+            return Ok(AssuoPatch::Gated {
+                cfg,
+                patch: Box::new(in_bytes(*patch, source)?),
+            })
+        }
+        AssuoPatch::FromEnd(patch) => {
+            return match *patch {
+                // counting back in characters needs to know how many characters there are, which is worked out below
+                AssuoPatch::InChars(patch) => in_bytes(
+                    AssuoPatch::InChars(Box::new(AssuoPatch::FromEnd(patch))),
+                    source,
+                ),
+                patch => in_bytes(from_start(patch, source.len())?, source),
+            };
+        }
+        patch => return Ok(patch),
+    };
+
+    let text = std::str::from_utf8(source).map_err(|error| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "spots measured in characters need the source to be UTF-8, but it isn't at byte {}",
+                error.valid_up_to()
+            ),
+        )
+    })?;
+
+    // the byte offset of every character, and then of the end of the source
+    let offsets = text
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(text.len()))
+        .collect::<Vec<_>>();
+
+    let patch = match patch {
+        AssuoPatch::FromEnd(patch) => from_start(*patch, offsets.len() - 1)?,
+        patch => patch,
+    };
+
+    let offset = |chars: usize| {
+        offsets.get(chars).copied().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "character {} is past the end of the source, which is {} characters long",
+                    chars,
+                    offsets.len() - 1
+                ),
+            )
+        })
+    };
+
+    Ok(match patch {
+        AssuoPatch::Insert { way, spot, source } => AssuoPatch::Insert {
+            way,
+            spot: offset(spot)?,
+            source,
+        },
+        // a post removal removes what's after the character at the spot, so the byte spot is the last byte of it
+        AssuoPatch::Remove {
+            way: Direction::Post,
+            spot,
+            count,
+        } => {
+            let start = offset(spot + 1)?;
+            AssuoPatch::Remove {
+                way: Direction::Post,
+                spot: start - 1,
+                count: offset(spot + 1 + count)? - start,
+            }
+        }
+        AssuoPatch::Remove {
+            way: Direction::Pre,
+            spot,
+            count,
+        } => {
+            let end = offset(spot)?;
+            let start = spot.checked_sub(count).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "pre removal of {} characters before character {} goes past the start of the source",
+                        count, spot
+                    ),
+                )
+            })?;
+
+            AssuoPatch::Remove {
+                way: Direction::Pre,
+                spot: end,
+                count: end - offset(start)?,
+            }
+        }
+        AssuoPatch::Remove {
+            way: Direction::At,
+            spot,
+            count,
+        } => {
+            let start = offset(spot)?;
+            AssuoPatch::Remove {
+                way: Direction::At,
+                spot: start,
+                count: offset(spot + count)? - start,
+            }
+        }
+        AssuoPatch::RemoveUntil {
+            way,
+            spot,
+            until,
+            to_end,
+        } => AssuoPatch::RemoveUntil {
+            way,
+            spot: offset(spot)?,
+            until,
+            to_end,
+        },
+        AssuoPatch::Capture { name, spot, count } => {
+            let start = offset(spot)?;
+            AssuoPatch::Capture {
+                name,
+                spot: start,
+                count: offset(spot + count)? - start,
+            }
+        }
+        patch @ AssuoPatch::InChars(_) => in_bytes(patch, source)?,
+        patch @ AssuoPatch::FromEnd(_) => in_bytes(AssuoPatch::InChars(Box::new(patch)), source)?,
+        AssuoPatch::Found { find, patch } => AssuoPatch::Found {
+            find,
+            patch: Box::new(in_bytes(AssuoPatch::InChars(patch), source)?),
+        },
+        AssuoPatch::Matched { regex, patch } => AssuoPatch::Matched {
+            regex,
+            patch: Box::new(in_bytes(AssuoPatch::InChars(patch), source)?),
+        },
+        AssuoPatch::Gated { cfg, patch } => AssuoPatch::Gated {
+            cfg,
+            patch: Box::new(in_bytes(AssuoPatch::InChars(patch), source)?),
+        },
+        AssuoPatch::Labeled { label, patch } => AssuoPatch::Labeled {
+            label,
+            patch: Box::new(in_bytes(AssuoPatch::InChars(patch), source)?),
+        },
+    })
+}
+
+/// Counts the spot of a patch forward from the start of a source `len` long, rather than back from the end.
+fn from_start<S>(patch: AssuoPatch<S>, len: usize) -> std::io::Result<AssuoPatch<S>> {
+    let forward = |spot: usize| {
+        len.checked_sub(spot).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "spot end-{} is before the start of the source, which is {} long",
+                    spot, len
+                ),
+            )
+        })
+    };
+
+    Ok(match patch {
+        AssuoPatch::Insert { way, spot, source } => AssuoPatch::Insert {
+            way,
+            spot: forward(spot)?,
+            source,
+        },
+        AssuoPatch::Remove { way, spot, count } => AssuoPatch::Remove {
+            way,
+            spot: forward(spot)?,
+            count,
+        },
+        AssuoPatch::RemoveUntil {
+            way,
+            spot,
+            until,
+            to_end,
+        } => AssuoPatch::RemoveUntil {
+            way,
+            spot: forward(spot)?,
+            until,
+            to_end,
+        },
+        AssuoPatch::Capture { name, spot, count } => AssuoPatch::Capture {
+            name,
+            spot: forward(spot)?,
+            count,
+        },
+        AssuoPatch::InChars(patch) => AssuoPatch::InChars(Box::new(from_start(*patch, len)?)),
+        AssuoPatch::FromEnd(patch) => AssuoPatch::FromEnd(Box::new(from_start(*patch, len)?)),
+        AssuoPatch::Found { find, patch } => AssuoPatch::Found {
+            find,
+            patch: Box::new(from_start(*patch, len)?),
+        },
+        AssuoPatch::Matched { regex, patch } => AssuoPatch::Matched {
+            regex,
+            patch: Box::new(from_start(*patch, len)?),
+        },
+        AssuoPatch::Gated { cfg, patch } => AssuoPatch::Gated {
+            cfg,
+            patch: Box::new(from_start(*patch, len)?),
+        },
+        AssuoPatch::Labeled { label, patch } => AssuoPatch::Labeled {
+            label,
+            patch: Box::new(from_start(*patch, len)?),
+        },
+    })
+}
+
+/// How a patch is named in errors, by its index in the order they are listed in, and its label if it has one.
+pub(crate) fn describe(index: usize, label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("patch #{} ('{}')", index, label),
+        None => format!("patch #{}", index),
+    }
+}
 
-                indexes.splice(insertion_point..(insertion_point + count), vec![fold]);
+/// Errors if two inserts target the exact same spot in the same direction, since which one ends up first depends
+/// entirely on the order they happen to be listed in.
+fn check_spots<'a>(patches: impl Iterator<Item = &'a AssuoPatch>) -> std::io::Result<()> {
+    let mut seen = std::collections::HashMap::new();
 
-                file.source
-                    .splice(insertion_point..(insertion_point + count), vec![]);
+    for (index, patch) in patches.enumerate() {
+        if let AssuoPatch::Insert { way, spot, .. } = patch.unlabeled() {
+            if let Some(first) = seen.insert((*way, *spot), index) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "patches #{} and #{} both insert {} at spot {}",
+                        first, index, way, spot
+                    ),
+                ));
             }
         }
     }
 
-    Ok(file.source)
+    Ok(())
 }
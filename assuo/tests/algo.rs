@@ -1,13 +1,28 @@
 //! Tests for the patching algorithm of `assuo`
 
 use assuo::{
-    models::{AssuoFile, AssuoPatch, AssuoSource, Direction},
-    patch::do_patch,
+    models::{
+        AssuoFile, AssuoPatch, AssuoSource, Direction, Endian, Footer, Resolvable, DEFAULT_TEMPLATE,
+    },
+    patch::{
+        do_patch, do_patch_into, do_patch_with, do_patch_with_options, patched_len, Origin,
+        PatchOptions, Patcher, PostProcessor, Provenance, Warning,
+    },
 };
 
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 
+/// A file of the source and patches, without anything else, which is what most of the tests here patch.
+fn file_with(source: AssuoSource, patches: Vec<AssuoPatch>) -> AssuoFile {
+    AssuoFile {
+        source,
+        patch: Some(patches),
+        expect_output_sha256: None,
+        footer: None,
+    }
+}
+
 /// This simple test ensures that an insert at a specific spot will insert the data there.
 /// It doesn't matter whether we have a pre insert or a post insert, since there is only one,
 /// it will insert itself at the `spot` exactly.
@@ -20,6 +35,8 @@ async fn single_insert_inserts_at_spot() -> Result<(), Box<dyn std::error::Error
             spot: "Hello".len(),
             source: AssuoSource::Text(String::from(", World")),
         }]),
+        expect_output_sha256: None,
+        footer: None,
     };
 
     let patched = do_patch(file).await?;
@@ -33,6 +50,8 @@ async fn single_insert_inserts_at_spot() -> Result<(), Box<dyn std::error::Error
             spot: "Hello".len(),
             source: AssuoSource::Text(String::from(", World")),
         }]),
+        expect_output_sha256: None,
+        footer: None,
     };
 
     let patched = do_patch(file).await?;
@@ -59,6 +78,8 @@ async fn two_post_inserts_insert_in_order() -> Result<(), Box<dyn std::error::Er
                 source: AssuoSource::Text(String::from(", ")),
             },
         ]),
+        expect_output_sha256: None,
+        footer: None,
     };
 
     let patched = do_patch(file).await?;
@@ -85,6 +106,8 @@ async fn two_pre_inserts_insert_in_order() -> Result<(), Box<dyn std::error::Err
                 source: AssuoSource::Text(String::from("World")),
             },
         ]),
+        expect_output_sha256: None,
+        footer: None,
     };
 
     let patched = do_patch(file).await?;
@@ -145,6 +168,8 @@ async fn inserts_are_relative_to_original_source_randomized(
         let file = AssuoFile {
             source: AssuoSource::Text(String::from("Hlo ol!")),
             patch: Some(patches),
+            expect_output_sha256: None,
+            footer: None,
         };
 
         let source = format!("{:?}", file);
@@ -156,6 +181,67 @@ async fn inserts_are_relative_to_original_source_randomized(
     Ok(())
 }
 
+/// Inserts that land on the same spot get grouped together before being spliced in. This makes sure that the
+/// grouping doesn't change the result, by comparing against a naive model where every gap in the source keeps
+/// its own list: post inserts go to the front of it, pre inserts go to the back of it.
+///
+/// The spots, directions and order are completely randomized as to try catch errors.
+#[tokio::test]
+async fn grouped_inserts_match_sequential_inserts_randomized(
+) -> Result<(), Box<dyn std::error::Error>> {
+    use rand::Rng;
+
+    let base = "abcdef";
+    let mut rng = thread_rng();
+
+    for _ in 0..1000 {
+        let mut gaps = vec![std::collections::VecDeque::new(); base.len() + 1];
+        let mut patches = Vec::new();
+
+        for n in 0..rng.gen_range(1, 12) {
+            let text = format!("<{}>", n);
+
+            if rng.gen() {
+                let spot = rng.gen_range(1, base.len() + 1);
+                gaps[spot].push_front(text.clone());
+                patches.push(AssuoPatch::Insert {
+                    way: Direction::Post,
+                    spot,
+                    source: AssuoSource::Text(text),
+                });
+            } else {
+                let spot = rng.gen_range(0, base.len());
+                gaps[spot].push_back(text.clone());
+                patches.push(AssuoPatch::Insert {
+                    way: Direction::Pre,
+                    spot,
+                    source: AssuoSource::Text(text),
+                });
+            }
+        }
+
+        let mut expected = String::new();
+        for (spot, gap) in gaps.iter().enumerate() {
+            expected.extend(gap.iter().map(String::as_str));
+            expected.extend(base.get(spot..spot + 1));
+        }
+
+        let file = AssuoFile {
+            source: AssuoSource::Text(String::from(base)),
+            patch: Some(patches),
+            expect_output_sha256: None,
+            footer: None,
+        };
+
+        let source = format!("{:?}", file);
+        let patched = do_patch(file).await?;
+
+        assert_eq!(&patched, &expected.as_bytes(), "{}", source);
+    }
+
+    Ok(())
+}
+
 /// It shouldn't matter which order we have these pre and post inserts in, because the spot they insert at
 /// is relative to the original document, and them being pre and post inserts should make the order clear.
 #[tokio::test]
@@ -174,6 +260,8 @@ async fn mixed_pre_and_post_inserts_are_in_order() -> Result<(), Box<dyn std::er
                 source: AssuoSource::Text(String::from(", ")),
             },
         ]),
+        expect_output_sha256: None,
+        footer: None,
     };
 
     let patched = do_patch(file).await?;
@@ -194,6 +282,8 @@ async fn mixed_pre_and_post_inserts_are_in_order() -> Result<(), Box<dyn std::er
                 source: AssuoSource::Text(String::from("World")),
             },
         ]),
+        expect_output_sha256: None,
+        footer: None,
     };
 
     let patched = do_patch(file).await?;
@@ -202,6 +292,449 @@ async fn mixed_pre_and_post_inserts_are_in_order() -> Result<(), Box<dyn std::er
     Ok(())
 }
 
+/// Resolving consumes a config, so resolving the same config twice means cloning it first. Both runs should give the
+/// exact same bytes.
+#[tokio::test]
+async fn cloned_configs_patch_the_same() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assuo::models::try_parse(
+        r#"
+[source]
+template = { text = "Hello, ${NAME}!", vars = { NAME = "World" } }
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = 5
+source = { xor = { of = { text = "," }, key = [0] } }
+
+[[patch]]
+do = "remove"
+way = "post"
+spot = 6
+count = 1
+"#,
+    )?;
+
+    let first = do_patch(file.clone()).await?;
+    let second = do_patch(file).await?;
+
+    assert_eq!(first, second);
+    Ok(())
+}
+
+/// Patching into the same buffer twice should give the same results as patching into new buffers, without anything
+/// from the first patch being left over.
+#[tokio::test]
+async fn patching_into_a_reused_buffer_matches_do_patch() -> Result<(), Box<dyn std::error::Error>>
+{
+    let long = assuo::models::try_parse(
+        r#"
+[source]
+text = "Hello, World! This is long."
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { text = " there" }
+"#,
+    )?;
+
+    let short = assuo::models::try_parse(
+        r#"
+[source]
+text = "Hi"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 2
+source = { text = "!" }
+"#,
+    )?;
+
+    let mut buffer = Vec::new();
+
+    do_patch_into(long.clone(), &mut buffer).await?;
+    assert_eq!(buffer, do_patch(long).await?);
+
+    do_patch_into(short.clone(), &mut buffer).await?;
+    assert_eq!(buffer, do_patch(short).await?);
+    assert_eq!(&buffer, &"Hi!".as_bytes());
+    Ok(())
+}
+
+#[tokio::test]
+async fn post_processor_runs_over_the_output() -> Result<(), Box<dyn std::error::Error>> {
+    let file = AssuoFile {
+        source: AssuoSource::Text(String::from("Hello!")),
+        patch: Some(vec![AssuoPatch::Insert {
+            way: Direction::Post,
+            spot: 5,
+            source: AssuoSource::Text(String::from(", World")),
+        }]),
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    let uppercase: PostProcessor = Box::new(|mut output| {
+        output.make_ascii_uppercase();
+        Ok(output)
+    });
+
+    let (patched, _) =
+        do_patch_with(file.clone(), &PatchOptions::default(), Some(uppercase)).await?;
+    assert_eq!(&patched, &"HELLO, WORLD!".as_bytes());
+
+    let (patched, _) = do_patch_with(file, &PatchOptions::default(), None).await?;
+    assert_eq!(&patched, &"Hello, World!".as_bytes());
+    Ok(())
+}
+
+#[tokio::test]
+async fn patches_that_change_nothing_are_warned_about() -> Result<(), Box<dyn std::error::Error>> {
+    let file = AssuoFile {
+        source: AssuoSource::Text(String::from("Hello!")),
+        patch: Some(vec![
+            AssuoPatch::Insert {
+                way: Direction::Post,
+                spot: 5,
+                source: AssuoSource::Text(String::from(", World")),
+            },
+            AssuoPatch::Insert {
+                way: Direction::Pre,
+                spot: 0,
+                source: AssuoSource::Text(String::new()),
+            },
+            AssuoPatch::Remove {
+                way: Direction::Post,
+                spot: 0,
+                count: 0,
+            },
+        ]),
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    let (patched, warnings) = do_patch_with(file, &PatchOptions::default(), None).await?;
+    assert_eq!(&patched, &"Hello, World!".as_bytes());
+    assert_eq!(
+        warnings,
+        vec![
+            Warning::NoOpPatch { patch: 1 },
+            Warning::NoOpPatch { patch: 2 }
+        ]
+    );
+    Ok(())
+}
+
+/// Linting should find every out of bounds patch at once, and point back at which patch it was.
+#[tokio::test]
+async fn lint_bounds_reports_every_out_of_bounds_patch() -> Result<(), Box<dyn std::error::Error>> {
+    let file = AssuoFile {
+        source: AssuoSource::Text(String::from("Hello!")),
+        patch: Some(vec![
+            AssuoPatch::Insert {
+                way: Direction::Post,
+                spot: 99,
+                source: AssuoSource::Text(String::from(", World")),
+            },
+            AssuoPatch::Insert {
+                way: Direction::Post,
+                spot: "Hello".len(),
+                source: AssuoSource::Text(String::from(", World")),
+            },
+            AssuoPatch::Remove {
+                way: Direction::Pre,
+                spot: 2,
+                count: 3,
+            },
+            AssuoPatch::Remove {
+                way: Direction::At,
+                spot: 4,
+                count: 2,
+            },
+            AssuoPatch::Remove {
+                way: Direction::At,
+                spot: 4,
+                count: 3,
+            },
+        ]),
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    let findings = file.lint_bounds(&PatchOptions::default()).await?;
+
+    assert_eq!(
+        findings
+            .iter()
+            .map(|finding| finding.patch)
+            .collect::<Vec<_>>(),
+        vec![0, 2, 4]
+    );
+    Ok(())
+}
+
+/// A file without a source starts from no bytes, which inserts at spot 0 go onto either way, so linting doesn't flag
+/// them. Anything past that is still out of bounds.
+#[tokio::test]
+async fn lint_bounds_takes_inserts_at_the_start_of_no_source(
+) -> Result<(), Box<dyn std::error::Error>> {
+    for way in &["pre", "post"] {
+        let file = assuo::models::try_parse(&format!(
+            "[[patch]]\ndo = \"insert\"\nway = \"{}\"\nspot = 0\nsource = {{ text = \"x\" }}\n",
+            way
+        ))?;
+
+        assert!(file.lint_bounds(&PatchOptions::default()).await?.is_empty());
+        assert_eq!(&do_patch(file).await?, &"x".as_bytes());
+    }
+
+    let file = assuo::models::try_parse(
+        "[[patch]]\ndo = \"insert\"\nway = \"pre\"\nspot = 1\nsource = { text = \"x\" }\n",
+    )?;
+    assert_eq!(file.lint_bounds(&PatchOptions::default()).await?.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn labels_name_patches_in_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assuo::models::try_parse(
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+label = "say who"
+do = "insert"
+way = "post"
+spot = 5
+source = { text = ", World" }
+
+[[patch]]
+label = "drop the greeting"
+do = "remove"
+way = "pre"
+spot = 2
+count = 3
+"#,
+    )?;
+
+    let findings = file.lint_bounds(&PatchOptions::default()).await?;
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].label.as_deref(), Some("drop the greeting"));
+    assert!(
+        findings[0]
+            .to_string()
+            .starts_with("patch #1 ('drop the greeting'): pre removal"),
+        "{}",
+        findings[0]
+    );
+
+    let error = do_patch(file).await.unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .starts_with("patch #1 ('drop the greeting'): pre removal of 3 bytes"),
+        "{}",
+        error
+    );
+
+    // labels don't change what the patch does
+    helper(
+        "Hello, World!",
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+label = "say who"
+do = "insert"
+way = "post"
+spot = 5
+source = { text = ", World" }
+"#,
+    )
+    .await
+}
+
+/// Two inserts at the same spot and direction are applied in order by default, but are ambiguous under strict
+/// spots. Inserts at the same spot in different directions are never ambiguous.
+#[tokio::test]
+async fn same_spot_inserts_only_error_under_strict_spots() -> Result<(), Box<dyn std::error::Error>>
+{
+    let file = || {
+        file_with(
+            AssuoSource::Text(String::from("Hello!")),
+            vec![
+                AssuoPatch::Insert {
+                    way: Direction::Post,
+                    spot: "Hello".len(),
+                    source: AssuoSource::Text(String::from("World")),
+                },
+                AssuoPatch::Insert {
+                    way: Direction::Pre,
+                    spot: "Hello".len(),
+                    source: AssuoSource::Text(String::from("!")),
+                },
+                AssuoPatch::Insert {
+                    way: Direction::Post,
+                    spot: "Hello".len(),
+                    source: AssuoSource::Text(String::from(", ")),
+                },
+            ],
+        )
+    };
+
+    let strict = PatchOptions {
+        strict_spots: true,
+        ..PatchOptions::default()
+    };
+
+    let patched = do_patch_with_options(file(), &PatchOptions::default()).await?;
+    assert_eq!(&patched, &"Hello, World!!".as_bytes());
+
+    let error = do_patch_with_options(file(), &strict).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(error.to_string().contains("#0 and #2"), "{}", error);
+    Ok(())
+}
+
+/// Sorting patches by their spots doesn't change what they patch to, and patches at the same spot stay in the order
+/// they are listed in.
+#[tokio::test]
+async fn sorted_patches_are_applied_in_order_of_their_spots(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let insert = |way, spot, text: &str| AssuoPatch::Insert {
+        way,
+        spot,
+        source: AssuoSource::Text(String::from(text)),
+    };
+
+    let sorted = PatchOptions {
+        sort_patches: true,
+        ..PatchOptions::default()
+    };
+
+    let file = |patch| file_with(AssuoSource::Text(String::from("Hello!")), patch);
+
+    let patches = || {
+        vec![
+            insert(Direction::Post, "Hello".len(), ", World"),
+            AssuoPatch::Remove {
+                way: Direction::At,
+                spot: 1,
+                count: 1,
+            },
+            insert(Direction::Pre, 0, "> "),
+        ]
+    };
+
+    let unsorted = do_patch_with_options(file(patches()), &PatchOptions::default()).await?;
+    assert_eq!(&unsorted, &"> Hllo, World!".as_bytes());
+
+    let patched = do_patch_with_options(file(patches()), &sorted).await?;
+    assert_eq!(patched, unsorted);
+
+    // inserts at the same spot in the same direction are applied in the order they are listed in either way
+    let colliding = || {
+        vec![
+            insert(Direction::Post, "Hello".len(), "World"),
+            insert(Direction::Pre, 0, "> "),
+            insert(Direction::Post, "Hello".len(), ", "),
+        ]
+    };
+
+    let patched = do_patch_with_options(file(colliding()), &sorted).await?;
+    assert_eq!(&patched, &"> Hello, World!".as_bytes());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn provenance_says_which_patch_inserted_each_byte() -> Result<(), Box<dyn std::error::Error>>
+{
+    let file = AssuoFile {
+        source: AssuoSource::Text(String::from("ab")),
+        patch: Some(vec![
+            AssuoPatch::Insert {
+                way: Direction::Post,
+                spot: 1,
+                source: AssuoSource::Text(String::from("X")),
+            },
+            AssuoPatch::Remove {
+                way: Direction::At,
+                spot: 1,
+                count: 1,
+            },
+            AssuoPatch::Insert {
+                way: Direction::Pre,
+                spot: 1,
+                source: AssuoSource::Text(String::from("YZ")),
+            },
+            AssuoPatch::Insert {
+                way: Direction::Pre,
+                spot: 0,
+                source: AssuoSource::Text(String::from(">")),
+            },
+        ]),
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    let options = PatchOptions {
+        provenance: Some(Provenance::default()),
+        ..PatchOptions::default()
+    };
+
+    let uppercase: PostProcessor = Box::new(|output| Ok(output.to_ascii_uppercase()));
+    let (patched, _) = do_patch_with(file, &options, Some(uppercase)).await?;
+    assert_eq!(&patched, &">AYZX".as_bytes());
+
+    // the bytes explained are the ones before they're post processed, which are just as many
+    assert_eq!(
+        options.provenance.unwrap().take(),
+        vec![
+            Origin::Patch(3),
+            Origin::Base(0),
+            Origin::Patch(2),
+            Origin::Patch(2),
+            Origin::Patch(0),
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn files_without_a_source_patch_onto_nothing() -> Result<(), Box<dyn std::error::Error>> {
+    let file = |patch| file_with(AssuoSource::default(), vec![patch]);
+
+    let append = AssuoPatch::Insert {
+        way: Direction::Post,
+        spot: 0,
+        source: AssuoSource::Text(String::from("appended")),
+    };
+    assert_eq!(&do_patch(file(append)).await?, &"appended".as_bytes());
+
+    // there's no other spot in nothing, and nothing to remove from it
+    let past_the_end = AssuoPatch::Insert {
+        way: Direction::Pre,
+        spot: 1,
+        source: AssuoSource::Text(String::from("appended")),
+    };
+    let error = do_patch(file(past_the_end)).await.unwrap_err();
+    assert!(error.to_string().contains("the data is empty"), "{}", error);
+
+    let remove = AssuoPatch::Remove {
+        way: Direction::At,
+        spot: 0,
+        count: 1,
+    };
+    assert!(do_patch(file(remove)).await.is_err());
+    Ok(())
+}
+
 // == CODE SAMPLE TESTS ==
 // if there is an assuo config file on the documentation, it should be copied here to ensure it works.
 // old tests shouldn't get removed (unless there is a `major` version upgrade) to ensure no regressions.
@@ -216,6 +749,12 @@ async fn helper<B: AsRef<[u8]>>(
     Ok(())
 }
 
+/// `assuo --init` prints this, so it should always be something the parser takes, and do what it says it does.
+#[tokio::test]
+async fn default_template_says_hello_world() -> Result<(), Box<dyn std::error::Error>> {
+    helper("Hello, World!", DEFAULT_TEMPLATE).await
+}
+
 #[tokio::test]
 async fn readme_1() -> Result<(), Box<dyn std::error::Error>> {
     helper(
@@ -270,53 +809,98 @@ bytes = [1, 2, 3, 4]
 }
 
 #[tokio::test]
-async fn readme_3() -> Result<(), Box<dyn std::error::Error>> {
+async fn readme_2_mixed() -> Result<(), Box<dyn std::error::Error>> {
     helper(
         "Hello!",
         r#"
 [source]
-text = "Hello!"
+bytes = [72, "ello", 33]
 "#,
     )
     .await
 }
 
-// TODO: test loading files
-// TODO: test loading URL
-// TODO: test loading assuo files
-// TODO: test loading assuo URL
-
 #[tokio::test]
-async fn readme_8() -> Result<(), Box<dyn std::error::Error>> {
+async fn readme_2_repeat() -> Result<(), Box<dyn std::error::Error>> {
     helper(
-        ">ba<",
+        [0, 255, 0, 255, 0, 255, 0, 1],
         r#"
 [source]
-text = "><"
-
-[[patch]]
-do = "insert"
-way = "post"
-spot = 1
-source = { text = "a" }
-
-[[patch]]
-do = "insert"
-way = "post"
-spot = 1
-source = { text = "b" }
+bytes = [0, [3, [255, 0]], 1]
 "#,
     )
     .await
 }
 
 #[tokio::test]
-async fn readme_9() -> Result<(), Box<dyn std::error::Error>> {
+async fn readme_3() -> Result<(), Box<dyn std::error::Error>> {
     helper(
-        ">bac<",
+        "Hello!",
         r#"
 [source]
-text = "><"
+text = "Hello!"
+"#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn readme_concat() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "Hello, World!",
+        r#"
+[[source]]
+text = "Hello"
+
+[[source]]
+text = "World!"
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = 5
+source = { text = ", " }
+"#,
+    )
+    .await
+}
+
+// TODO: test loading files
+// TODO: test loading URL
+// TODO: test loading assuo files
+// TODO: test loading assuo URL
+
+#[tokio::test]
+async fn readme_8() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        ">ba<",
+        r#"
+[source]
+text = "><"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 1
+source = { text = "a" }
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 1
+source = { text = "b" }
+"#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn readme_9() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        ">bac<",
+        r#"
+[source]
+text = "><"
 
 [[patch]]
 do = "insert"
@@ -340,5 +924,1179 @@ source = { text = "c" }
     .await
 }
 
-// == PREVENTING REGRESSION TESTS ==
-// if there is an issue posted, a test should be placed after this point to ensure that there will be no regression in the future
+/// Removing until a newline removes the rest of the line (or the start of it, for pre removals), but keeps the
+/// newline itself. Spots after the removal still refer to the original source.
+#[tokio::test]
+async fn remove_until_removes_up_to_the_anchor() -> Result<(), Box<dyn std::error::Error>> {
+    let remove_until = |way, spot, to_end| {
+        file_with(
+            AssuoSource::Text(String::from("key = value\nnext = line")),
+            vec![AssuoPatch::RemoveUntil {
+                way,
+                spot,
+                until: AssuoSource::Text(String::from("\n")),
+                to_end,
+            }],
+        )
+    };
+
+    let mut file = remove_until(Direction::Post, 3, false);
+    file.patch.as_mut().unwrap().push(AssuoPatch::Insert {
+        way: Direction::Pre,
+        spot: 12,
+        source: AssuoSource::Text(String::from("# ")),
+    });
+    assert_eq!(&do_patch(file).await?, &"key\n# next = line".as_bytes());
+
+    let patched = do_patch(remove_until(Direction::Pre, 16, false)).await?;
+    assert_eq!(&patched, &"key = value\n = line".as_bytes());
+
+    // there's no newline to stop at, so it's only removed when asked to remove to the end (or start)
+    assert!(do_patch(remove_until(Direction::Post, 16, false))
+        .await
+        .is_err());
+    assert!(do_patch(remove_until(Direction::Pre, 4, false))
+        .await
+        .is_err());
+
+    let patched = do_patch(remove_until(Direction::Post, 16, true)).await?;
+    assert_eq!(&patched, &"key = value\nnext".as_bytes());
+
+    let patched = do_patch(remove_until(Direction::Pre, 4, true)).await?;
+    assert_eq!(&patched, &"= value\nnext = line".as_bytes());
+    Ok(())
+}
+
+#[tokio::test]
+async fn readme_remove_until() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "key\nnext = line",
+        r#"
+[source]
+text = "key = value\nnext = line"
+
+[[patch]]
+do = "remove"
+way = "post"
+spot = 3
+until = { text = "\n" }
+"#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn captured_regions_can_be_inserted_elsewhere() -> Result<(), Box<dyn std::error::Error>> {
+    let file = AssuoFile {
+        source: AssuoSource::Text(String::from("Hello, World!")),
+        patch: Some(vec![
+            // the capture comes from the original source, so removing it first doesn't matter
+            AssuoPatch::Remove {
+                way: Direction::Post,
+                spot: 6,
+                count: 5,
+            },
+            AssuoPatch::Insert {
+                way: Direction::Pre,
+                spot: 0,
+                source: AssuoSource::Captured(String::from("who")),
+            },
+            AssuoPatch::Capture {
+                name: String::from("who"),
+                spot: 7,
+                count: 5,
+            },
+        ]),
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    assert_eq!(&do_patch(file).await?, &"WorldHello, !".as_bytes());
+
+    let missing = AssuoFile {
+        source: AssuoSource::Text(String::from("Hello, World!")),
+        patch: Some(vec![AssuoPatch::Insert {
+            way: Direction::Pre,
+            spot: 0,
+            source: AssuoSource::Captured(String::from("who")),
+        }]),
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    assert!(do_patch(missing).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn wrapped_captures_are_taken_too() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "WorldHello, World!",
+        &format!(
+            r#"
+[source]
+text = "Hello, World!"
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = 0
+source = [{{ captured = "who" }}, {{ captured = "where" }}]
+
+[[patch]]
+do = "capture"
+name = "who"
+spot = 7
+count = 5
+label = "take who"
+
+[[patch]]
+do = "capture"
+name = "where"
+spot = 0
+count = 0
+label = "take where"
+cfg = {{ os = "{}" }}
+"#,
+            std::env::consts::OS
+        ),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn readme_capture() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "World says Hello, World!",
+        r#"
+[source]
+text = "Hello, World!"
+
+[[patch]]
+do = "capture"
+name = "who"
+spot = 7
+count = 5
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = 0
+source = [{ captured = "who" }, { text = " says " }]
+"#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn base_sources_read_the_original_source() -> Result<(), Box<dyn std::error::Error>> {
+    let file = AssuoFile {
+        source: AssuoSource::Text(String::from("Hello!")),
+        patch: Some(vec![
+            // the base is read from before anything gets patched, so removing the prefix first doesn't matter
+            AssuoPatch::Remove {
+                way: Direction::Pre,
+                spot: 5,
+                count: 5,
+            },
+            AssuoPatch::Insert {
+                way: Direction::Post,
+                spot: 6,
+                source: AssuoSource::Base { start: 0, end: 5 },
+            },
+        ]),
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    assert_eq!(&do_patch(file).await?, &"!Hello".as_bytes());
+
+    let past_the_end = AssuoFile {
+        source: AssuoSource::Text(String::from("Hello!")),
+        patch: Some(vec![AssuoPatch::Insert {
+            way: Direction::Post,
+            spot: 6,
+            source: AssuoSource::Base { start: 3, end: 7 },
+        }]),
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    assert!(do_patch(past_the_end).await.is_err());
+
+    // the base can't be its own source
+    let own_source = AssuoFile {
+        source: AssuoSource::Base { start: 0, end: 0 },
+        patch: None,
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    assert!(do_patch(own_source).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn readme_base() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "Hello, Hello!",
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = 5
+source = [{ text = ", " }, { base = { start = 0, end = 5 } }]
+"#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn spots_can_count_back_from_the_end() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "[Hello]!?",
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = "end"
+source = { text = "?" }
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = "end-1"
+source = { text = "]" }
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = "start+0"
+source = { text = "[" }
+"#,
+    )
+    .await?;
+
+    helper(
+        "Heo!",
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "remove"
+way = "post"
+spot = "start+1"
+count = 2
+"#,
+    )
+    .await?;
+
+    // in characters, the end is counted back in characters too
+    helper(
+        "héllo, wörld",
+        r#"
+[source]
+text = "héllo wörld"
+
+[[patch]]
+do = "insert"
+way = "post"
+unit = "char"
+spot = "end - 6"
+source = { text = "," }
+"#,
+    )
+    .await?;
+
+    let before_the_start = assuo::models::try_parse(
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = "end-7"
+source = { text = "?" }
+"#,
+    )?;
+
+    assert!(do_patch(before_the_start).await.is_err());
+
+    // spots that don't fit in the source are errors, rather than panics
+    for (way, spot) in &[
+        ("pre", "start+100"),
+        ("post", "start+100"),
+        ("post", "end-6"),
+    ] {
+        let out_of_bounds = assuo::models::try_parse(&format!(
+            r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "{}"
+spot = "{}"
+source = {{ text = "?" }}
+"#,
+            way, spot
+        ))?;
+
+        let error = do_patch(out_of_bounds).await.unwrap_err();
+        assert_eq!(
+            error.kind(),
+            std::io::ErrorKind::InvalidInput,
+            "{} {}",
+            way,
+            spot
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn readme_from_the_end() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "Hello!?",
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = "end"
+source = { text = "?" }
+"#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn found_spots_are_where_what_they_find_starts() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "<b>Hello</b>, World!",
+        r#"
+[source]
+text = "Hello, World!"
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = { find = { text = "Hello" } }
+source = { text = "<b>" }
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = { find = { bytes = [44] } }
+source = { text = "</b>" }
+"#,
+    )
+    .await?;
+
+    // anything can be found with one, like the start of a removal
+    helper(
+        "Hello!",
+        r#"
+[source]
+text = "Hello, World!"
+
+[[patch]]
+do = "remove"
+way = "at"
+label = "drop the name"
+spot = { find = { text = ", World" } }
+count = 7
+"#,
+    )
+    .await?;
+
+    let file = |find: &str| {
+        assuo::models::try_parse(&format!(
+            r#"
+[source]
+text = "Hello, World!"
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = {{ find = {{ text = "{}" }} }}
+source = {{ text = "?" }}
+"#,
+            find
+        ))
+    };
+
+    let error = do_patch(file("o")?).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(error.to_string().contains("2 times"), "{}", error);
+
+    let error = do_patch(file("Goodbye")?).await.unwrap_err();
+    assert!(
+        error.to_string().contains("isn't in the source"),
+        "{}",
+        error
+    );
+
+    assert!(do_patch(file("")?).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn readme_finding_a_spot() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "Hello, World!",
+        r#"
+[source]
+text = "Hello World!"
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = { find = { text = " World" } }
+source = { text = "," }
+"#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn gated_patches_only_apply_on_their_target() -> Result<(), Box<dyn std::error::Error>> {
+    let foreign = if std::env::consts::OS == "windows" {
+        "linux"
+    } else {
+        "windows"
+    };
+
+    // the patch for another os is skipped without fetching its source, which wouldn't resolve anyway
+    helper(
+        "Hello, World!",
+        &format!(
+            r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = {{ text = ", World" }}
+cfg = {{ os = "{}", arch = "{}" }}
+
+[[patch]]
+do = "remove"
+way = "pre"
+spot = 5
+count = 5
+label = "not here"
+cfg = {{ os = "{}" }}
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = {{ url = "http://127.0.0.1:1/" }}
+cfg = {{ os = "{}" }}
+"#,
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            foreign,
+            foreign
+        ),
+    )
+    .await?;
+
+    // every part of the target has to match
+    helper(
+        "Hello!",
+        &format!(
+            r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = {{ text = ", World" }}
+cfg = {{ os = "{}", arch = "not-an-arch" }}
+"#,
+            std::env::consts::OS
+        ),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn readme_patches_for_some_targets() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        if cfg!(windows) {
+            "Hello, World!"
+        } else {
+            "Hello!"
+        },
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { text = ", World" }
+cfg = { os = "windows" }
+"#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn char_spots_land_between_characters() -> Result<(), Box<dyn std::error::Error>> {
+    let in_chars =
+        |source: AssuoSource, patch| file_with(source, vec![AssuoPatch::InChars(Box::new(patch))]);
+
+    // a post insert goes after the character before the spot, which is the é at character 1 (bytes 1 and 2)
+    let file = in_chars(
+        AssuoSource::Text(String::from("héllo")),
+        AssuoPatch::Insert {
+            way: Direction::Post,
+            spot: 2,
+            source: AssuoSource::Text(String::from("!")),
+        },
+    );
+    assert_eq!(&do_patch(file).await?, &"hé!llo".as_bytes());
+
+    let file = in_chars(
+        AssuoSource::Text(String::from("héllo")),
+        AssuoPatch::Remove {
+            way: Direction::Pre,
+            spot: 3,
+            count: 2,
+        },
+    );
+    assert_eq!(&do_patch(file).await?, &"hlo".as_bytes());
+
+    let file = in_chars(
+        AssuoSource::Bytes(vec![b'h', 0xFF]),
+        AssuoPatch::Insert {
+            way: Direction::Pre,
+            spot: 1,
+            source: AssuoSource::Text(String::from("!")),
+        },
+    );
+    let error = do_patch(file).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    Ok(())
+}
+
+#[tokio::test]
+async fn readme_unit_char() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "héllo, wörld",
+        r#"
+[source]
+text = "héllo wörld"
+
+[[patch]]
+do = "insert"
+way = "post"
+unit = "char"
+spot = 5
+source = { text = "," }
+"#,
+    )
+    .await
+}
+
+// == PREVENTING REGRESSION TESTS ==
+// if there is an issue posted, a test should be placed after this point to ensure that there will be no regression in the future
+
+/// Removing bytes used to leave an extra entry behind in the index map, which pushed every patch after it one byte
+/// too far along.
+#[tokio::test]
+async fn patches_after_a_removal_land_in_the_right_spot() -> Result<(), Box<dyn std::error::Error>>
+{
+    helper(
+        "acXdYef",
+        r#"
+[source]
+text = "abcdef"
+
+[[patch]]
+do = "remove"
+way = "post"
+spot = 0
+count = 1
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 3
+source = { text = "X" }
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = 4
+source = { text = "Y" }
+"#,
+    )
+    .await
+}
+
+/// A spot in the middle of a range that an earlier patch removed isn't anywhere anymore, so inserting, removing or
+/// capturing there is an error that says which patch removed it. The spots at either edge of the range are still fine.
+#[tokio::test]
+async fn patching_inside_a_removed_range_is_an_error() -> Result<(), Box<dyn std::error::Error>> {
+    let file = |patch| {
+        file_with(
+            AssuoSource::Text(String::from("abcdef")),
+            vec![
+                AssuoPatch::Insert {
+                    way: Direction::Pre,
+                    spot: 0,
+                    source: AssuoSource::Text(String::from(">")),
+                },
+                AssuoPatch::Remove {
+                    way: Direction::At,
+                    spot: 1,
+                    count: 3,
+                },
+                patch,
+            ],
+        )
+    };
+    let insert = |way, spot| AssuoPatch::Insert {
+        way,
+        spot,
+        source: AssuoSource::Text(String::from("X")),
+    };
+
+    for spot in 2..=3 {
+        let mut patches = vec![
+            insert(Direction::Pre, spot),
+            insert(Direction::Post, spot),
+            AssuoPatch::Remove {
+                way: Direction::At,
+                spot,
+                count: 1,
+            },
+            AssuoPatch::Remove {
+                way: Direction::Pre,
+                spot,
+                count: 1,
+            },
+            AssuoPatch::Capture {
+                name: String::from("gone"),
+                spot,
+                count: 1,
+            },
+        ];
+        // a post removal starts after the byte at its spot
+        patches.push(AssuoPatch::Remove {
+            way: Direction::Post,
+            spot: spot - 1,
+            count: 1,
+        });
+
+        for patch in patches {
+            let error = do_patch(file(patch)).await.unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "patch #2: targets spot {}, which was removed by patch #1",
+                    spot
+                )
+            );
+        }
+    }
+
+    assert_eq!(
+        &do_patch(file(insert(Direction::Pre, 1))).await?,
+        &">aXef".as_bytes()
+    );
+    assert_eq!(
+        &do_patch(file(insert(Direction::Pre, 4))).await?,
+        &">aXef".as_bytes()
+    );
+    assert_eq!(
+        &do_patch(file(AssuoPatch::Remove {
+            way: Direction::At,
+            spot: 4,
+            count: 1,
+        }))
+        .await?,
+        &">af".as_bytes()
+    );
+    Ok(())
+}
+
+/// Removing zero bytes doesn't touch anything, wherever the spot is.
+#[tokio::test]
+async fn removing_zero_bytes_is_a_no_op() -> Result<(), Box<dyn std::error::Error>> {
+    for way in &[Direction::Pre, Direction::Post, Direction::At] {
+        for spot in &[0, 9] {
+            let file = file_with(
+                AssuoSource::Text(String::from("Hello!")),
+                vec![AssuoPatch::Remove {
+                    way: *way,
+                    spot: *spot,
+                    count: 0,
+                }],
+            );
+
+            // and linting agrees that there's nothing wrong with it
+            assert!(file.lint_bounds(&PatchOptions::default()).await?.is_empty());
+            assert_eq!(&do_patch(file).await?, &"Hello!".as_bytes());
+        }
+    }
+
+    Ok(())
+}
+
+/// A removal may go right up to either end of the data, but no further.
+#[tokio::test]
+async fn removals_can_reach_the_ends_but_not_past_them() -> Result<(), Box<dyn std::error::Error>> {
+    let removal = |way, spot, count| {
+        file_with(
+            AssuoSource::Text(String::from("Hello!")),
+            vec![AssuoPatch::Remove { way, spot, count }],
+        )
+    };
+
+    assert_eq!(
+        &do_patch(removal(Direction::Post, 0, 5)).await?,
+        &"H".as_bytes()
+    );
+    assert_eq!(
+        &do_patch(removal(Direction::Pre, 5, 5)).await?,
+        &"!".as_bytes()
+    );
+
+    let error = do_patch(removal(Direction::Post, 0, 6)).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(error.to_string().contains("goes past the end"), "{}", error);
+
+    let error = do_patch(removal(Direction::Pre, 5, 6)).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(
+        error.to_string().contains("goes past the start"),
+        "{}",
+        error
+    );
+
+    Ok(())
+}
+
+/// Applying patches one at a time with a `Patcher` ends up the same as `do_patch`, and shows each step along the way.
+#[tokio::test]
+async fn patcher_applies_patches_one_at_a_time() -> Result<(), Box<dyn std::error::Error>> {
+    let patches = vec![
+        AssuoPatch::Insert {
+            way: Direction::Post,
+            spot: 5,
+            source: AssuoSource::Text(String::from(", World")),
+        },
+        AssuoPatch::Remove {
+            way: Direction::Post,
+            spot: 0,
+            count: 4,
+        },
+        AssuoPatch::Insert {
+            way: Direction::Pre,
+            spot: 1,
+            source: AssuoSource::Text(String::from("ey")),
+        },
+        AssuoPatch::Insert {
+            way: Direction::Post,
+            spot: 6,
+            source: AssuoSource::Text(String::new()),
+        },
+    ];
+
+    let file = AssuoFile {
+        source: AssuoSource::Text(String::from("Hello!")),
+        patch: Some(patches.clone()),
+        expect_output_sha256: None,
+        footer: None,
+    };
+
+    let mut patcher = Patcher::new(b"Hello!".to_vec());
+    let mut steps = Vec::new();
+    for patch in patches {
+        let patch = match patch {
+            AssuoPatch::Insert { way, spot, source } => AssuoPatch::Insert {
+                way,
+                spot,
+                source: source.resolve().await?,
+            },
+            AssuoPatch::Remove { way, spot, count } => AssuoPatch::Remove { way, spot, count },
+            _ => unreachable!(),
+        };
+
+        patcher.apply(patch)?;
+        steps.push(String::from_utf8(patcher.buffer().to_vec())?);
+    }
+
+    assert_eq!(
+        steps,
+        vec!["Hello, World!", "H, World!", "Hey, World!", "Hey, World!"]
+    );
+    assert_eq!(
+        patcher.take_warnings(),
+        vec![Warning::NoOpPatch { patch: 3 }]
+    );
+    assert_eq!(patcher.finish(), do_patch(file).await?);
+
+    // spots that aren't in the data are errors, rather than panics
+    for patch in [
+        AssuoPatch::Insert {
+            way: Direction::Pre,
+            spot: 99,
+            source: b"?".to_vec(),
+        },
+        AssuoPatch::Remove {
+            way: Direction::At,
+            spot: 99,
+            count: 1,
+        },
+    ] {
+        let mut patcher = Patcher::new(b"Hello!".to_vec());
+        let error = patcher.apply(patch).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    Ok(())
+}
+
+/// A removal at a spot removes the byte at the spot and the ones after it, and leaves everything around them alone.
+#[tokio::test]
+async fn at_removals_remove_from_the_spot_on() -> Result<(), Box<dyn std::error::Error>> {
+    let removal = |spot, count| {
+        file_with(
+            AssuoSource::Text(String::from("Hello, World!")),
+            vec![AssuoPatch::Remove {
+                way: Direction::At,
+                spot,
+                count,
+            }],
+        )
+    };
+
+    assert_eq!(&do_patch(removal(5, 7)).await?, &"Hello!".as_bytes());
+    assert_eq!(&do_patch(removal(0, 7)).await?, &"World!".as_bytes());
+    assert_eq!(&do_patch(removal(7, 6)).await?, &"Hello, ".as_bytes());
+
+    let error = do_patch(removal(7, 7)).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(error.to_string().contains("goes past the end"), "{}", error);
+
+    // only removals have a start of their own
+    let file = AssuoFile {
+        source: AssuoSource::Text(String::from("Hello!")),
+        patch: Some(vec![AssuoPatch::Insert {
+            way: Direction::At,
+            spot: 5,
+            source: AssuoSource::Text(String::from(", World")),
+        }]),
+        expect_output_sha256: None,
+        footer: None,
+    };
+    assert!(do_patch(file).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn readme_remove_at() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "Hello!",
+        r#"
+[source]
+text = "Hello, World!"
+
+[[patch]]
+do = "remove"
+way = "at"
+spot = 5
+count = 7
+"#,
+    )
+    .await
+}
+
+/// The length found out without patching is the length of the patched output, including when removals overlap in the
+/// original source, or depend on what's around them.
+#[tokio::test]
+async fn patched_len_is_the_length_of_the_output() -> Result<(), Box<dyn std::error::Error>> {
+    let patches = [
+        // inserts on both sides of the same spot
+        r#"
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { text = ", World" }
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = 5
+source = { text = "?" }
+"#,
+        // removals that overlap in the original source
+        r#"
+[[patch]]
+do = "remove"
+way = "post"
+spot = 0
+count = 3
+
+[[patch]]
+do = "remove"
+way = "at"
+spot = 0
+count = 2
+"#,
+        // inserts and removals, measured in characters and from the end
+        r#"
+[[patch]]
+do = "remove"
+way = "pre"
+unit = "char"
+spot = "end-1"
+count = 1
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = "end"
+source = { text = "!!" }
+"#,
+        // a removal until an anchor
+        r#"
+[[patch]]
+do = "remove"
+way = "post"
+spot = 1
+until = { text = "!" }
+"#,
+    ];
+
+    for patches in &patches {
+        let source = format!("[source]\ntext = \"Hello!\"\n{}", patches);
+        let file = assuo::models::try_parse(&source)?;
+
+        assert_eq!(
+            patched_len(file.clone()).await?,
+            do_patch(file).await?.len(),
+            "{}",
+            patches
+        );
+    }
+
+    Ok(())
+}
+
+/// The output is checked against the hash the file expects it to have, if it expects one.
+#[tokio::test]
+async fn output_is_checked_against_the_expected_hash() -> Result<(), Box<dyn std::error::Error>> {
+    let file = |expected: &str| AssuoFile {
+        expect_output_sha256: Some(String::from(expected)),
+        ..file_with(
+            AssuoSource::Text(String::from("Hello!")),
+            vec![AssuoPatch::Insert {
+                way: Direction::Post,
+                spot: 5,
+                source: AssuoSource::Text(String::from(", World")),
+            }],
+        )
+    };
+
+    let hash = "dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f";
+    assert_eq!(&do_patch(file(hash)).await?, &"Hello, World!".as_bytes());
+    assert_eq!(
+        &do_patch(file(&hash.to_uppercase())).await?,
+        &"Hello, World!".as_bytes()
+    );
+
+    let mut buffer = Vec::new();
+    do_patch_into(file(hash), &mut buffer).await?;
+    assert_eq!(&buffer, &"Hello, World!".as_bytes());
+
+    let wrong = "334d016f755cd6dc58c53a86e183882f8ec14f52fb05345887c8a5edd42c87b7";
+    let error = do_patch(file(wrong)).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains(hash), "{}", error);
+
+    assert!(do_patch_into(file(wrong), &mut buffer).await.is_err());
+
+    Ok(())
+}
+
+/// A footer appends a checksum of the patched bytes, which the hash the output is expected to have includes.
+#[tokio::test]
+async fn footer_appends_a_checksum_of_the_patched_bytes() -> Result<(), Box<dyn std::error::Error>>
+{
+    let file = |footer: Footer| AssuoFile {
+        footer: Some(footer),
+        ..file_with(
+            AssuoSource::Text(String::from("12345")),
+            vec![AssuoPatch::Insert {
+                way: Direction::Post,
+                spot: 5,
+                source: AssuoSource::Text(String::from("6789")),
+            }],
+        )
+    };
+
+    // the CRC-32 of "123456789" is the check value every CRC-32 implementation is held to
+    let crc32 = Footer {
+        crc32: true,
+        ..Footer::default()
+    };
+    let mut expected = b"123456789".to_vec();
+    expected.extend_from_slice(&0xcbf4_3926u32.to_le_bytes());
+    assert_eq!(do_patch(file(crc32)).await?, expected);
+    assert_eq!(patched_len(file(crc32)).await?, expected.len());
+
+    let mut buffer = Vec::new();
+    do_patch_into(file(crc32), &mut buffer).await?;
+    assert_eq!(buffer, expected);
+
+    let big = Footer {
+        endian: Some(Endian::Big),
+        ..crc32
+    };
+    assert_eq!(do_patch(file(big)).await?[9..], [0xcb, 0xf4, 0x39, 0x26]);
+
+    // the SHA-256 comes after the CRC-32
+    let both = Footer {
+        sha256: true,
+        ..crc32
+    };
+    let output = do_patch(file(both)).await?;
+    assert_eq!(output[..13], expected[..]);
+    assert_eq!(
+        output[13..]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>(),
+        "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225"
+    );
+
+    let expecting = |hash: &str| AssuoFile {
+        expect_output_sha256: Some(String::from(hash)),
+        ..file(crc32)
+    };
+    let with_footer = "91fe2aac6a2ad3f7099f277c0ac978a43a895c64058961711083426438a512b1";
+    assert_eq!(do_patch(expecting(with_footer)).await?, expected);
+    let without_footer = "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225";
+    assert!(do_patch(expecting(without_footer)).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn readme_expect_output_sha256() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "Hello, World!",
+        r#"
+expect_output_sha256 = "dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f"
+
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { text = ", World" }
+"#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn readme_footer() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        b"123456789\x26\x39\xf4\xcb",
+        r#"
+footer = { crc32 = true }
+
+[source]
+text = "12345"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { text = "6789" }
+"#,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn readme_profiles() -> Result<(), Box<dyn std::error::Error>> {
+    let source = r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { text = ", dev" }
+label = "greet dev"
+
+[profiles.prod]
+source = { text = "Howdy!" }
+disable = ["greet dev"]
+
+[[profiles.prod.patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { text = ", prod" }
+"#;
+
+    helper("Hello, dev!", source).await?;
+
+    let file = assuo::models::try_parse_in_profile(source, None, Some("prod"))?;
+    assert_eq!(&do_patch(file).await?, &"Howdy, prod!".as_bytes());
+    Ok(())
+}
+
+#[tokio::test]
+async fn readme_no_source() -> Result<(), Box<dyn std::error::Error>> {
+    helper(
+        "Hello, World!",
+        r#"
+[[patch]]
+do = "insert"
+way = "pre"
+spot = 0
+source = { text = "Hello" }
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = 0
+source = { text = ", World!" }
+"#,
+    )
+    .await
+}
@@ -0,0 +1,179 @@
+//! Tests for turning unified diffs into patches, and for working out the patches between two files
+
+use assuo::diff::{patches_from_diff, patches_from_unified_diff};
+use assuo::models::{AssuoFile, AssuoSource};
+use assuo::patch::do_patch;
+
+use rand::Rng;
+
+async fn diff_apply(base: &str, diff: &str) -> std::io::Result<Vec<u8>> {
+    let patch = patches_from_unified_diff(base.as_bytes(), diff)?;
+
+    do_patch(AssuoFile {
+        source: AssuoSource::Bytes(base.as_bytes().to_vec()),
+        patch: Some(patch),
+        expect_output_sha256: None,
+        footer: None,
+    })
+    .await
+}
+
+async fn from_diff(old: &[u8], new: &[u8]) -> std::io::Result<Vec<u8>> {
+    do_patch(AssuoFile {
+        source: AssuoSource::Bytes(old.to_vec()),
+        patch: Some(patches_from_diff(old, new)),
+        expect_output_sha256: None,
+        footer: None,
+    })
+    .await
+}
+
+#[tokio::test]
+async fn one_hunk_diff_applies() -> Result<(), Box<dyn std::error::Error>> {
+    let patched = diff_apply(
+        "fn main() {\n    println!(\"Hello!\");\n}\n",
+        r#"--- a/main.rs
++++ b/main.rs
+@@ -1,3 +1,4 @@
+ fn main() {
+-    println!("Hello!");
++    let name = "World";
++    println!("Hello, {}!", name);
+ }
+"#,
+    )
+    .await?;
+
+    assert_eq!(
+        String::from_utf8(patched)?,
+        "fn main() {\n    let name = \"World\";\n    println!(\"Hello, {}!\", name);\n}\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn many_hunks_apply_at_the_start_and_end() -> Result<(), Box<dyn std::error::Error>> {
+    let patched = diff_apply(
+        "a\nb\nc\nd\ne\nf\ng\n",
+        r#"--- a.txt
++++ b.txt
+@@ -0,0 +1 @@
++start
+@@ -2,3 +3,2 @@
+ b
+-c
+-d
++D
+@@ -6,2 +6,2 @@
+ f
+-g
++end
+\ No newline at end of file
+"#,
+    )
+    .await?;
+
+    assert_eq!(String::from_utf8(patched)?, "start\na\nb\nD\ne\nf\nend");
+    Ok(())
+}
+
+#[tokio::test]
+async fn diff_of_a_new_file_applies_to_an_empty_base() -> Result<(), Box<dyn std::error::Error>> {
+    let patched = diff_apply(
+        "",
+        r#"--- /dev/null
++++ new.txt
+@@ -0,0 +1,2 @@
++Hello,
++World!
+"#,
+    )
+    .await?;
+
+    assert_eq!(&patched, b"Hello,\nWorld!\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn diff_for_another_file_errors() {
+    let error = diff_apply(
+        "a\nb\n",
+        r#"@@ -1,2 +1,2 @@
+ a
+-c
++d
+"#,
+    )
+    .await
+    .unwrap_err();
+
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error
+        .to_string()
+        .contains("doesn't match the base at line 2"));
+}
+
+#[tokio::test]
+async fn patches_from_a_diff_reproduce_the_new_file() -> Result<(), Box<dyn std::error::Error>> {
+    let cases: &[(&[u8], &[u8])] = &[
+        (b"Hello!", b"Hello, World!"),
+        (b"Hello, World!", b"Hello!"),
+        (b"abcdef", b"xbcdey"),
+        (b"abc", b"abcdef"),
+        (b"abc", b""),
+        (b"", b"abc"),
+        (b"", b""),
+        (b"same", b"same"),
+        (b"a\nb\nc\n", b"start\na\nB\nc"),
+    ];
+
+    for (old, new) in cases {
+        assert_eq!(&from_diff(old, new).await?, new, "{:?} -> {:?}", old, new);
+    }
+
+    // every run of bytes that changed is one insert, and one removal
+    let patches = patches_from_diff(b"fn foo() {}", b"fn bar() {}");
+    assert_eq!(patches.len(), 2, "{:?}", patches);
+    Ok(())
+}
+
+#[tokio::test]
+async fn patches_from_a_diff_of_unrelated_files_replace_everything(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rng = rand::thread_rng();
+    let old = (0..30_000).map(|_| rng.gen()).collect::<Vec<u8>>();
+    let new = (0..30_000).map(|_| rng.gen()).collect::<Vec<u8>>();
+
+    assert_eq!(from_diff(&old, &new).await?, new);
+    Ok(())
+}
+
+#[test]
+fn patches_from_a_diff_of_huge_unrelated_files_replace_everything() {
+    // how much is kept while looking for edits doesn't grow with the files, only with how many edits are looked for
+    let old = vec![0; 32 * 1024 * 1024];
+    let new = vec![1; 32 * 1024 * 1024];
+
+    // all of it is removed, and all of the new one inserted
+    assert_eq!(patches_from_diff(&old, &new).len(), 2);
+}
+
+#[tokio::test]
+async fn patches_from_a_diff_of_random_files_reproduce_the_new_file(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..200 {
+        // a small alphabet makes the two share a lot, like versions of the same file do
+        let old = (0..rng.gen_range(0, 64))
+            .map(|_| rng.gen_range(b'a', b'e'))
+            .collect::<Vec<_>>();
+        let new = (0..rng.gen_range(0, 64))
+            .map(|_| rng.gen_range(b'a', b'e'))
+            .collect::<Vec<_>>();
+
+        assert_eq!(from_diff(&old, &new).await?, new, "{:?}", old);
+    }
+
+    Ok(())
+}
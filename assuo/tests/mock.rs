@@ -0,0 +1,40 @@
+//! Tests for answering urls from memory with the `testing` feature, which need `--features testing` to run
+
+use assuo::models::{try_parse, Resolvable};
+use assuo::patch::{do_patch_with_options, PatchOptions};
+
+#[tokio::test]
+async fn mocked_urls_are_answered_without_a_server() -> Result<(), Box<dyn std::error::Error>> {
+    let options = PatchOptions::default();
+    options
+        .mock_responses
+        .respond("https://example.invalid/greeting", ", World");
+    options.mock_responses.respond(
+        "https://example.invalid/assuo.toml",
+        "[source]\ntext = \"Hello!\"\n",
+    );
+
+    let config = try_parse(
+        r#"
+[source]
+assuo-url = "https://example.invalid/assuo.toml"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { url = "https://example.invalid/greeting" }
+"#,
+    )?;
+
+    assert_eq!(
+        do_patch_with_options(config, &options).await?,
+        b"Hello, World!"
+    );
+
+    // urls that aren't mocked are still fetched, and nothing answers at this one
+    let config = try_parse("[source]\nurl = \"https://example.invalid/other\"\n")?;
+    assert!(config.resolve_with(&options).await.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,700 @@
+//! Tests for parsing assuo patch files
+
+use assuo::models::{
+    merge, to_toml, try_parse, try_parse_any, try_parse_as, try_parse_collecting, AssuoPatch,
+    AssuoSource, Direction, Endian, Footer, Format,
+};
+use assuo::patch::PatchOptions;
+
+#[test]
+fn collecting_reports_every_problem() {
+    let errors = try_parse_collecting(
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "inject"
+way = "post"
+spot = 5
+source = { text = ", World" }
+
+[[patch]]
+do = "insert"
+way = "post"
+source = { txet = "!" }
+"#,
+    )
+    .unwrap_err();
+
+    assert_eq!(errors.len(), 3, "{:?}", errors);
+    assert!(errors[0].starts_with("patch #0: expected either 'insert' or 'remove'"));
+    assert!(errors[1].starts_with("patch #1: didn't get 'spot'"));
+    assert!(errors[2].starts_with("patch #1: didn't get key text/url/file"));
+}
+
+#[test]
+fn collecting_says_where_each_problem_is() {
+    let errors = try_parse_collecting(
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "inject"
+way = "post"
+spot = 5
+source = { text = ", World" }
+
+[[patch]]
+label = "exclaim"
+way = "post"
+do = "insert"
+source = { txet = "!" }
+"#,
+    )
+    .unwrap_err();
+
+    // problems with a field point at it, and a missing field points at the start of the patch
+    assert_eq!(errors.len(), 3, "{:?}", errors);
+    assert!(
+        errors[0].ends_with("for 'do' at line 6 column 6"),
+        "{}",
+        errors[0]
+    );
+    assert!(
+        errors[1].ends_with("'spot' at line 12 column 9"),
+        "{}",
+        errors[1]
+    );
+    assert!(errors[2].ends_with("at line 15 column 10"), "{}", errors[2]);
+}
+
+#[test]
+fn collecting_agrees_with_try_parse_on_valid_files() {
+    let source = r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { text = ", World" }
+
+[[patch]]
+do = "remove"
+way = "pre"
+spot = 5
+count = 1
+"#;
+
+    let collected = try_parse_collecting(source).unwrap();
+    let parsed = try_parse(source).unwrap();
+    assert_eq!(format!("{:?}", collected), format!("{:?}", parsed));
+}
+
+#[test]
+fn check_urls_reports_every_invalid_url() {
+    let file = try_parse(
+        r#"
+[source]
+url = "not a url"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { url = "https://example.com/" }
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { xor = { of = { assuo-url = "https://exa mple.com/" }, key = [1] } }
+"#,
+    )
+    .unwrap();
+
+    let findings = file.check_urls(&PatchOptions::default());
+
+    assert_eq!(findings.len(), 2, "{:?}", findings);
+    assert_eq!(findings[0].patch, None);
+    assert_eq!(findings[0].url, "not a url");
+    assert_eq!(findings[1].patch, Some(1));
+    assert!(findings[1].to_string().starts_with("patch #1: invalid url"));
+}
+
+#[test]
+fn patches_without_a_way_use_the_default_way() {
+    let source = r#"
+default_way = "pre"
+
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+spot = 5
+source = { text = ", World" }
+
+[[patch]]
+do = "remove"
+way = "post"
+spot = 0
+count = 1
+"#;
+
+    for file in &[
+        try_parse(source).unwrap(),
+        try_parse_collecting(source).unwrap(),
+    ] {
+        let ways = file
+            .patch
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|patch| match patch {
+                AssuoPatch::Insert { way, .. }
+                | AssuoPatch::Remove { way, .. }
+                | AssuoPatch::RemoveUntil { way, .. } => *way,
+                AssuoPatch::Capture { .. }
+                | AssuoPatch::InChars(_)
+                | AssuoPatch::FromEnd(_)
+                | AssuoPatch::Found { .. }
+                | AssuoPatch::Matched { .. }
+                | AssuoPatch::Labeled { .. }
+                | AssuoPatch::Gated { .. } => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(ways, vec![Direction::Pre, Direction::Post]);
+    }
+
+    // without a default, the way is still needed
+    let source = source.replace("default_way = \"pre\"", "");
+    assert!(try_parse(&source).is_err());
+    assert!(try_parse_collecting(&source).is_err());
+}
+
+#[test]
+fn missing_source_is_no_bytes() {
+    let source = r#"
+[[patch]]
+do = "insert"
+way = "post"
+spot = 0
+source = { text = "Hello, World!" }
+"#;
+
+    assert_eq!(try_parse(source).unwrap().source, AssuoSource::default());
+    assert_eq!(
+        try_parse_collecting(source).unwrap().source,
+        AssuoSource::Bytes(Vec::new())
+    );
+}
+
+#[test]
+fn merged_files_parse_with_every_patch() {
+    let a = try_parse(
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { text = ", World" }
+"#,
+    )
+    .unwrap();
+
+    let b = try_parse(
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "remove"
+way = "pre"
+spot = 1
+count = 1
+"#,
+    )
+    .unwrap();
+
+    let merged = try_parse(&to_toml(&merge(&[a.clone(), b]).unwrap()).unwrap()).unwrap();
+    assert_eq!(merged.source, a.source);
+    assert_eq!(merged.patch.unwrap().len(), 2);
+
+    let other = try_parse("[source]\ntext = \"Bye!\"\n").unwrap();
+    assert!(merge(&[a, other]).is_err());
+    assert!(merge(&[]).is_err());
+}
+
+#[test]
+fn every_format_parses_to_the_same_file() {
+    let toml = try_parse(
+        r#"
+default_way = "pre"
+
+[source]
+bytes = [72, "ello"]
+
+[[patch]]
+do = "remove"
+spot = 5
+count = 1
+"#,
+    )
+    .unwrap();
+
+    let expected = format!("{:?}", toml);
+
+    let json = r#"{
+  "default_way": "pre",
+  "source": { "bytes": [72, "ello"] },
+  "patch": [{ "do": "remove", "spot": 5, "count": 1 }]
+}"#;
+
+    let yaml = r#"
+default_way: pre
+source:
+  bytes: [72, ello]
+patch:
+  - do: remove
+    spot: 5
+    count: 1
+"#;
+
+    assert_eq!(
+        format!("{:?}", try_parse_as(json, Format::Json).unwrap()),
+        expected
+    );
+    assert_eq!(
+        format!("{:?}", try_parse_as(yaml, Format::Yaml).unwrap()),
+        expected
+    );
+    assert_eq!(format!("{:?}", try_parse_any(json).unwrap()), expected);
+    assert_eq!(format!("{:?}", try_parse_any(yaml).unwrap()), expected);
+
+    // JSON isn't TOML, and TOML isn't JSON
+    assert!(try_parse_as(json, Format::Toml).is_err());
+    assert!(try_parse_as(&to_toml(&toml).unwrap(), Format::Json).is_err());
+}
+
+#[test]
+fn spots_from_the_end_round_trip() {
+    let source = r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = "end"
+source = { text = "?" }
+
+[[patch]]
+do = "remove"
+way = "pre"
+unit = "char"
+spot = "end-1"
+count = 1
+
+[[patch]]
+do = "remove"
+way = "pre"
+spot = "start+2"
+count = 1
+"#;
+
+    let file = try_parse(source).unwrap();
+    let patches = file.patch.as_ref().unwrap();
+    assert!(
+        matches!(&patches[0], AssuoPatch::FromEnd(patch) if matches!(**patch, AssuoPatch::Insert { spot: 0, .. }))
+    );
+    assert!(
+        matches!(&patches[1], AssuoPatch::InChars(patch) if matches!(**patch, AssuoPatch::FromEnd(_)))
+    );
+    assert!(matches!(&patches[2], AssuoPatch::Remove { spot: 2, .. }));
+
+    let toml = to_toml(&file).unwrap();
+    assert!(toml.contains("spot = \"end\""), "{}", toml);
+    assert!(toml.contains("spot = \"end-1\""), "{}", toml);
+    assert_eq!(
+        format!("{:?}", try_parse(&toml).unwrap()),
+        format!("{:?}", file)
+    );
+
+    for spot in &["\"end+1\"", "\"start-1\"", "\"middle\"", "\"end-x\""] {
+        assert!(
+            try_parse(&source.replace("\"start+2\"", spot)).is_err(),
+            "{}",
+            spot
+        );
+    }
+}
+
+#[test]
+fn found_spots_round_trip() {
+    let source = r#"
+[source]
+text = "Hello, World!"
+
+[[patch]]
+do = "remove"
+way = "at"
+label = "drop the name"
+spot = { find = { text = ", World" } }
+count = 7
+"#;
+
+    let file = try_parse(source).unwrap();
+    let patch = &file.patch.as_ref().unwrap()[0];
+    assert_eq!(patch.label(), Some("drop the name"));
+    assert!(matches!(
+        patch.unlabeled(),
+        AssuoPatch::Found { patch, .. } if matches!(**patch, AssuoPatch::Remove { count: 7, .. })
+    ));
+
+    let toml = to_toml(&file).unwrap();
+    assert_eq!(
+        format!("{:?}", try_parse(&toml).unwrap()),
+        format!("{:?}", file)
+    );
+    assert_eq!(
+        format!("{:?}", try_parse_collecting(source).unwrap()),
+        format!("{:?}", file)
+    );
+
+    // found spots are in bytes, and only say what to find
+    assert!(try_parse(&source.replace("count = 7", "count = 7\nunit = \"char\"")).is_err());
+    assert!(try_parse(&source.replace("{ find =", "{ look = ")).is_err());
+    assert!(try_parse(&source.replace("} }", "}, also = 1 }")).is_err());
+
+    // a plain string is the text to find
+    assert_eq!(
+        format!(
+            "{:?}",
+            try_parse(&source.replace("{ text = \", World\" }", "\", World\"")).unwrap()
+        ),
+        format!("{:?}", file)
+    );
+
+    let error = try_parse(&source.replace("{ text = \", World\" }", "5")).unwrap_err();
+    assert!(error.to_string().contains("'find'"), "{}", error);
+}
+
+#[test]
+fn regex_spots_round_trip() {
+    let source = r#"
+[source]
+text = "fn main() {}"
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = { regex = '\bfn \w+' }
+source = { text = "pub " }
+"#;
+
+    let file = try_parse(source).unwrap();
+    assert_eq!(file.patch.as_ref().unwrap()[0].regex(), Some(r"\bfn \w+"));
+
+    let toml = to_toml(&file).unwrap();
+    assert_eq!(
+        format!("{:?}", try_parse(&toml).unwrap()),
+        format!("{:?}", file)
+    );
+    assert_eq!(
+        format!("{:?}", try_parse_collecting(source).unwrap()),
+        format!("{:?}", file)
+    );
+
+    // regex spots are in bytes, and only have the one regex
+    assert!(try_parse(&source.replace("way = \"pre\"", "way = \"pre\"\nunit = \"char\"")).is_err());
+    assert!(try_parse(&source.replace("{ regex = '\\bfn \\w+' }", "{ regex = 1 }")).is_err());
+    assert!(try_parse(&source.replace("' }", "', find = { text = \"fn\" } }")).is_err());
+}
+
+#[test]
+fn gated_patches_round_trip() {
+    let source = r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+label = "windows only"
+source = { text = ", World" }
+cfg = { os = "windows", arch = "x86_64" }
+"#;
+
+    let file = try_parse(source).unwrap();
+    let patch = &file.patch.as_ref().unwrap()[0];
+    assert_eq!(patch.label(), Some("windows only"));
+    assert!(matches!(
+        patch,
+        AssuoPatch::Gated { cfg, .. }
+            if cfg.os.as_deref() == Some("windows") && cfg.arch.as_deref() == Some("x86_64")
+    ));
+
+    let toml = to_toml(&file).unwrap();
+    assert_eq!(
+        format!("{:?}", try_parse(&toml).unwrap()),
+        format!("{:?}", file)
+    );
+    assert_eq!(
+        format!("{:?}", try_parse_collecting(source).unwrap()),
+        format!("{:?}", file)
+    );
+
+    for cfg in &[
+        "cfg = \"windows\"",
+        "cfg = { os = 1 }",
+        "cfg = { family = \"unix\" }",
+    ] {
+        let source = source.replace("cfg = { os = \"windows\", arch = \"x86_64\" }", cfg);
+        assert!(try_parse(&source).is_err(), "{}", cfg);
+    }
+}
+
+#[test]
+fn if_sources_round_trip() {
+    let source = r#"
+[source]
+if = { cond = { os = "linux", env = "PROD" }, then = { url = "https://example.com/" }, else = { text = "stub" } }
+"#;
+
+    let file = try_parse(source).unwrap();
+    let toml = to_toml(&file).unwrap();
+    assert_eq!(
+        format!("{:?}", try_parse(&toml).unwrap()),
+        format!("{:?}", file)
+    );
+
+    for cond in &[
+        "cond = \"PROD\"",
+        "cond = { env = 1 }",
+        "cond = { family = \"unix\" }",
+    ] {
+        let source = source.replace("cond = { os = \"linux\", env = \"PROD\" }", cond);
+        assert!(try_parse(&source).is_err(), "{}", cond);
+    }
+}
+
+#[test]
+fn labels_round_trip() {
+    let file = try_parse(
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+label = "add footer"
+do = "insert"
+way = "post"
+unit = "char"
+spot = "end"
+source = { text = "!" }
+"#,
+    )
+    .unwrap();
+
+    let patch = &file.patch.as_ref().unwrap()[0];
+    assert_eq!(patch.label(), Some("add footer"));
+    assert!(matches!(patch.unlabeled(), AssuoPatch::InChars(_)));
+
+    let toml = to_toml(&file).unwrap();
+    assert!(toml.contains("label = \"add footer\""), "{}", toml);
+    assert_eq!(
+        format!("{:?}", try_parse(&toml).unwrap()),
+        format!("{:?}", file)
+    );
+
+    assert!(try_parse(&toml.replace("\"add footer\"", "5")).is_err());
+}
+
+#[test]
+fn expected_output_hashes_round_trip() {
+    let source = r#"
+expect_output_sha256 = "dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f"
+
+[source]
+text = "Hello!"
+"#;
+
+    for file in &[
+        try_parse(source).unwrap(),
+        try_parse_collecting(source).unwrap(),
+    ] {
+        assert_eq!(
+            file.expect_output_sha256.as_deref(),
+            Some("dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f")
+        );
+
+        let toml = to_toml(file).unwrap();
+        assert_eq!(
+            format!("{:?}", try_parse(&toml).unwrap()),
+            format!("{:?}", file)
+        );
+    }
+
+    assert!(try_parse("expect_output_sha256 = 5\n[source]\ntext = \"Hello!\"\n").is_err());
+}
+
+#[test]
+fn footers_round_trip() {
+    let source = r#"
+footer = { crc32 = true, endian = "big" }
+
+[source]
+text = "Hello!"
+"#;
+
+    for file in &[
+        try_parse(source).unwrap(),
+        try_parse_collecting(source).unwrap(),
+    ] {
+        assert_eq!(
+            file.footer,
+            Some(Footer {
+                crc32: true,
+                sha256: false,
+                endian: Some(Endian::Big),
+            })
+        );
+
+        let toml = to_toml(file).unwrap();
+        assert_eq!(
+            format!("{:?}", try_parse(&toml).unwrap()),
+            format!("{:?}", file)
+        );
+    }
+
+    let unknown = "footer = { md5 = true }\n[source]\ntext = \"Hello!\"\n";
+    assert!(try_parse(unknown).is_err());
+    assert!(try_parse_collecting(unknown).is_err());
+}
+
+#[test]
+fn only_files_with_the_same_footer_merge() {
+    let crc32 = try_parse("footer = { crc32 = true }\n[source]\ntext = \"Hello!\"\n").unwrap();
+    let sha256 = try_parse("footer = { sha256 = true }\n[source]\ntext = \"Hello!\"\n").unwrap();
+    let none = try_parse("[source]\ntext = \"Hello!\"\n").unwrap();
+
+    let merged = merge(&[crc32.clone(), crc32.clone()]).unwrap();
+    assert_eq!(merged.footer, crc32.footer);
+
+    let error = merge(&[crc32.clone(), sha256]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "file #1 has a different footer than the first file"
+    );
+    assert!(merge(&[crc32, none]).is_err());
+}
+
+#[test]
+fn only_removals_of_a_count_go_at_a_spot() {
+    let source = r#"
+[source]
+text = "Hello, World!"
+
+[[patch]]
+do = "remove"
+way = "at"
+spot = 5
+count = 7
+"#;
+
+    let file = try_parse(source).unwrap();
+    assert!(matches!(
+        &file.patch.as_ref().unwrap()[0],
+        AssuoPatch::Remove {
+            way: Direction::At,
+            spot: 5,
+            count: 7
+        }
+    ));
+
+    let toml = to_toml(&file).unwrap();
+    assert!(toml.contains("way = \"at\""), "{}", toml);
+
+    let errors = try_parse_collecting(
+        r#"
+[source]
+text = "Hello, World!"
+
+[[patch]]
+do = "insert"
+way = "at"
+spot = 5
+source = { text = "!" }
+
+[[patch]]
+do = "remove"
+way = "at"
+spot = 5
+until = { text = "!" }
+"#,
+    )
+    .unwrap_err();
+
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    for error in &errors {
+        assert!(error.contains("only removals of a count"), "{}", error);
+    }
+}
+
+#[test]
+fn fetch_policies_round_trip() {
+    let source = r#"
+[source]
+url = "https://example.com/"
+timeout = "500ms"
+retry = 2
+redirects = { limit = 3 }
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 0
+source = { post = { url = "https://example.com/", timeout = "2m", redirects = "none" } }
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 0
+source = { url = "https://example.com/", redirects = "same-host" }
+"#;
+
+    let file = try_parse(source).unwrap();
+    let toml = to_toml(&file).unwrap();
+    assert_eq!(
+        format!("{:?}", try_parse(&toml).unwrap()),
+        format!("{:?}", file)
+    );
+
+    for policy in &[
+        "timeout = \"soon\"",
+        "timeout = 10",
+        "timeout = \"18446744073709551615m\"",
+        "retry = -1",
+        "redirects = \"all\"",
+        "redirects = { limit = -1 }",
+        "redirects = 3",
+    ] {
+        assert!(
+            try_parse(&source.replace("retry = 2", policy)).is_err(),
+            "{}",
+            policy
+        );
+    }
+
+    // a file isn't fetched, so it doesn't have a fetch policy
+    assert!(try_parse("[source]\nfile = \"a.txt\"\ntimeout = \"1s\"\n").is_err());
+}
@@ -0,0 +1,67 @@
+//! Houses all tests for spots that are wherever a regex matches. These only run with the `regex` feature.
+#![cfg(feature = "regex")]
+
+use assuo::models::try_parse;
+use assuo::patch::do_patch;
+
+fn file(regex: &str) -> String {
+    format!(
+        r#"
+[source]
+text = "fn main() {{}}\nfn helper() {{}}\n"
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = {{ regex = '{}' }}
+source = {{ text = "pub " }}
+"#,
+        regex
+    )
+}
+
+#[tokio::test]
+async fn regex_spots_are_where_the_match_starts() -> Result<(), Box<dyn std::error::Error>> {
+    let patched = do_patch(try_parse(&file(r"\bfn helper\("))?).await?;
+    assert_eq!(
+        String::from_utf8(patched)?,
+        "fn main() {}\npub fn helper() {}\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn regex_spots_have_to_match_exactly_once() -> Result<(), Box<dyn std::error::Error>> {
+    for (regex, reason) in &[
+        (r"\bfn nothing\(", "doesn't match the source"),
+        (r"\bfn \w+", "matches the source 2 times"),
+        (r"\bfn (", "is invalid"),
+    ] {
+        let error = do_patch(try_parse(&file(regex))?).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains(reason), "{}", error);
+    }
+
+    Ok(())
+}
+
+// == CODE SAMPLE TESTS ==
+
+#[tokio::test]
+async fn readme_regex_spots() -> Result<(), Box<dyn std::error::Error>> {
+    let file = try_parse(
+        r#"
+[source]
+text = "fn main() {}"
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = { regex = "\\bfn \\w+\\(" }
+source = { text = "pub " }
+"#,
+    )?;
+
+    assert_eq!(&do_patch(file).await?, &"pub fn main() {}".as_bytes());
+    Ok(())
+}
@@ -0,0 +1,767 @@
+//! Tests for resolving the different kinds of sources `assuo` supports
+
+use std::io::Write;
+
+use assuo::models::{try_parse, AssuoSource, FetchPolicy, LenEncoding, Resolvable};
+use assuo::patch::{do_patch, do_patch_with_options, Manifest, PatchOptions};
+
+/// Writes a zip archive with the given entries into a temporary directory, returning the directory (so it isn't
+/// deleted until the test is done with it) and the path to the archive.
+fn make_zip(
+    entries: &[(&str, &str)],
+) -> Result<(tempfile::TempDir, String), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("bundle.zip");
+
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&path)?);
+    for (name, contents) in entries {
+        zip.start_file(*name, zip::write::FileOptions::default())?;
+        zip.write_all(contents.as_bytes())?;
+    }
+    zip.finish()?;
+
+    let path = path.to_str().unwrap().replace('\\', "/");
+    Ok((dir, path))
+}
+
+#[tokio::test]
+async fn zip_entry_is_read_from_archive() -> Result<(), Box<dyn std::error::Error>> {
+    let (_dir, archive) = make_zip(&[("part1.txt", ", World"), ("part2.txt", "nope")])?;
+
+    let file = try_parse(&format!(
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = {{ zip = {{ archive = "{}", entry = "part1.txt" }} }}
+"#,
+        archive
+    ))?;
+
+    let patched = do_patch(file).await?;
+
+    assert_eq!(&patched, &"Hello, World!".as_bytes());
+    Ok(())
+}
+
+#[tokio::test]
+async fn zip_entry_errors_when_missing() -> Result<(), Box<dyn std::error::Error>> {
+    let (dir, archive) = make_zip(&[("part1.txt", "Hello!")])?;
+
+    let missing_entry = AssuoSource::ZipEntry {
+        archive: archive.clone(),
+        entry: String::from("part2.txt"),
+    };
+
+    let error = Resolvable::<Vec<u8>>::resolve(missing_entry)
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+
+    let missing_archive = AssuoSource::ZipEntry {
+        archive: dir.path().join("missing.zip").to_str().unwrap().to_owned(),
+        entry: String::from("part1.txt"),
+    };
+
+    let error = Resolvable::<Vec<u8>>::resolve(missing_archive)
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+
+    Ok(())
+}
+
+/// Sets an environment variable until it's dropped, and then puts back whatever was there before, so that it doesn't
+/// leak into other tests.
+struct EnvVar {
+    name: &'static str,
+    before: Option<std::ffi::OsString>,
+}
+
+impl EnvVar {
+    fn set(name: &'static str, value: &str) -> Self {
+        let before = std::env::var_os(name);
+        std::env::set_var(name, value);
+        EnvVar { name, before }
+    }
+}
+
+impl Drop for EnvVar {
+    fn drop(&mut self) {
+        match &self.before {
+            Some(before) => std::env::set_var(self.name, before),
+            None => std::env::remove_var(self.name),
+        }
+    }
+}
+
+#[tokio::test]
+async fn now_honors_source_date_epoch() -> Result<(), Box<dyn std::error::Error>> {
+    let _epoch = EnvVar::set("SOURCE_DATE_EPOCH", "1600000000");
+
+    let now = AssuoSource::Now(String::from("%Y-%m-%d %H:%M:%S"));
+    let resolved = Resolvable::<Vec<u8>>::resolve(now).await?;
+    assert_eq!(&resolved, &"2020-09-13 12:26:40".as_bytes());
+
+    let invalid = AssuoSource::Now(String::from("%Y-%Q"));
+    let error = Resolvable::<Vec<u8>>::resolve(invalid).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    Ok(())
+}
+
+async fn resolve_template(template: &str) -> std::io::Result<Vec<u8>> {
+    let file = try_parse(&format!(
+        r#"
+[source]
+template = {}
+"#,
+        template
+    ))?;
+
+    Ok(file.resolve().await?.source)
+}
+
+#[tokio::test]
+async fn template_substitutes_vars() -> Result<(), Box<dyn std::error::Error>> {
+    let resolved = resolve_template(
+        r#"{ text = "${GREETING}, ${NAME}!", vars = { GREETING = "Hello", NAME = "World" } }"#,
+    )
+    .await?;
+
+    assert_eq!(&resolved, &"Hello, World!".as_bytes());
+    Ok(())
+}
+
+#[tokio::test]
+async fn template_falls_back_to_defaults() -> Result<(), Box<dyn std::error::Error>> {
+    let resolved = resolve_template(
+        r#"{ text = "${GREETING:-Hello}, ${NAME:-nobody}!", vars = { NAME = "World" } }"#,
+    )
+    .await?;
+
+    assert_eq!(&resolved, &"Hello, World!".as_bytes());
+    Ok(())
+}
+
+#[tokio::test]
+async fn template_errors_on_unknown_vars() -> Result<(), Box<dyn std::error::Error>> {
+    let error = resolve_template(r#"{ text = "Hello, ${NAME}!" }"#)
+        .await
+        .unwrap_err();
+
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(error.to_string().contains("NAME"), "{}", error);
+    Ok(())
+}
+
+#[tokio::test]
+async fn len_of_prefixes_length() -> Result<(), Box<dyn std::error::Error>> {
+    let file = try_parse(
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = 0
+source = { len_of = { text = "Hello!" }, as = "u32le" }
+"#,
+    )?;
+
+    let patched = do_patch(file).await?;
+
+    assert_eq!(&patched, &b"\x06\x00\x00\x00Hello!");
+    Ok(())
+}
+
+#[tokio::test]
+async fn len_of_errors_on_overflow() -> Result<(), Box<dyn std::error::Error>> {
+    let len_of = AssuoSource::LenOf {
+        of: Box::new(AssuoSource::Bytes(vec![0; 256])),
+        encoding: LenEncoding::U8,
+    };
+
+    let error = Resolvable::<Vec<u8>>::resolve(len_of).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    Ok(())
+}
+
+#[tokio::test]
+async fn disallowed_source_kinds_are_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let text_only = PatchOptions {
+        allowed_sources: Some(vec![String::from("text")].into_iter().collect()),
+        ..PatchOptions::default()
+    };
+
+    let file = try_parse(
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { template = { file = "./secrets.txt" } }
+"#,
+    )?;
+
+    let error = do_patch_with_options(file, &text_only).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    assert!(error.to_string().contains("'template'"), "{}", error);
+
+    let file = try_parse(
+        r#"
+[source]
+file = "./secrets.txt"
+"#,
+    )?;
+
+    let error = do_patch_with_options(file, &text_only).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    assert!(error.to_string().contains("'file'"), "{}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn empty_sources_are_only_an_error_when_asked() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("empty.txt");
+    std::fs::write(&path, "")?;
+
+    let no_empty_sources = PatchOptions {
+        no_empty_sources: true,
+        ..PatchOptions::default()
+    };
+
+    let as_base = format!("[source]\nfile = {:?}\n", path.to_str().unwrap());
+    let in_patch = format!(
+        r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = {{ file = {:?} }}
+"#,
+        path.to_str().unwrap()
+    );
+
+    for (config, expected, what) in &[
+        (as_base, "", "the source resolved"),
+        (in_patch, "Hello!", "the source of patch #0 resolved"),
+    ] {
+        assert_eq!(&do_patch(try_parse(config)?).await?, expected.as_bytes());
+
+        let error = do_patch_with_options(try_parse(config)?, &no_empty_sources)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with(what), "{}", error);
+        assert!(error.to_string().contains("'file'"), "{}", error);
+    }
+
+    // a file without a source starts from no bytes on purpose
+    let left_out =
+        "[[patch]]\ndo = \"insert\"\nway = \"pre\"\nspot = 0\nsource = { text = \"Hello!\" }\n";
+    assert_eq!(
+        &do_patch_with_options(try_parse(left_out)?, &no_empty_sources).await?,
+        &"Hello!".as_bytes()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reverse_reverses_the_bytes() -> Result<(), Box<dyn std::error::Error>> {
+    let file = try_parse("[source]\nreverse = { text = \"abc\" }\n")?;
+    assert_eq!(&file.resolve().await?.source, &"cba".as_bytes());
+
+    // it reverses whatever part of some other source it's given, like a range of the base
+    let file = try_parse(
+        r#"
+[source]
+text = "abcdef"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 6
+source = { reverse = { base = { start = 0, end = 3 } } }
+"#,
+    )?;
+    assert_eq!(&do_patch(file).await?, &"abcdefcba".as_bytes());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn lines_selects_lines_of_a_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("lines.txt");
+    std::fs::write(&path, "one\ntwo\nthree\nfour\nfive\nsix\nseven")?;
+    let path = path.to_str().unwrap().replace('\\', "/");
+
+    let lines = |select: &str| {
+        try_parse(&format!(
+            "[source]\nlines = {{ of = {{ file = \"{}\" }}, select = \"{}\" }}\n",
+            path, select
+        ))
+    };
+
+    // lines keep their line endings, and the last one doesn't have one
+    let file = lines("2-4,7")?;
+    assert_eq!(
+        &file.resolve().await?.source,
+        &"two\nthree\nfour\nseven".as_bytes()
+    );
+
+    let file = lines(" 7 , 1 ")?;
+    assert_eq!(&file.resolve().await?.source, &"sevenone\n".as_bytes());
+
+    for out_of_range in &["6-8", "8"] {
+        let error = lines(out_of_range)?.resolve().await.unwrap_err();
+        assert!(error.to_string().contains("line 8"), "{}", error);
+    }
+
+    for invalid in &["0", "4-2", "two", "1,", "-3"] {
+        assert!(lines(invalid)?.resolve().await.is_err(), "{}", invalid);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn deduped_sources_resolve_once() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("comma.txt");
+    std::fs::write(&path, ",")?;
+    let path = path.to_str().unwrap().replace('\\', "/");
+
+    let config = || {
+        try_parse(&format!(
+            r#"
+[source]
+text = "a b c d"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 1
+source = {{ file = "{0}" }}
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 3
+source = {{ file = "{0}" }}
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = {{ file = "{0}" }}
+"#,
+            path
+        ))
+    };
+
+    // every source that gets resolved is in the manifest, which is how the reads of the file are counted
+    for (dedupe_sources, reads) in &[(false, 3), (true, 1)] {
+        let options = PatchOptions {
+            dedupe_sources: *dedupe_sources,
+            manifest: Some(Manifest::default()),
+            ..PatchOptions::default()
+        };
+
+        assert_eq!(
+            do_patch_with_options(config()?, &options).await?,
+            b"a, b, c, d"
+        );
+
+        let manifest = options.manifest.unwrap().entries();
+        let files = manifest.iter().filter(|entry| entry.kind == "file");
+        assert_eq!(files.count(), *reads);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn urlencode_round_trips() -> Result<(), Box<dyn std::error::Error>> {
+    let file = try_parse("[source]\nurlencode = { text = \"a b&c/~ü\" }\n")?;
+    assert_eq!(
+        &file.resolve().await?.source,
+        &"a%20b%26c%2F~%C3%BC".as_bytes()
+    );
+
+    let file = try_parse(
+        r#"
+[source]
+urldecode = { urlencode = { text = "a b&c/~ü" } }
+"#,
+    )?;
+    assert_eq!(&file.resolve().await?.source, &"a b&c/~ü".as_bytes());
+
+    // case doesn't matter in the hex digits
+    let file = try_parse("[source]\nurldecode = { text = \"%c3%BC\" }\n")?;
+    assert_eq!(&file.resolve().await?.source, &"ü".as_bytes());
+
+    for invalid in &["100%", "%4", "%zz"] {
+        let file = try_parse(&format!(
+            "[source]\nurldecode = {{ text = \"{}\" }}\n",
+            invalid
+        ))?;
+        let error = file.resolve().await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn xor_round_trips() -> Result<(), Box<dyn std::error::Error>> {
+    let file = try_parse(
+        r#"
+[source]
+xor = { of = { text = "Hello, World!" }, key = [255, 1] }
+"#,
+    )?;
+
+    let xored = file.resolve().await?.source;
+    assert_eq!(&xored[..2], &[b'H' ^ 255, b'e' ^ 1]);
+
+    let file = try_parse(
+        r#"
+[source]
+xor = { of = { xor = { of = { text = "Hello, World!" }, key = [255, 1] } }, key = [255, 1] }
+"#,
+    )?;
+
+    let unxored = file.resolve().await?.source;
+    assert_eq!(&unxored, &"Hello, World!".as_bytes());
+
+    let empty_key = AssuoSource::Xor {
+        of: Box::new(AssuoSource::Text(String::from("Hello, World!"))),
+        key: vec![],
+    };
+
+    let error = Resolvable::<Vec<u8>>::resolve(empty_key).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+#[tokio::test]
+async fn pad_pads_to_a_multiple() -> Result<(), Box<dyn std::error::Error>> {
+    let file = try_parse(
+        r#"
+[source]
+pad = { of = { bytes = [1, 2, 3] }, to = 8, with = 0 }
+"#,
+    )?;
+
+    assert_eq!(&file.resolve().await?.source, &[1, 2, 3, 0, 0, 0, 0, 0]);
+
+    // it composes with the other sources, and what's already a multiple isn't padded
+    let file = try_parse(
+        r#"
+[source]
+pad = { of = [{ len_of = { text = "Hi" }, as = "u16le" }, { text = "Hi" }], to = 4, with = 255 }
+"#,
+    )?;
+
+    assert_eq!(&file.resolve().await?.source, &[2, 0, b'H', b'i']);
+
+    let to_zero = AssuoSource::Pad {
+        of: Box::new(AssuoSource::Bytes(vec![1, 2, 3])),
+        to: 0,
+        with: 0,
+    };
+
+    let error = Resolvable::<Vec<u8>>::resolve(to_zero).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    // and a config can't ask for it in the first place
+    let error = try_parse("[source]\npad = { of = { bytes = [1, 2, 3] }, to = 0 }\n").unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("expected positive integer 'to' for pad"),
+        "{}",
+        error
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn first_of_uses_the_first_source_that_resolves() -> Result<(), Box<dyn std::error::Error>> {
+    let file = try_parse(
+        r#"
+[source]
+first_of = [{ file = "./this/file/does/not/exist" }, { text = "fallback" }, { text = "unused" }]
+"#,
+    )?;
+
+    assert_eq!(&file.resolve().await?.source, &"fallback".as_bytes());
+
+    let file = try_parse(
+        r#"
+[source]
+first_of = [{ file = "./this/file/does/not/exist" }, { xor = { of = { text = "" }, key = [] } }]
+"#,
+    )?;
+
+    let error = file.resolve().await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+#[tokio::test]
+async fn if_resolves_the_branch_its_condition_picks() -> Result<(), Box<dyn std::error::Error>> {
+    // only this test touches this variable, so toggling it doesn't race with other tests
+    let file = || {
+        try_parse(
+            r#"
+[source]
+if = { cond = { env = "ASSUO_TEST_IF_PROD" }, then = { text = "prod" }, else = { text = "stub" } }
+"#,
+        )
+    };
+
+    std::env::remove_var("ASSUO_TEST_IF_PROD");
+    assert_eq!(&file()?.resolve().await?.source, &"stub".as_bytes());
+
+    std::env::set_var("ASSUO_TEST_IF_PROD", "1");
+    assert_eq!(&file()?.resolve().await?.source, &"prod".as_bytes());
+    std::env::remove_var("ASSUO_TEST_IF_PROD");
+
+    // the branch that isn't picked isn't resolved, so it can't fail anything
+    let file = try_parse(
+        r#"
+[source]
+if = { cond = { os = "not-an-os" }, then = { file = "./this/file/does/not/exist" }, else = { text = "stub" } }
+"#,
+    )?;
+    assert_eq!(&file.resolve().await?.source, &"stub".as_bytes());
+
+    assert!(try_parse(
+        "[source]\nif = { cond = { env = \"PROD\" }, then = { text = \"prod\" } }\n"
+    )
+    .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn file_ranges_only_read_that_range() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("big.bin");
+
+    // not valid UTF-8, which reading the whole file would trip over
+    let contents = (0..=255).cycle().take(4096).collect::<Vec<u8>>();
+    std::fs::write(&path, &contents)?;
+
+    let ranged = |range: &str| {
+        try_parse(&format!(
+            "[source]\nfile = {:?}\n{}\n",
+            path.to_str().unwrap(),
+            range
+        ))
+    };
+
+    let file = ranged("offset = 1000\nlength = 256\nexpect_size = 4096")?;
+    assert_eq!(file.resolve().await?.source, &contents[1000..1256]);
+
+    let error = ranged("offset = 4000\nlength = 97")?
+        .resolve()
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    assert!(ranged("offset = 1000").is_err());
+    Ok(())
+}
+
+async fn resolve_url(url: &str) -> std::io::Result<Vec<u8>> {
+    Resolvable::<Vec<u8>>::resolve(AssuoSource::Url {
+        url: String::from(url),
+        strip_bom: false,
+        policy: FetchPolicy::default(),
+    })
+    .await
+}
+
+#[tokio::test]
+async fn data_uris_are_decoded_without_fetching() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!(
+        resolve_url("data:text/plain;base64,SGVsbG8sIFdvcmxkIQ==").await?,
+        b"Hello, World!"
+    );
+    assert_eq!(
+        resolve_url("data:,Hello%2C%20World!").await?,
+        b"Hello, World!"
+    );
+    assert_eq!(
+        resolve_url("data:text/plain;charset=utf-8,%FF%00").await?,
+        &[0xFF, 0x00]
+    );
+
+    for invalid in &["data:text/plain", "data:;base64,not*base64", "data:text,hi"] {
+        let error = resolve_url(invalid).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("invalid data uri"));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn file_size_is_checked_when_expected() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("input.txt");
+    std::fs::write(&path, "Hello, World!")?;
+
+    let file_source = |expect_size: usize| {
+        try_parse(&format!(
+            r#"
+[source]
+file = {:?}
+expect_size = {}
+"#,
+            path.to_str().unwrap(),
+            expect_size
+        ))
+    };
+
+    let resolved = file_source(13)?.resolve().await?;
+    assert_eq!(&resolved.source, &"Hello, World!".as_bytes());
+
+    let error = file_source(12)?.resolve().await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("expected"));
+    assert!(error
+        .to_string()
+        .ends_with("to be 12 bytes, but it's 13 bytes"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn indirect_resolves_the_source_in_another_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let greeting = dir.path().join("greeting.toml");
+    let cycle = dir.path().join("cycle.toml");
+
+    std::fs::write(
+        &greeting,
+        r#"
+[source]
+template = { text = "Hello, ${NAME}!", vars = { NAME = "World" } }
+"#,
+    )?;
+    std::fs::write(
+        &cycle,
+        format!(
+            "[source]\nxor = {{ of = {{ indirect = {:?} }}, key = [1] }}\n",
+            cycle.to_str().unwrap()
+        ),
+    )?;
+
+    let indirect = |path: &std::path::Path| {
+        Resolvable::<Vec<u8>>::resolve(AssuoSource::Indirect(String::from(path.to_str().unwrap())))
+    };
+
+    assert_eq!(&indirect(&greeting).await?, &"Hello, World!".as_bytes());
+
+    let error = indirect(&cycle).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(error.to_string().ends_with("leads back to itself"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn bom_is_only_stripped_when_asked() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("input.txt");
+    std::fs::write(&path, "\u{FEFF}Hello!")?;
+
+    for source in &[
+        format!("file = {:?}", path.to_str().unwrap()),
+        String::from("url = \"data:;base64,77u/SGVsbG8h\""),
+    ] {
+        let with_bom = try_parse(&format!("[source]\n{}\n", source))?;
+        assert_eq!(&with_bom.resolve().await?.source, b"\xEF\xBB\xBFHello!");
+
+        let stripped = try_parse(&format!("[source]\n{}\nstrip_bom = true\n", source))?;
+        assert_eq!(&stripped.resolve().await?.source, b"Hello!");
+
+        // the manifest only has the source as it was written, with what it resolved to once stripped
+        let options = PatchOptions {
+            manifest: Some(Manifest::default()),
+            ..PatchOptions::default()
+        };
+        let stripped = try_parse(&format!("[source]\n{}\nstrip_bom = true\n", source))?;
+        do_patch_with_options(stripped, &options).await?;
+
+        let manifest = options.manifest.unwrap().entries();
+        assert_eq!(manifest.len(), 1);
+        assert_eq!(manifest[0].spec["strip_bom"], true);
+        assert_eq!(manifest[0].length, "Hello!".len());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "ssh"))]
+#[tokio::test]
+async fn scp_needs_the_ssh_feature() -> Result<(), Box<dyn std::error::Error>> {
+    let file = try_parse(
+        r#"
+[source]
+scp = "user@host:/path/to/file"
+"#,
+    )?;
+
+    let error = file.resolve().await.unwrap_err();
+    assert!(error.to_string().contains("`ssh` feature"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn bytes_repeat_nested_groups() -> Result<(), Box<dyn std::error::Error>> {
+    let file = try_parse(
+        r#"
+[source]
+bytes = [[2, [1, [2, [7]], "a"]], [0, [9]], 255]
+"#,
+    )?;
+    assert_eq!(
+        &file.resolve().await?.source,
+        &[1, 7, 7, 97, 1, 7, 7, 97, 255]
+    );
+
+    // counts too big to fit in memory are errors, rather than aborting
+    for bytes in &[
+        "[[-1, [0]]]",
+        "[[2, [256]]]",
+        "[[2, 0]]",
+        "[[2, [0], 1]]",
+        "[[9223372036854775807, [0, 0]]]",
+        "[[1073741825, [0]]]",
+    ] {
+        assert!(
+            try_parse(&format!("[source]\nbytes = {}\n", bytes)).is_err(),
+            "{}",
+            bytes
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+//! Houses all tests that need an SSH server. These only run with the `ssh` feature, and when `ASSUO_SCP_FIXTURE` is
+//! set to a file like `user@host:/path/to/file` that the SSH agent (or a key in `~/.ssh`) can log in to fetch, with
+//! `ASSUO_SCP_CONTENTS` set to what's in it.
+#![cfg(feature = "ssh")]
+
+use assuo::models::{try_parse, Resolvable};
+
+#[tokio::test]
+#[ignore = "needs an ssh server, see the top of this file"]
+async fn when_source_is_scp_it_resolves_it_by_fetching_it() -> Result<(), Box<dyn std::error::Error>>
+{
+    let target = std::env::var("ASSUO_SCP_FIXTURE")?;
+    let contents = std::env::var("ASSUO_SCP_CONTENTS")?;
+
+    let file = try_parse(&format!("[source]\nscp = {:?}\n", target))?;
+
+    let resolved = file.resolve().await?;
+    assert_eq!(resolved.source, contents.into_bytes());
+    Ok(())
+}
+
+#[tokio::test]
+async fn scp_without_a_path_is_an_error() -> Result<(), Box<dyn std::error::Error>> {
+    for target in &["user@host", "user@host:", "user@host:2222:"] {
+        let file = try_parse(&format!("[source]\nscp = {:?}\n", target))?;
+
+        let error = file.resolve().await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput, "{}", target);
+    }
+
+    Ok(())
+}
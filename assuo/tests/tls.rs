@@ -0,0 +1,16 @@
+//! Houses all tests that need the internet, to fetch an https url with whichever TLS library assuo is built with.
+//! These only run with the `rustls` or `native-tls` feature.
+#![cfg(any(feature = "rustls", feature = "native-tls"))]
+
+use assuo::models::{try_parse, Resolvable};
+
+#[tokio::test]
+#[ignore = "needs the internet"]
+async fn https_urls_are_fetched() -> Result<(), Box<dyn std::error::Error>> {
+    let file = try_parse("[source]\nurl = \"https://example.com/\"\n")?;
+
+    let resolved = file.resolve().await?;
+    let resolved = String::from_utf8(resolved.source)?;
+    assert!(resolved.contains("Example Domain"), "{}", resolved);
+    Ok(())
+}
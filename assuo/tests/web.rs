@@ -1,8 +1,16 @@
 //! Houses all tests that need a web server for whatever reason
 
-use assuo::models::try_parse;
+use std::time::{Duration, Instant};
+
 use assuo::models::Resolvable;
-use httptest::{matchers::request, responders::status_code, Expectation, Server};
+use assuo::models::{try_parse, FetchPolicy};
+use assuo::patch::{do_patch_until, do_patch_with_options, PatchOptions, Warning};
+use httptest::{
+    all_of,
+    matchers::{contains, request},
+    responders::{delay_and_then, status_code},
+    Expectation, Server,
+};
 
 #[tokio::test]
 async fn when_source_is_url_it_resolves_it_by_downloading_it(
@@ -63,3 +71,616 @@ assuo-url = "{}"
 
     Ok(())
 }
+
+#[tokio::test]
+async fn when_source_is_post_it_resolves_it_by_posting_the_body(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::run();
+
+    server.expect(
+        Expectation::matching(all_of![
+            request::method_path("POST", "/"),
+            request::headers(contains(("x-token", "secret"))),
+            request::body("query"),
+        ])
+        .respond_with(status_code(200).body("Hello, World!")),
+    );
+
+    let url = server.url("/");
+
+    let assuo_config = try_parse(&format!(
+        r#"
+[source]
+post = {{ url = "{}", body = {{ text = "query" }}, headers = {{ x-token = "secret" }} }}
+"#,
+        url
+    ))
+    .unwrap();
+
+    let resolved = assuo_config.resolve().await?;
+    assert_eq!(resolved.source.as_slice(), "Hello, World!".as_bytes());
+
+    Ok(())
+}
+
+thread_local! {
+    static TRACES: std::cell::RefCell<Vec<String>> = Default::default();
+}
+
+#[tokio::test]
+async fn trace_http_sees_every_request_without_credentials(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::run();
+
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .respond_with(status_code(200).body("Hello")),
+    );
+    server.expect(
+        Expectation::matching(all_of![
+            request::method_path("POST", "/"),
+            request::headers(contains(("authorization", "Bearer secret"))),
+        ])
+        .respond_with(status_code(404)),
+    );
+
+    let url = server.url("/");
+
+    let assuo_config = try_parse(&format!(
+        r#"
+[source]
+url = "{0}"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = {{ first_of = [{{ post = {{ url = "{0}", headers = {{ Authorization = "Bearer secret" }} }} }}, {{ text = "!" }}] }}
+"#,
+        url
+    ))
+    .unwrap();
+
+    let options = PatchOptions {
+        trace_http: Some(|trace| TRACES.with(|traces| traces.borrow_mut().push(trace.to_string()))),
+        ..PatchOptions::default()
+    };
+
+    assert_eq!(
+        do_patch_with_options(assuo_config, &options).await?,
+        b"Hello!"
+    );
+
+    let traces = TRACES.with(|traces| traces.borrow().clone());
+    assert_eq!(
+        traces,
+        vec![
+            format!("GET {} -> 200, 5 bytes", url),
+            format!("POST {} [Authorization: <redacted>] -> 404", url),
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn when_post_responds_with_an_error_status_it_errors(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::run();
+
+    server.expect(
+        Expectation::matching(request::method_path("POST", "/"))
+            .respond_with(status_code(500).body("Hello, World!")),
+    );
+
+    let url = server.url("/");
+
+    let assuo_config = try_parse(&format!(
+        r#"
+[source]
+post = {{ url = "{}" }}
+"#,
+        url
+    ))
+    .unwrap();
+
+    let error = assuo_config.resolve().await.unwrap_err();
+    assert!(error.to_string().contains("500"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn first_of_falls_back_when_a_url_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::run();
+
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .respond_with(status_code(500).body("Internal Server Error")),
+    );
+
+    let url = server.url("/");
+
+    let assuo_config = try_parse(&format!(
+        r#"
+[source]
+first_of = [{{ url = "{}" }}, {{ text = "Hello, World!" }}]
+"#,
+        url
+    ))
+    .unwrap();
+
+    let resolved = assuo_config.resolve().await?;
+    assert_eq!(resolved.source.as_slice(), "Hello, World!".as_bytes());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn continue_on_resolve_error_inserts_nothing_for_broken_sources(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::run();
+
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/works"))
+            .times(2)
+            .respond_with(status_code(200).body(", World")),
+    );
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/broken"))
+            .times(2)
+            .respond_with(status_code(500).body("Internal Server Error")),
+    );
+
+    let config = || {
+        try_parse(&format!(
+            r#"
+[source]
+text = "Hello!"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = {{ url = "{}" }}
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = {{ url = "{}" }}
+"#,
+            server.url("/works"),
+            server.url("/broken")
+        ))
+    };
+
+    let error = do_patch_with_options(config()?, &PatchOptions::default())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("500"));
+
+    let options = PatchOptions {
+        continue_on_resolve_error: true,
+        ..PatchOptions::default()
+    };
+    assert_eq!(
+        do_patch_with_options(config()?, &options).await?,
+        b"Hello, World!"
+    );
+
+    // inserting nothing doesn't change anything, which gets its own warning
+    let warnings = options.warnings.take();
+    match warnings.as_slice() {
+        [Warning::SkippedSource { patch: 1, error }, Warning::NoOpPatch { patch: 1 }] => {
+            assert!(error.contains("500"))
+        }
+        _ => panic!("unexpected warnings: {:?}", warnings),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn strip_bom_strips_a_bom_from_a_fetched_body() -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::run();
+
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .times(2)
+            .respond_with(status_code(200).body("\u{FEFF}Hello, World!")),
+    );
+
+    let url = server.url("/");
+
+    let with_bom = try_parse(&format!("[source]\nurl = \"{}\"\n", url))?;
+    let resolved = with_bom.resolve().await?;
+    assert_eq!(
+        resolved.source.as_slice(),
+        "\u{FEFF}Hello, World!".as_bytes()
+    );
+
+    let stripped = try_parse(&format!("[source]\nurl = \"{}\"\nstrip_bom = true\n", url))?;
+    let resolved = stripped.resolve().await?;
+    assert_eq!(resolved.source.as_slice(), "Hello, World!".as_bytes());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn source_timeout_covers_reading_a_slow_body() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{Read, Write};
+
+    // httptest sends a body all at once, so this server trickles one out by hand, a byte every 50ms
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+
+    std::thread::spawn(move || {
+        if let Ok((mut stream, _)) = listener.accept() {
+            let _ = stream.read(&mut [0; 1024]);
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n");
+            for _ in 0..100 {
+                if stream.write_all(b".").is_err() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+    });
+
+    let assuo_config = try_parse(&format!("[source]\nurl = \"{}\"\n", url))?;
+
+    let options = PatchOptions {
+        source_timeout: Some(Duration::from_millis(500)),
+        ..PatchOptions::default()
+    };
+
+    let start = Instant::now();
+    let error = do_patch_with_options(assuo_config, &options)
+        .await
+        .unwrap_err();
+
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    assert!(error.to_string().contains("the source"));
+    assert!(start.elapsed() < Duration::from_secs(3));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cancelling_stops_a_slow_fetch_promptly() -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::run();
+
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .times(0..)
+            .respond_with(delay_and_then(
+                Duration::from_secs(30),
+                status_code(200).body("Hello, World!"),
+            )),
+    );
+
+    let url = server.url("/");
+
+    let assuo_config = try_parse(&format!(
+        r#"
+[source]
+url = "{}"
+"#,
+        url
+    ))
+    .unwrap();
+
+    let start = Instant::now();
+    let cancel = tokio::time::delay_for(Duration::from_millis(100));
+
+    let error = do_patch_until(assuo_config, &PatchOptions::default(), cancel)
+        .await
+        .unwrap_err();
+
+    assert_eq!(error.kind(), std::io::ErrorKind::Interrupted);
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    Ok(())
+}
+
+/// Serves every request that comes in on its own thread, answering each with `respond(index)` after `delay`, where
+/// `index` counts the requests so far. Returns the url of the server.
+fn serve_by_hand(
+    delay: Duration,
+    respond: fn(usize) -> &'static str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+
+    std::thread::spawn(move || {
+        for (index, stream) in listener.incoming().enumerate() {
+            if let Ok(mut stream) = stream {
+                std::thread::spawn(move || {
+                    let _ = stream.read(&mut [0; 1024]);
+                    std::thread::sleep(delay);
+                    let _ = stream.write_all(respond(index).as_bytes());
+                });
+            }
+        }
+    });
+
+    Ok(url)
+}
+
+#[tokio::test]
+async fn deadlines_hold_in_nested_assuo_files() -> Result<(), Box<dyn std::error::Error>> {
+    let slow = serve_by_hand(Duration::from_secs(5), |_| {
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nHello!"
+    })?;
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/nested.toml"))
+            .respond_with(status_code(200).body(format!("[source]\nurl = \"{}\"\n", slow))),
+    );
+
+    let config = try_parse(&format!(
+        "[source]\nassuo-url = \"{}\"\n",
+        server.url("/nested.toml")
+    ))?;
+
+    let start = Instant::now();
+    let options = PatchOptions {
+        deadline: Some(start + Duration::from_millis(500)),
+        ..PatchOptions::default()
+    };
+
+    let error = do_patch_with_options(config, &options).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    assert!(start.elapsed() < Duration::from_secs(3));
+
+    // once the deadline has passed, nothing else gets fetched at all
+    let config = try_parse(&format!("[source]\nurl = \"{}\"\n", slow))?;
+    let options = PatchOptions {
+        deadline: Some(Instant::now()),
+        ..PatchOptions::default()
+    };
+
+    let error = do_patch_with_options(config, &options).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    assert!(error.to_string().contains("deadline"), "{}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fetch_timeouts_default_to_the_options() -> Result<(), Box<dyn std::error::Error>> {
+    let url = serve_by_hand(Duration::from_millis(300), |_| {
+        "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nHello!"
+    })?;
+
+    let options = PatchOptions {
+        fetch_defaults: FetchPolicy {
+            timeout: Some(Duration::from_millis(100)),
+            retry: None,
+            redirects: None,
+        },
+        ..PatchOptions::default()
+    };
+
+    // without a timeout of its own, the url gets the default one
+    let config = try_parse(&format!("[source]\nurl = \"{}\"\n", url))?;
+    let error = do_patch_with_options(config, &options).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+
+    // and one of its own wins over it
+    let config = try_parse(&format!("[source]\nurl = \"{}\"\ntimeout = \"5s\"\n", url))?;
+    assert_eq!(do_patch_with_options(config, &options).await?, b"Hello!");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fetches_are_retried_as_many_times_as_asked() -> Result<(), Box<dyn std::error::Error>> {
+    // every url fails the first time, and works after that
+    let flaky = || {
+        serve_by_hand(Duration::from_millis(0), |index| match index {
+            0 => "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n",
+            _ => "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nHello!",
+        })
+    };
+
+    let options = PatchOptions {
+        fetch_defaults: FetchPolicy {
+            timeout: None,
+            retry: Some(1),
+            redirects: None,
+        },
+        ..PatchOptions::default()
+    };
+
+    let config = try_parse(&format!("[source]\nurl = \"{}\"\n", flaky()?))?;
+    assert!(do_patch_with_options(config, &PatchOptions::default())
+        .await
+        .is_err());
+
+    let config = try_parse(&format!("[source]\nurl = \"{}\"\n", flaky()?))?;
+    assert_eq!(do_patch_with_options(config, &options).await?, b"Hello!");
+
+    let config = try_parse(&format!(
+        "[source]\npost = {{ url = \"{}\", retry = 0 }}\n",
+        flaky()?
+    ))?;
+    assert!(do_patch_with_options(config, &options).await.is_err());
+
+    // a status that isn't the server's fault would be the same the next time, so it isn't retried
+    let not_found = serve_by_hand(Duration::from_millis(0), |index| match index {
+        0 => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+        _ => "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nHello!",
+    })?;
+
+    let config = try_parse(&format!("[source]\nurl = \"{}\"\n", not_found))?;
+    let error = do_patch_with_options(config, &options).await.unwrap_err();
+    assert!(error.to_string().contains("404"), "{}", error);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn redirects_are_followed_as_the_source_says() -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/moved"))
+            .times(4)
+            .respond_with(status_code(302).insert_header("Location", "/here")),
+    );
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/here"))
+            .times(2)
+            .respond_with(status_code(200).body("Hello!")),
+    );
+
+    let url = server.url("/moved");
+    let config = |redirects: &str| {
+        try_parse(&format!(
+            "[source]\nurl = \"{}\"\nredirects = {}\n",
+            url, redirects
+        ))
+    };
+
+    // a pinned url can't lead somewhere else
+    let error = do_patch_with_options(config("\"none\"")?, &PatchOptions::default())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("302"), "{}", error);
+
+    assert_eq!(
+        do_patch_with_options(config("{ limit = 1 }")?, &PatchOptions::default()).await?,
+        b"Hello!"
+    );
+    assert_eq!(
+        do_patch_with_options(config("\"same-host\"")?, &PatchOptions::default()).await?,
+        b"Hello!"
+    );
+
+    // one redirect is more than none are allowed
+    assert!(
+        do_patch_with_options(config("{ limit = 0 }")?, &PatchOptions::default())
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sources_that_arent_used_are_never_fetched() -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/used"))
+            .times(3)
+            .respond_with(status_code(200).body("Hello!")),
+    );
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/unused"))
+            .times(0)
+            .respond_with(status_code(500)),
+    );
+
+    let used = server.url("/used");
+    let unused = server.url("/unused");
+
+    let assuo_config = try_parse(&format!(
+        r#"
+[source]
+if = {{ cond = {{ env = "ASSUO_TEST_NEVER_SET" }}, then = {{ url = "{1}" }}, else = {{ url = "{0}" }} }}
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 6
+source = {{ first_of = [{{ url = "{0}" }}, {{ url = "{1}" }}] }}
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 6
+source = {{ url = "{1}" }}
+cfg = {{ os = "not-an-os" }}
+
+[[patch]]
+do = "insert"
+way = "pre"
+spot = 0
+source = {{ if = {{ cond = {{ os = "{2}" }}, then = {{ url = "{0}" }}, else = {{ url = "{1}" }} }} }}
+"#,
+        used,
+        unused,
+        std::env::consts::OS
+    ))?;
+
+    assert_eq!(
+        do_patch_with_options(assuo_config, &PatchOptions::default()).await?,
+        b"Hello!Hello!Hello!"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn relative_urls_resolve_against_where_their_config_came_from(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/configs/greeting.toml")).respond_with(
+            status_code(200).body(
+                r#"
+[source]
+url = "hello.txt"
+
+[[patch]]
+do = "insert"
+way = "post"
+spot = 5
+source = { url = "/shared/world.txt" }
+"#,
+            ),
+        ),
+    );
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/configs/hello.txt"))
+            .times(2)
+            .respond_with(status_code(200).body("Hello!")),
+    );
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/shared/world.txt"))
+            .respond_with(status_code(200).body(", World")),
+    );
+
+    let assuo_config = try_parse(&format!(
+        "[source]\nassuo-url = \"{}\"\n",
+        server.url("/configs/greeting.toml")
+    ))?;
+
+    assert_eq!(
+        do_patch_with_options(assuo_config, &PatchOptions::default()).await?,
+        b"Hello, World!"
+    );
+
+    // the config being patched has no url of its own, so its relative urls need to be given a base
+    let assuo_config = try_parse("[source]\nurl = \"hello.txt\"\n")?;
+    assert!(
+        do_patch_with_options(assuo_config.clone(), &PatchOptions::default())
+            .await
+            .is_err()
+    );
+
+    let options = PatchOptions {
+        base_url: Some(server.url("/configs/").to_string()),
+        ..PatchOptions::default()
+    };
+    assert_eq!(
+        do_patch_with_options(assuo_config, &options).await?,
+        b"Hello!"
+    );
+
+    Ok(())
+}